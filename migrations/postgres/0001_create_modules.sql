@@ -0,0 +1,17 @@
+-- Mirrors the table created by scripts/setup_database.sh so existing
+-- databases are adopted rather than recreated.
+CREATE TABLE IF NOT EXISTS modules (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) UNIQUE NOT NULL,
+    version VARCHAR(50),
+    entry_point VARCHAR(255),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_modules_name ON modules(name);
+ALTER TABLE modules ADD COLUMN IF NOT EXISTS module_type VARCHAR(50);
+ALTER TABLE modules ADD COLUMN IF NOT EXISTS install_path VARCHAR(255);
+ALTER TABLE modules ADD COLUMN IF NOT EXISTS installed_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE modules ADD COLUMN IF NOT EXISTS last_used_at TIMESTAMP WITH TIME ZONE;
+CREATE INDEX IF NOT EXISTS idx_modules_module_type ON modules(module_type);
@@ -0,0 +1,2 @@
+ALTER TABLE modules ADD COLUMN IF NOT EXISTS python_version VARCHAR(50);
+ALTER TABLE modules ADD COLUMN IF NOT EXISTS python_path VARCHAR(255);
@@ -0,0 +1,2 @@
+ALTER TABLE modules ADD COLUMN python_version TEXT;
+ALTER TABLE modules ADD COLUMN python_path TEXT;
@@ -0,0 +1,146 @@
+//! Answers module for the Module Validator application.
+//!
+//! Installs and launches ask for values such as API ports, environment variables and
+//! script paths. Answers supply those values up front: first from an answers file,
+//! then from `--set KEY=VALUE` flags. Anything still missing is prompted for, unless
+//! prompting is disabled with `--non-interactive`, in which case callers report every
+//! missing value at once instead of waiting on stdin.
+
+use crate::error::{Error, Result};
+use crate::redact;
+use dialoguer::Input;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Values given ahead of time for prompts.
+#[derive(Debug, Clone)]
+pub struct Answers {
+    values: BTreeMap<String, String>,
+    interactive: bool,
+}
+
+impl Default for Answers {
+    fn default() -> Self {
+        Answers {
+            values: BTreeMap::new(),
+            interactive: true,
+        }
+    }
+}
+
+impl Answers {
+    /// Collects answers from an optional answers file and `KEY=VALUE` pairs, the latter taking precedence.
+    ///
+    /// # Arguments
+    ///
+    /// * `answers_file` - A YAML file mapping keys to values.
+    /// * `sets` - `KEY=VALUE` pairs from `--set`.
+    /// * `interactive` - Whether missing values may be prompted for.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the Answers, or an error if the file or a pair is malformed.
+    pub fn load(answers_file: Option<&Path>, sets: &[String], interactive: bool) -> Result<Self> {
+        let mut values = match answers_file {
+            Some(path) => Self::read_file(path)?,
+            None => BTreeMap::new(),
+        };
+        for set in sets {
+            let (key, value) = set
+                .split_once('=')
+                .filter(|(key, _)| !key.trim().is_empty())
+                .ok_or_else(|| Error::InvalidInput(format!("--set expects KEY=VALUE, got `{}`", set)))?;
+            values.insert(key.trim().to_string(), value.to_string());
+        }
+        Ok(Answers { values, interactive })
+    }
+
+    /// Reads an answers file: a YAML mapping from keys to scalar values.
+    fn read_file(path: &Path) -> Result<BTreeMap<String, String>> {
+        let content = fs::read_to_string(path)?;
+        let raw: BTreeMap<String, serde_yaml::Value> = serde_yaml::from_str(&content)
+            .map_err(|e| Error::ConfigInvalid(format!("Invalid answers file {}: {}", path.display(), e)))?;
+        raw.into_iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    serde_yaml::Value::String(value) => value,
+                    serde_yaml::Value::Number(value) => value.to_string(),
+                    serde_yaml::Value::Bool(value) => value.to_string(),
+                    serde_yaml::Value::Null => String::new(),
+                    _ => {
+                        return Err(Error::ConfigInvalid(format!(
+                            "Invalid answers file {}: {} must be a string, number or boolean",
+                            path.display(),
+                            key
+                        )))
+                    }
+                };
+                Ok((key, value))
+            })
+            .collect()
+    }
+
+    /// Writes the answers to a file readable by the current user only, for [`Answers::load`]
+    /// in another process.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The answers file to create or replace.
+    pub fn write_file(&self, path: &Path) -> Result<()> {
+        let content = serde_yaml::to_string(&self.values)
+            .map_err(|e| Error::ConfigInvalid(format!("Cannot write answers file {}: {}", path.display(), e)))?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(path)?.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    /// Returns whether missing values may be prompted for.
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
+    /// Returns the answer given for a key, if any.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// Returns every answer, ordered by key.
+    pub fn values(&self) -> &BTreeMap<String, String> {
+        &self.values
+    }
+
+    /// Returns the answer for a key, prompting for it when none was given and prompting is allowed.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key looked up in the answers.
+    /// * `prompt` - The text shown when prompting.
+    /// * `default` - The value used when the prompt is left empty, or when prompting is disabled.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the value, or None if there is no answer, no default and prompting is disabled.
+    pub fn ask(&self, key: &str, prompt: &str, default: Option<&str>) -> Result<Option<String>> {
+        if let Some(value) = self.get(key) {
+            return Ok(Some(value.to_string()));
+        }
+        if !self.interactive {
+            return Ok(default.map(str::to_string));
+        }
+        let mut input = Input::<String>::new();
+        // dialoguer repeats the default after the prompt, which would reveal a secret
+        input.with_prompt(prompt).allow_empty(true).show_default(!redact::is_secret(key));
+        if let Some(default) = default {
+            input.default(default.to_string());
+        }
+        Ok(Some(input.interact_text()?))
+    }
+}
@@ -1,28 +1,159 @@
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use actix_web::http::StatusCode;
+use actix_web::{web, App, HttpResponse, HttpServer, ResponseError};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
-use regex::Regex;
-use crate::inference::python_executor::PythonExecutor;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use crate::config::Config;
+use crate::config_parser::ConfigParser;
+use crate::error::{Error, Result};
+use crate::inference::embedded::EmbeddedExecutor;
+use crate::inference::worker::{WorkerPool, WorkerPoolOptions};
+use crate::inference::process::{CancelHandle, ExecutionResult};
+use crate::inference::python_executor::{PythonExecutor, RunOptions};
+use crate::logs::{self, filter_lines, parse_since, LogSettings};
+use crate::manifest::ModuleManifest;
+use crate::redact;
+use crate::sandbox::SandboxPolicies;
+use crate::utils::check_name;
+use chrono::Utc;
 
 #[derive(Deserialize)]
 struct SubnetCommandRequest {
     subnet: String,
     command: String,
     args: HashMap<String, serde_json::Value>,
+    /// Seconds the command may run; defaults to the subnet manifest's `timeout`.
+    #[serde(default)]
+    timeout: Option<u64>,
+    /// Environment variables for this call, overriding the subnet's `.env` files. Only
+    /// variables declared in the subnet manifest's `env` table are accepted.
+    #[serde(default)]
+    env: BTreeMap<String, String>,
 }
 
+/// The settings from `config.yaml` that apply to every subnet command.
+struct CommandSettings {
+    sandbox: SandboxPolicies,
+    profile: Option<String>,
+    logs: LogSettings,
+}
+
+impl CommandSettings {
+    /// Builds the options of a subnet command request.
+    fn run_options(&self, info: &SubnetCommandRequest, cancel: Option<CancelHandle>) -> RunOptions {
+        RunOptions {
+            timeout: info.timeout.map(Duration::from_secs),
+            cancel,
+            sandbox: self.sandbox.for_module(&info.subnet).cloned(),
+            profile: self.profile.clone(),
+            env: info.env.clone(),
+            log: Some(self.logs.clone()),
+            supervised: false,
+        }
+    }
+}
+
+/// The response to a subnet command, successful or not.
 #[derive(Serialize)]
-struct ApiResponse {
+struct CommandResponse {
     message: String,
+    /// The error kind when the command failed, as in other error responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+    result: ExecutionResult,
+}
+
+#[derive(Serialize)]
+struct InferenceResponse {
+    result: serde_json::Value,
+}
+
+/// The query of `GET /logs/{module}/{role}`.
+#[derive(Deserialize)]
+struct LogsQuery {
+    /// Only lines written since then: a duration such as `10m`, or an RFC 3339 time.
+    #[serde(default)]
+    since: Option<String>,
+    /// At most this many of the last lines; defaults to [`DEFAULT_LOG_LINES`] without `since`.
+    #[serde(default)]
+    lines: Option<usize>,
+}
+
+/// The number of lines returned by `GET /logs/{module}/{role}` without `since` or `lines`.
+const DEFAULT_LOG_LINES: usize = 100;
+
+#[derive(Serialize)]
+struct LogListResponse {
+    module: String,
+    logs: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct LogsResponse {
+    module: String,
+    role: String,
+    lines: Vec<String>,
 }
 
-struct SubnetCommand {
-    name: String,
-    args: Vec<String>,
+/// Inference modules loaded by the API, kept between requests.
+struct InferenceModules {
+    /// The pool sizing when the `workers` runtime is configured; None runs modules embedded.
+    workers: Option<WorkerPoolOptions>,
+    embedded: Mutex<HashMap<String, Arc<EmbeddedExecutor>>>,
+    pools: Mutex<HashMap<String, Arc<WorkerPool>>>,
 }
 
+impl InferenceModules {
+    fn new(config: &Config) -> Self {
+        InferenceModules {
+            workers: (config.inference_runtime == "workers").then(|| WorkerPoolOptions::from_config(config)),
+            embedded: Mutex::new(HashMap::new()),
+            pools: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Calls a module's `process` function, loading the module on first use.
+    fn process(&self, name: &str, args: serde_json::Value) -> Result<serde_json::Value> {
+        match self.workers {
+            Some(options) => {
+                let pool = {
+                    let mut pools = self.pools.lock().unwrap_or_else(|e| e.into_inner());
+                    match pools.get(name) {
+                        Some(pool) => pool.clone(),
+                        None => {
+                            let pool = Arc::new(WorkerPool::start(name, options)?);
+                            pools.insert(name.to_string(), pool.clone());
+                            pool
+                        }
+                    }
+                };
+                pool.call(args)
+            }
+            None => {
+                let executor = {
+                    let mut executors = self.embedded.lock().unwrap_or_else(|e| e.into_inner());
+                    match executors.get(name) {
+                        Some(executor) => executor.clone(),
+                        None => {
+                            let executor = Arc::new(EmbeddedExecutor::start(name)?);
+                            executors.insert(name.to_string(), executor.clone());
+                            executor
+                        }
+                    }
+                };
+                executor.process(args)
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
 fn discover_subnets() -> Vec<String> {
     let subnets_dir = Path::new("subnets");
     fs::read_dir(subnets_dir)
@@ -36,80 +167,216 @@ fn discover_subnets() -> Vec<String> {
         .collect()
 }
 
-fn parse_subnet_commands(subnet: &str) -> Vec<SubnetCommand> {
+/// Resolves a subnet command request to the executor of its script and its arguments.
+///
+/// The request's `args` are mapped to `--name value` options of the command as parsed
+/// by [`ConfigParser`] and passed to Python as separate arguments, never through a shell.
+async fn prepare_subnet_command(
+    settings: &CommandSettings,
+    info: &SubnetCommandRequest,
+) -> Result<(PythonExecutor, Vec<String>)> {
+    let subnet = &info.subnet;
+    let command = &info.command;
+
+    // The name comes from the request body and becomes part of every path below
+    check_name("subnet", subnet)?;
     let subnet_dir = Path::new("subnets").join(subnet);
-    let command_regex = Regex::new(r#"@app\.command\(['"](\w+)['"])\s*def\s+(\w+)\((.*?)\):"#).unwrap();
-    let arg_regex = Regex::new(r"(\w+):\s*\w+(?:\s*=\s*[^,)]+)?").unwrap();
+    if !subnet_dir.is_dir() {
+        return Err(Error::ModuleNotFound(subnet.to_string()));
+    }
+    ModuleManifest::load(&subnet_dir)?.unwrap_or_default().check_call_env(&info.env)?;
+    let module_config = ConfigParser::parse_commands(&subnet_dir)?;
+    let target_script_path = format!("subnets/{}/src/communex/cli/{}.py", subnet, command);
+    let cmd = module_config.commands.get(command).ok_or_else(|| Error::ScriptNotFound {
+        role: format!("Command {}", command),
+        path: target_script_path.clone().into(),
+    })?;
+    let args = cmd.to_argv(&info.args)?;
 
-    fs::read_dir(subnet_dir)
-        .unwrap()
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.path().extension().map_or(false, |ext| ext == "py"))
-        .flat_map(|entry| {
-            let content = fs::read_to_string(entry.path()).unwrap();
-            command_regex.captures_iter(&content)
-                .map(|cap| {
-                    let name = cap[1].to_string();
-                    let args = arg_regex.captures_iter(&cap[3])
-                        .map(|arg_cap| arg_cap[1].to_string())
-                        .collect();
-                    SubnetCommand { name, args }
-                })
-                .collect::<Vec<_>>()
-        })
-        .collect()
+    // Creating the executor prepares the virtual environment, which blocks
+    let sandbox = settings.sandbox.for_module(subnet).cloned();
+    let subnet = subnet.to_string();
+    let python_executor = web::block(move || {
+        PythonExecutor::new(subnet, "subnet".to_string(), target_script_path, sandbox.as_ref())
+    })
+        .await
+        .map_err(|e| Error::Io(std::io::Error::other(e)))??;
+    Ok((python_executor, args))
 }
 
-async fn execute_subnet_command(info: web::Json<SubnetCommandRequest>) -> impl Responder {
-    let subnet = &info.subnet;
-    let command = &info.command;
-    
-    let commands = parse_subnet_commands(subnet);
-    if let Some(cmd) = commands.iter().find(|c| c.name == *command) {
-        let mut args = Vec::new();
-        for arg_name in &cmd.args {
-            if let Some(value) = info.args.get(arg_name) {
-                args.push(value.to_string());
-            } else {
-                return HttpResponse::BadRequest().json(ApiResponse {
-                    message: format!("Missing argument: {}", arg_name),
-                });
+impl CommandResponse {
+    /// Describes a finished run, with the HTTP status matching its outcome.
+    fn new(result: ExecutionResult) -> (StatusCode, Self) {
+        let result = result.redacted();
+        if result.success() {
+            return (
+                StatusCode::OK,
+                CommandResponse {
+                    message: format!("Command result: {}", result.stdout),
+                    error: None,
+                    result,
+                },
+            );
+        }
+        let error = result.clone().check().unwrap_err();
+        (
+            error.status_code(),
+            CommandResponse {
+                message: error.to_string(),
+                error: Some(error.kind()),
+                result,
+            },
+        )
+    }
+}
+
+/// Runs a typer command of an installed subnet.
+///
+/// Failures are returned as [`Error`], whose `ResponseError` impl picks the HTTP status:
+/// 404 for an unknown subnet or command, 400 for an unknown or missing argument, 504 on
+/// timeout and 500 when the Python process fails. Once the command has run, the response
+/// carries its [`ExecutionResult`] (output, exit code or signal, wall time and peak
+/// memory) whether or not it succeeded.
+///
+/// The subnet's sandbox policy from `config.yaml`, if any, confines the command. The
+/// request's `env` is applied over the subnet's `.env` files and the configured profile;
+/// it may only set variables the subnet's manifest declares, and never `PATH`,
+/// `VIRTUAL_ENV`, `LD_*` or `PYTHON*`, which are rejected with a 400.
+///
+/// If the client disconnects, actix drops this future, which stops the command's
+/// process group.
+async fn execute_subnet_command(
+    settings: web::Data<CommandSettings>,
+    info: web::Json<SubnetCommandRequest>,
+) -> Result<HttpResponse> {
+    let (python_executor, args) = prepare_subnet_command(&settings, &info).await?;
+    let options = settings.run_options(&info, None);
+    let result = python_executor.run_command_with(&args, &options).await?;
+    let (status, response) = CommandResponse::new(result);
+    Ok(HttpResponse::build(status).json(response))
+}
+
+/// Runs a typer command of an installed subnet, streaming its output as it is produced.
+///
+/// The response is newline-delimited JSON: one `{"stream": ..., "line": ...}` object per
+/// line of stdout or stderr, then the same [`CommandResponse`] as `/subnet_command`.
+/// Errors found before the command starts are returned as plain error responses. If the
+/// client disconnects, the command is cancelled and its process group stopped.
+async fn stream_subnet_command(
+    settings: web::Data<CommandSettings>,
+    info: web::Json<SubnetCommandRequest>,
+) -> Result<HttpResponse> {
+    let (python_executor, args) = prepare_subnet_command(&settings, &info).await?;
+    let cancel = CancelHandle::new();
+    let options = settings.run_options(&info, Some(cancel.clone()));
+    let mut running = python_executor.spawn(&args, &options)?;
+    let mut lines = running.output().expect("output is taken once");
+
+    let (sender, body) = mpsc::channel::<std::result::Result<web::Bytes, Error>>(64);
+    tokio::spawn(async move {
+        while let Some(line) = lines.next().await {
+            // The receiver is dropped when the client disconnects
+            if sender.send(Ok(json_line(&line.redacted()))).await.is_err() {
+                cancel.cancel();
             }
         }
+        let last = match running.wait().await {
+            Ok(result) => Ok(json_line(&CommandResponse::new(result).1)),
+            Err(e) => Err(Error::Io(e)),
+        };
+        let _ = sender.send(last).await;
+    });
 
-        let module_name = subnet.to_string();
-        let module_type = "subnet".to_string();
-        let target_script_path = format!("subnets/{}/src/communex/cli/{}.py", subnet, command);
-
-        match PythonExecutor::new(module_name, module_type, target_script_path) {
-            Ok(python_executor) => {
-                match python_executor.run_command(args.join(" ")) {
-                    Ok(result) => HttpResponse::Ok().json(ApiResponse {
-                        message: format!("Command result: {}", result),
-                    }),
-                    Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
-                        message: format!("Error executing command: {}", e),
-                    }),
-                }
-            },
-            Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
-                message: format!("Error creating Python executor: {}", e),
-            }),
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(ReceiverStream::new(body)))
+}
+
+/// Serializes a value as one line of newline-delimited JSON.
+fn json_line(value: &impl Serialize) -> web::Bytes {
+    let mut line = serde_json::to_vec(value).unwrap_or_default();
+    line.push(b'\n');
+    line.into()
+}
+
+/// Calls the `process` function of an inference module, in the embedded interpreter or
+/// a worker process depending on `inference_runtime`.
+///
+/// The request body is passed to `process` as JSON: an object as keyword arguments, an
+/// array as positional arguments and anything else as the single argument. The module
+/// is imported on its first request and stays loaded for the following ones.
+async fn run_inference(
+    modules: web::Data<InferenceModules>,
+    name: web::Path<String>,
+    args: web::Json<serde_json::Value>,
+) -> Result<HttpResponse> {
+    let name = name.into_inner();
+    check_name("module", &name)?;
+    let args = args.into_inner();
+    let result = web::block(move || modules.process(&name, args))
+        .await
+        .map_err(|e| Error::Io(std::io::Error::other(e)))??;
+    Ok(HttpResponse::Ok().json(InferenceResponse { result: redact_json(result) }))
+}
+
+/// Lists the logs of a module: the roles that wrote to `data/logs/<module>/<role>.log`.
+async fn list_module_logs(settings: web::Data<CommandSettings>, module: web::Path<String>) -> Result<HttpResponse> {
+    let module = module.into_inner();
+    let logs = settings.logs.roles(&module)?;
+    Ok(HttpResponse::Ok().json(LogListResponse { module, logs }))
+}
+
+/// Returns the last lines of a module's log, reading its rotated files too.
+///
+/// Lines are returned as written: `<RFC 3339 time> <stdout|stderr|supervisor> <text>`,
+/// with secrets already masked. A log that does not exist has no lines.
+async fn module_logs(
+    settings: web::Data<CommandSettings>,
+    path: web::Path<(String, String)>,
+    query: web::Query<LogsQuery>,
+) -> Result<HttpResponse> {
+    let (module, role) = path.into_inner();
+    let log_path = settings.logs.path(&module, &role)?;
+    let since = query.since.as_deref().map(|since| parse_since(since, Utc::now())).transpose()?;
+    let limit = query.lines.or(if since.is_none() { Some(DEFAULT_LOG_LINES) } else { None });
+    let lines = web::block(move || logs::read_all(&log_path))
+        .await
+        .map_err(|e| Error::Io(std::io::Error::other(e)))??;
+    let lines = filter_lines(lines, since, limit);
+    Ok(HttpResponse::Ok().json(LogsResponse { module, role, lines }))
+}
+
+/// Masks secrets in the strings of a JSON value.
+fn redact_json(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(text) => serde_json::Value::String(redact::text(&text)),
+        serde_json::Value::Array(items) => serde_json::Value::Array(items.into_iter().map(redact_json).collect()),
+        serde_json::Value::Object(fields) => {
+            serde_json::Value::Object(fields.into_iter().map(|(key, value)| (key, redact_json(value))).collect())
         }
-    } else {
-        HttpResponse::NotFound().json(ApiResponse {
-            message: format!("Command not found: {}", command),
-        })
+        value => value,
     }
 }
 
 pub struct API;
 
 impl API {
-    pub async fn start(host: String, port: u16) -> std::io::Result<()> {
-        HttpServer::new(|| {
+    pub async fn start(host: String, port: u16, config: &Config) -> std::io::Result<()> {
+        let modules = web::Data::new(InferenceModules::new(config));
+        let settings = web::Data::new(CommandSettings {
+            sandbox: config.sandbox.clone(),
+            profile: config.profile.clone(),
+            logs: config.logs.clone(),
+        });
+        HttpServer::new(move || {
             App::new()
+                .app_data(modules.clone())
+                .app_data(settings.clone())
                 .route("/subnet_command", web::post().to(execute_subnet_command))
+                .route("/subnet_command/stream", web::post().to(stream_subnet_command))
+                .route("/inference/{name}", web::post().to(run_inference))
+                .route("/logs/{module}", web::get().to(list_module_logs))
+                .route("/logs/{module}/{role}", web::get().to(module_logs))
         })
         .bind(format!("{}:{}", host, port))?
         .run()
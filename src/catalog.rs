@@ -0,0 +1,192 @@
+//! Catalog module for the Module Validator application.
+//!
+//! A catalog is a JSON file listing known subnets by name and git URL, such as
+//! `subnets/commune.json`. Every `*.json` file in the catalog directory is a
+//! separate catalog, so each network or team can keep its own curated list.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The directory catalogs are loaded from.
+pub const CATALOG_DIR: &str = "subnets";
+
+/// A subnet listed in a catalog.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    /// The name the subnet is installed under.
+    pub name: String,
+    /// The git URL of the subnet repository.
+    pub path: String,
+}
+
+/// The on-disk layout of a catalog file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CatalogFile {
+    subnets: Vec<CatalogEntry>,
+}
+
+/// A single catalog file and its entries.
+#[derive(Debug)]
+pub struct Catalog {
+    /// The catalog name, taken from the file stem (e.g. `commune`).
+    pub name: String,
+    /// The path of the catalog file.
+    pub path: PathBuf,
+    /// The subnets listed in the catalog.
+    pub entries: Vec<CatalogEntry>,
+}
+
+impl Catalog {
+    /// Loads a catalog from a JSON file, or starts an empty one if the file does not exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the catalog file.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the Catalog if successful, or an error if the file is not a valid catalog.
+    pub fn load(path: &Path) -> Result<Self> {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| Error::InvalidInput(format!("Invalid catalog path: {}", path.display())))?
+            .to_string();
+        let entries = if path.exists() {
+            let content = fs::read_to_string(path)?;
+            let file: CatalogFile = serde_json::from_str(&content)
+                .map_err(|e| Error::ConfigInvalid(format!("Failed to parse catalog {}: {}", path.display(), e)))?;
+            file.subnets
+        } else {
+            Vec::new()
+        };
+        Ok(Catalog {
+            name,
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Writes the catalog back to its file, keeping the four-space indentation of the shipped catalogs.
+    pub fn save(&self) -> Result<()> {
+        let file = CatalogFile {
+            subnets: self.entries.clone(),
+        };
+        let mut buffer = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+        let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, formatter);
+        file.serialize(&mut serializer).map_err(io::Error::from)?;
+        buffer.push(b'\n');
+        fs::write(&self.path, buffer)?;
+        Ok(())
+    }
+
+    /// Looks up a subnet by name.
+    pub fn get(&self, name: &str) -> Option<&CatalogEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Adds a subnet, failing if the catalog already lists one with the same name.
+    pub fn add(&mut self, entry: CatalogEntry) -> Result<()> {
+        if self.get(&entry.name).is_some() {
+            return Err(Error::InvalidInput(format!(
+                "Subnet {} is already in the {} catalog",
+                entry.name, self.name
+            )));
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Removes a subnet by name, returning whether it was listed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.name != name);
+        self.entries.len() != before
+    }
+}
+
+/// Every catalog in the catalog directory.
+#[derive(Debug)]
+pub struct Catalogs {
+    dir: PathBuf,
+    /// The loaded catalogs, ordered by name.
+    pub catalogs: Vec<Catalog>,
+}
+
+impl Catalogs {
+    /// Loads every `*.json` catalog in a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the catalog files.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the Catalogs if successful, or an error if a catalog cannot be read.
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let mut catalogs = Vec::new();
+        if dir.exists() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+                    catalogs.push(Catalog::load(&path)?);
+                }
+            }
+        }
+        catalogs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Catalogs {
+            dir: dir.to_path_buf(),
+            catalogs,
+        })
+    }
+
+    /// Finds a subnet by name across all catalogs, returning the first match.
+    pub fn find(&self, name: &str) -> Option<(&Catalog, &CatalogEntry)> {
+        self.catalogs
+            .iter()
+            .find_map(|catalog| catalog.get(name).map(|entry| (catalog, entry)))
+    }
+
+    /// Finds the catalog entry whose git URL matches `url`, ignoring a trailing `.git` or `/`.
+    pub fn find_by_url(&self, url: &str) -> Option<(&Catalog, &CatalogEntry)> {
+        fn normalize(url: &str) -> &str {
+            let url = url.trim_end_matches('/');
+            url.strip_suffix(".git").unwrap_or(url)
+        }
+        self.catalogs.iter().find_map(|catalog| {
+            catalog
+                .entries
+                .iter()
+                .find(|entry| normalize(&entry.path) == normalize(url))
+                .map(|entry| (catalog, entry))
+        })
+    }
+
+    /// Returns every entry whose name or URL contains `term`, ignoring case.
+    pub fn search(&self, term: &str) -> Vec<(&Catalog, &CatalogEntry)> {
+        let term = term.to_lowercase();
+        self.catalogs
+            .iter()
+            .flat_map(|catalog| catalog.entries.iter().map(move |entry| (catalog, entry)))
+            .filter(|(_, entry)| {
+                entry.name.to_lowercase().contains(&term) || entry.path.to_lowercase().contains(&term)
+            })
+            .collect()
+    }
+
+    /// Returns the named catalog, creating an empty one in the catalog directory if it does not exist yet.
+    pub fn get_or_create(&mut self, name: &str) -> Result<&mut Catalog> {
+        match self.catalogs.iter().position(|catalog| catalog.name == name) {
+            Some(index) => Ok(&mut self.catalogs[index]),
+            None => {
+                let catalog = Catalog::load(&self.dir.join(format!("{}.json", name)))?;
+                self.catalogs.push(catalog);
+                Ok(self.catalogs.last_mut().unwrap())
+            }
+        }
+    }
+}
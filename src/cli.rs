@@ -2,23 +2,93 @@
 //!
 //! This module defines the structure and available commands for the CLI.
 
-use clap::{Parser, Subcommand};
+use crate::config::ConfigOverrides;
+use crate::answers::Answers;
+use crate::error::Result;
+use crate::supervisor::RestartPolicy;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
 
 /// Represents the command-line interface for the Module Validator application.
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 pub struct Cli {
+    /// Registry database URL, overriding config.yaml and DATABASE_URL
+    #[clap(long, global = true)]
+    pub database_url: Option<String>,
+    /// Log level (error, warn, info, debug or trace)
+    #[clap(long, global = true)]
+    pub log_level: Option<String>,
+    /// Maximum number of modules run at the same time
+    #[clap(long, global = true)]
+    pub max_concurrent_modules: Option<usize>,
+    /// Profile whose .env.<profile> files override the modules' .env
+    #[clap(long, global = true)]
+    pub profile: Option<String>,
+    /// Print secrets (API keys, passwords, mnemonics, ...) instead of masking them
+    #[clap(long, global = true)]
+    pub show_secrets: bool,
     #[clap(subcommand)]
     pub command: Commands,
 }
 
+impl Cli {
+    /// Returns the configuration values given as flags.
+    pub fn config_overrides(&self) -> ConfigOverrides {
+        ConfigOverrides {
+            database_url: self.database_url.clone(),
+            log_level: self.log_level.clone(),
+            max_concurrent_modules: self.max_concurrent_modules,
+            profile: self.profile.clone(),
+        }
+    }
+}
+
 /// Enumerates the available commands for the Module Validator CLI.
 #[derive(Subcommand)]
 pub enum Commands {
     /// Install a new module
     Install {
-        /// URL of the module to install
+        /// URL of the module to install, or the name of a subnet in a catalog
         url: String,
+        /// Keep the module tree and virtual environment of a failed install for debugging
+        #[clap(long)]
+        keep_failed: bool,
+        #[clap(flatten)]
+        answers: AnswerArgs,
+    },
+    /// Browse and edit the subnet catalogs in subnets/*.json
+    Catalog {
+        #[clap(subcommand)]
+        command: CatalogCommands,
+    },
+    /// Inspect the application configuration
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
+    },
+    /// List installed modules
+    List,
+    /// Uninstall a module and remove its virtual environment and .env entries
+    Uninstall {
+        /// Name of the module to uninstall
+        name: String,
+        /// Skip the confirmation prompt
+        #[clap(short, long)]
+        yes: bool,
+    },
+    /// Update an installed subnet or inference module
+    Update {
+        /// Name of the module to update
+        name: String,
+        /// Branch, tag or commit to check out (subnets only)
+        #[clap(long = "ref")]
+        git_ref: Option<String>,
+    },
+    /// Manage the virtual environments of installed modules
+    Env {
+        #[clap(subcommand)]
+        command: EnvCommands,
     },
     /// Run a module
     RunInference {
@@ -26,31 +96,84 @@ pub enum Commands {
         name: String,
         /// Input text for the module
         input: String,
+        /// Run the module in the embedded Python interpreter instead of a new process
+        #[clap(long)]
+        embedded: bool,
+        /// Stop the module after this many seconds (default: the manifest's timeout)
+        #[clap(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
     },
     /// Parse and display the configuration of an installed module
     ParseConfig {
         /// Name of the module to parse
         name: String,
+        #[clap(flatten)]
+        answers: AnswerArgs,
     },
     /// Launch a validator for a subnet module
     LaunchValidator {
         /// Name of the subnet module to launch validator for
         name: String,
-        /// Arguments to pass to the validator (optional)
-        #[clap(default_value = "")]
-        args: String,
+        /// Arguments to pass to the validator, after `--` (optional)
+        #[clap(last = true)]
+        args: Vec<String>,
+        /// Stop the validator after this many seconds (default: the manifest's timeout)
+        #[clap(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+        #[clap(flatten)]
+        answers: AnswerArgs,
+        #[clap(flatten)]
+        detach: DetachArgs,
     },
     /// Launch a miner for a subnet module
     LaunchMiner {
         /// Name of the subnet module to launch miner for
         name: String,
-        /// Arguments to pass to the miner (optional)
-        #[clap(default_value = "")]
-        args: String,
+        /// Arguments to pass to the miner, after `--` (optional)
+        #[clap(last = true)]
+        args: Vec<String>,
+        /// Stop the miner after this many seconds (default: the manifest's timeout)
+        #[clap(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+        #[clap(flatten)]
+        answers: AnswerArgs,
+        #[clap(flatten)]
+        detach: DetachArgs,
     },
     
-    /// Start the Translation API
-    StartTranslationAPI,
+    /// Start the Translation API in the background
+    StartTranslationAPI {
+        /// When to restart the API after it exits: always, on-failure or never
+        #[clap(long, default_value = "on-failure")]
+        restart: RestartPolicy,
+    },
+
+    /// List the processes started in the background
+    Ps,
+    /// Stop a background process
+    Stop {
+        /// Name of the process, as listed by `ps`
+        name: String,
+    },
+    /// Stop a background process if it is running, then start it again
+    Restart {
+        /// Name of the process, as listed by `ps`
+        name: String,
+    },
+    /// Print the captured stdout and stderr of a background process
+    Logs {
+        /// Name of the process, as listed by `ps`
+        name: String,
+        /// Keep printing lines as they are written, until interrupted
+        #[clap(short, long)]
+        follow: bool,
+        /// Only print lines written since then: a duration such as 10m, 2h or 1d, or an RFC 3339 time
+        #[clap(long, value_name = "TIME")]
+        since: Option<String>,
+        /// Print at most this many of the last lines (default: 100 without --since)
+        #[clap(short = 'n', long, value_name = "N")]
+        lines: Option<usize>,
+    },
 
     /// Launch a proxy
     LaunchProxy {
@@ -68,4 +191,111 @@ pub enum Commands {
         #[clap(short, long, default_value = "8000")]
         port: u16,
     },
+
+    /// Run a process under supervision until it is stopped (started by --detach)
+    #[clap(hide = true)]
+    Supervise {
+        /// Name of the process to supervise
+        name: String,
+        /// Directory holding the state of supervised processes
+        #[clap(long)]
+        dir: PathBuf,
+    },
+}
+
+/// Flags that run a launch in the background under the supervisor.
+#[derive(Args, Debug, Clone, Default)]
+pub struct DetachArgs {
+    /// Run in the background, surviving this command and the terminal
+    #[clap(long)]
+    pub detach: bool,
+    /// Name to manage the detached process under (default: <module>-<role>)
+    #[clap(long, requires = "detach")]
+    pub process_name: Option<String>,
+    /// When to restart the detached process after it exits: always, on-failure or never
+    #[clap(long, default_value = "on-failure", requires = "detach")]
+    pub restart: RestartPolicy,
+    /// Set by the supervisor of a detached launch, which logs the output itself
+    #[clap(long, hide = true, conflicts_with = "detach")]
+    pub supervised: bool,
+}
+
+/// Flags that answer prompts ahead of time, for unattended installs and launches.
+#[derive(Args, Debug, Clone, Default)]
+pub struct AnswerArgs {
+    /// Fail with a list of the missing values instead of prompting
+    #[clap(long)]
+    pub non_interactive: bool,
+    /// Answer a prompt or set an environment variable (repeatable)
+    #[clap(long = "set", value_name = "KEY=VALUE")]
+    pub set: Vec<String>,
+    /// YAML file mapping prompt keys to values; --set takes precedence
+    #[clap(long, value_name = "FILE")]
+    pub answers: Option<PathBuf>,
+}
+
+impl AnswerArgs {
+    /// Loads the answers given by these flags.
+    pub fn load(&self) -> Result<Answers> {
+        Answers::load(self.answers.as_deref(), &self.set, !self.non_interactive)
+    }
+}
+
+/// Enumerates the subcommands of the `catalog` command.
+#[derive(Subcommand)]
+pub enum CatalogCommands {
+    /// List the subnets in the catalogs
+    List {
+        /// Only list the subnets in this catalog
+        #[clap(long)]
+        catalog: Option<String>,
+    },
+    /// Search the catalogs by subnet name or URL
+    Search {
+        /// Text to look for
+        term: String,
+    },
+    /// Add a subnet to a catalog
+    Add {
+        /// Name to install the subnet under
+        name: String,
+        /// Git URL of the subnet repository
+        url: String,
+        /// Catalog to add the subnet to; created if it does not exist
+        #[clap(long, default_value = "commune")]
+        catalog: String,
+    },
+    /// Remove a subnet from the catalogs
+    Remove {
+        /// Name of the subnet to remove
+        name: String,
+        /// Only remove the subnet from this catalog
+        #[clap(long)]
+        catalog: Option<String>,
+    },
+}
+
+/// Enumerates the subcommands of the `config` command.
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Print the effective configuration and where each value came from
+    Show,
+}
+
+/// Enumerates the subcommands of the `env` command.
+#[derive(Subcommand)]
+pub enum EnvCommands {
+    /// Recreate a module's virtual environment and reinstall its requirements
+    Rebuild {
+        /// Name of the module whose environment to rebuild
+        name: String,
+    },
+    /// Print the environment variables a module's commands get and where each came from
+    Show {
+        /// Name of the module
+        name: String,
+        /// Also print the variables inherited unchanged from this process
+        #[clap(long)]
+        all: bool,
+    },
 }
@@ -2,10 +2,35 @@
 //!
 //! This module provides structures and methods for loading and saving
 //! configuration data for modules and the application itself.
+//!
+//! The application configuration is layered. Each layer overrides the values set by
+//! the ones before it:
+//!
+//! 1. Built-in defaults
+//! 2. `~/.config/module_validator/config.yaml`
+//! 3. `./config.yaml`
+//! 4. Environment variables (`DATABASE_URL`, `MODULE_VALIDATOR_LOG_LEVEL`, ...)
+//! 5. Command-line flags (`--database-url`, `--log-level`, ...)
 
+use crate::environment::provider::EnvProviders;
+use crate::error::{Error, Result};
+use crate::logs::LogSettings;
+use crate::sandbox::SandboxPolicies;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The name of the configuration file in the user and project config directories.
+pub const CONFIG_FILE: &str = "config.yaml";
+
+/// The log levels accepted for `log_level`.
+const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// The runtimes accepted for `inference_runtime`.
+const INFERENCE_RUNTIMES: [&str; 2] = ["embedded", "workers"];
 
 /// Represents the configuration of a module.
 #[derive(Debug, Deserialize)]
@@ -22,6 +47,132 @@ pub struct Config {
     pub modules: Vec<String>,
     pub log_level: String,
     pub max_concurrent_modules: usize,
+    /// How the API runs inference modules: `embedded` in this process, or `workers` in Python worker processes.
+    #[serde(default = "default_inference_runtime")]
+    pub inference_runtime: String,
+    /// The number of worker processes kept per module by the `workers` runtime.
+    #[serde(default = "default_worker_pool_size")]
+    pub worker_pool_size: usize,
+    /// The number of requests a worker serves before it is restarted; 0 never restarts it.
+    #[serde(default)]
+    pub worker_max_requests: usize,
+    /// Sandbox policies for module processes, keyed by module name (`*` for every other module).
+    #[serde(default)]
+    pub sandbox: SandboxPolicies,
+    /// Python environment providers, keyed by module name (`*` for every other module).
+    #[serde(default)]
+    pub env_providers: EnvProviders,
+    /// The profile whose `.env.<profile>` files override the modules' `.env`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Where the output of module scripts and background processes is logged, and how logs are rotated.
+    #[serde(default)]
+    pub logs: LogSettings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            database_url: "sqlite://data/modules.db".to_string(),
+            modules: Vec::new(),
+            log_level: "info".to_string(),
+            max_concurrent_modules: 4,
+            inference_runtime: default_inference_runtime(),
+            worker_pool_size: default_worker_pool_size(),
+            worker_max_requests: 0,
+            sandbox: SandboxPolicies::default(),
+            env_providers: EnvProviders::default(),
+            profile: None,
+            logs: LogSettings::default(),
+        }
+    }
+}
+
+fn default_inference_runtime() -> String {
+    "embedded".to_string()
+}
+
+fn default_worker_pool_size() -> usize {
+    2
+}
+
+/// A single configuration file layer. Every key is optional; unknown keys are rejected.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigLayer {
+    database_url: Option<String>,
+    modules: Option<Vec<String>>,
+    log_level: Option<String>,
+    max_concurrent_modules: Option<usize>,
+    inference_runtime: Option<String>,
+    worker_pool_size: Option<usize>,
+    worker_max_requests: Option<usize>,
+    sandbox: Option<SandboxPolicies>,
+    env_providers: Option<EnvProviders>,
+    profile: Option<String>,
+    logs: Option<LogSettings>,
+}
+
+/// Values given on the command line, applied after every other layer.
+#[derive(Debug, Default, Clone)]
+pub struct ConfigOverrides {
+    pub database_url: Option<String>,
+    pub log_level: Option<String>,
+    pub max_concurrent_modules: Option<usize>,
+    pub profile: Option<String>,
+}
+
+impl ConfigOverrides {
+    /// Returns the environment variables that set the same values, so a copy of this
+    /// program started in the background gets them without putting them on its command line.
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = Vec::new();
+        if let Some(database_url) = &self.database_url {
+            vars.push(("DATABASE_URL", database_url.clone()));
+        }
+        if let Some(log_level) = &self.log_level {
+            vars.push(("MODULE_VALIDATOR_LOG_LEVEL", log_level.clone()));
+        }
+        if let Some(max_concurrent_modules) = self.max_concurrent_modules {
+            vars.push(("MODULE_VALIDATOR_MAX_CONCURRENT_MODULES", max_concurrent_modules.to_string()));
+        }
+        if let Some(profile) = &self.profile {
+            vars.push(("MODULE_VALIDATOR_PROFILE", profile.clone()));
+        }
+        vars
+    }
+}
+
+/// Where an effective configuration value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// The built-in default.
+    Default,
+    /// A configuration file.
+    File(PathBuf),
+    /// An environment variable.
+    Env(&'static str),
+    /// A command-line flag.
+    Cli(&'static str),
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File(path) => write!(f, "file {}", path.display()),
+            ConfigSource::Env(name) => write!(f, "env {}", name),
+            ConfigSource::Cli(flag) => write!(f, "flag {}", flag),
+        }
+    }
+}
+
+/// The effective configuration together with the origin of each value.
+#[derive(Debug)]
+pub struct LoadedConfig {
+    pub config: Config,
+    /// The source of each key, keyed by its name in `config.yaml`.
+    pub sources: BTreeMap<&'static str, ConfigSource>,
 }
 
 impl Config {
@@ -34,9 +185,11 @@ impl Config {
     /// # Returns
     ///
     /// A Result containing the Config if successful, or an error if loading fails.
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
         let config_str = std::fs::read_to_string(path)?;
-        let config: Config = serde_yaml::from_str(&config_str)?;
+        let config: Config = serde_yaml::from_str(&config_str)
+            .map_err(|e| Error::ConfigInvalid(format!("{}: {}", path.display(), e)))?;
         Ok(config)
     }
 
@@ -49,9 +202,252 @@ impl Config {
     /// # Returns
     ///
     /// A Result indicating success or failure of the save operation.
-    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
-        let contents = serde_yaml::to_string(self)?;
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let contents = serde_yaml::to_string(self).map_err(io::Error::other)?;
         fs::write(path, contents)?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Returns the configuration files read at startup, in the order they are applied.
+    pub fn default_paths() -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if let Some(config_dir) = dirs::config_dir() {
+            paths.push(config_dir.join("module_validator").join(CONFIG_FILE));
+        }
+        paths.push(PathBuf::from(CONFIG_FILE));
+        paths
+    }
+
+    /// Loads the layered application configuration from the default paths and the process environment.
+    ///
+    /// # Arguments
+    ///
+    /// * `overrides` - The values given on the command line.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the effective configuration, or an error if a layer is invalid.
+    pub fn load(overrides: &ConfigOverrides) -> Result<LoadedConfig> {
+        Self::load_layers(&Self::default_paths(), |name| std::env::var(name).ok(), overrides)
+    }
+
+    /// Loads the layered application configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - The configuration files to apply in order; missing files are skipped.
+    /// * `env` - Looks up an environment variable.
+    /// * `overrides` - The values given on the command line.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the effective configuration, or an error if a layer is invalid.
+    pub fn load_layers(
+        files: &[PathBuf],
+        env: impl Fn(&str) -> Option<String>,
+        overrides: &ConfigOverrides,
+    ) -> Result<LoadedConfig> {
+        let mut loaded = LoadedConfig {
+            config: Config::default(),
+            sources: [
+                "database_url",
+                "modules",
+                "log_level",
+                "max_concurrent_modules",
+                "inference_runtime",
+                "worker_pool_size",
+                "worker_max_requests",
+                "sandbox",
+                "env_providers",
+                "profile",
+                "logs",
+            ]
+                .into_iter()
+                .map(|key| (key, ConfigSource::Default))
+                .collect(),
+        };
+
+        for path in files.iter().filter(|path| path.is_file()) {
+            let content = fs::read_to_string(path)?;
+            let layer: ConfigLayer = if content.trim().is_empty() {
+                ConfigLayer::default()
+            } else {
+                serde_yaml::from_str(&content)
+                    .map_err(|e| Error::ConfigInvalid(format!("{}: {}", path.display(), e)))?
+            };
+            loaded.apply(layer, || ConfigSource::File(path.clone()));
+        }
+
+        let max_concurrent_modules = match env("MODULE_VALIDATOR_MAX_CONCURRENT_MODULES") {
+            Some(value) => Some(value.trim().parse().map_err(|e| {
+                Error::ConfigInvalid(format!("MODULE_VALIDATOR_MAX_CONCURRENT_MODULES={}: {}", value, e))
+            })?),
+            None => None,
+        };
+        loaded.set_database_url(env("DATABASE_URL"), ConfigSource::Env("DATABASE_URL"));
+        loaded.set_log_level(env("MODULE_VALIDATOR_LOG_LEVEL"), ConfigSource::Env("MODULE_VALIDATOR_LOG_LEVEL"));
+        loaded.set_max_concurrent_modules(
+            max_concurrent_modules,
+            ConfigSource::Env("MODULE_VALIDATOR_MAX_CONCURRENT_MODULES"),
+        );
+        loaded.set_profile(env("MODULE_VALIDATOR_PROFILE"), ConfigSource::Env("MODULE_VALIDATOR_PROFILE"));
+
+        loaded.set_database_url(overrides.database_url.clone(), ConfigSource::Cli("--database-url"));
+        loaded.set_log_level(overrides.log_level.clone(), ConfigSource::Cli("--log-level"));
+        loaded.set_max_concurrent_modules(overrides.max_concurrent_modules, ConfigSource::Cli("--max-concurrent-modules"));
+        loaded.set_profile(overrides.profile.clone(), ConfigSource::Cli("--profile"));
+
+        loaded.validate()?;
+        Ok(loaded)
+    }
+}
+
+impl LoadedConfig {
+    /// Returns the effective value of every key as text, with its source, ordered by key.
+    pub fn entries(&self) -> Vec<(&'static str, String, &ConfigSource)> {
+        let config = &self.config;
+        let values = [
+            ("database_url", config.database_url.clone()),
+            ("env_providers", config.env_providers.to_string()),
+            ("inference_runtime", config.inference_runtime.clone()),
+            ("log_level", config.log_level.clone()),
+            ("logs", config.logs.to_string()),
+            ("max_concurrent_modules", config.max_concurrent_modules.to_string()),
+            ("modules", format!("[{}]", config.modules.join(", "))),
+            ("profile", config.profile.clone().unwrap_or_else(|| "none".to_string())),
+            ("sandbox", config.sandbox.to_string()),
+            ("worker_max_requests", config.worker_max_requests.to_string()),
+            ("worker_pool_size", config.worker_pool_size.to_string()),
+        ];
+        values
+            .into_iter()
+            .map(|(key, value)| (key, value, &self.sources[key]))
+            .collect()
+    }
+
+    fn apply(&mut self, layer: ConfigLayer, source: impl Fn() -> ConfigSource) {
+        self.set_database_url(layer.database_url, source());
+        self.set_log_level(layer.log_level, source());
+        self.set_max_concurrent_modules(layer.max_concurrent_modules, source());
+        self.set_profile(layer.profile, source());
+        if let Some(modules) = layer.modules {
+            self.config.modules = modules;
+            self.sources.insert("modules", source());
+        }
+        if let Some(inference_runtime) = layer.inference_runtime {
+            self.config.inference_runtime = inference_runtime;
+            self.sources.insert("inference_runtime", source());
+        }
+        if let Some(worker_pool_size) = layer.worker_pool_size {
+            self.config.worker_pool_size = worker_pool_size;
+            self.sources.insert("worker_pool_size", source());
+        }
+        if let Some(worker_max_requests) = layer.worker_max_requests {
+            self.config.worker_max_requests = worker_max_requests;
+            self.sources.insert("worker_max_requests", source());
+        }
+        // Policies are merged per module, so a later layer only replaces the modules it names
+        if let Some(sandbox) = layer.sandbox {
+            self.config.sandbox.0.extend(sandbox.0);
+            self.sources.insert("sandbox", source());
+        }
+        if let Some(logs) = layer.logs {
+            self.config.logs = logs;
+            self.sources.insert("logs", source());
+        }
+        if let Some(env_providers) = layer.env_providers {
+            self.config.env_providers.0.extend(env_providers.0);
+            self.sources.insert("env_providers", source());
+        }
+    }
+
+    fn set_database_url(&mut self, value: Option<String>, source: ConfigSource) {
+        if let Some(value) = value {
+            self.config.database_url = value;
+            self.sources.insert("database_url", source);
+        }
+    }
+
+    fn set_log_level(&mut self, value: Option<String>, source: ConfigSource) {
+        if let Some(value) = value {
+            self.config.log_level = value;
+            self.sources.insert("log_level", source);
+        }
+    }
+
+    fn set_max_concurrent_modules(&mut self, value: Option<usize>, source: ConfigSource) {
+        if let Some(value) = value {
+            self.config.max_concurrent_modules = value;
+            self.sources.insert("max_concurrent_modules", source);
+        }
+    }
+
+    fn set_profile(&mut self, value: Option<String>, source: ConfigSource) {
+        if let Some(value) = value {
+            self.config.profile = Some(value);
+            self.sources.insert("profile", source);
+        }
+    }
+
+    /// Checks the effective values, reporting every problem together with the layer that set it.
+    fn validate(&self) -> Result<()> {
+        let config = &self.config;
+        let mut problems = Vec::new();
+        if !(config.database_url.starts_with("postgres://")
+            || config.database_url.starts_with("postgresql://")
+            || config.database_url.starts_with("sqlite:"))
+        {
+            problems.push(format!(
+                "database_url `{}` (from {}) must start with postgres://, postgresql:// or sqlite:",
+                config.database_url, self.sources["database_url"]
+            ));
+        }
+        if !LOG_LEVELS.contains(&config.log_level.to_lowercase().as_str()) {
+            problems.push(format!(
+                "log_level `{}` (from {}) must be one of {}",
+                config.log_level,
+                self.sources["log_level"],
+                LOG_LEVELS.join(", ")
+            ));
+        }
+        if config.max_concurrent_modules == 0 {
+            problems.push(format!(
+                "max_concurrent_modules (from {}) must be at least 1",
+                self.sources["max_concurrent_modules"]
+            ));
+        }
+        if !INFERENCE_RUNTIMES.contains(&config.inference_runtime.as_str()) {
+            problems.push(format!(
+                "inference_runtime `{}` (from {}) must be one of {}",
+                config.inference_runtime,
+                self.sources["inference_runtime"],
+                INFERENCE_RUNTIMES.join(", ")
+            ));
+        }
+        if config.worker_pool_size == 0 {
+            problems.push(format!(
+                "worker_pool_size (from {}) must be at least 1",
+                self.sources["worker_pool_size"]
+            ));
+        }
+        // The profile names a file in each module directory
+        if let Some(profile) = &config.profile {
+            if profile.is_empty() || !profile.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                problems.push(format!(
+                    "profile `{}` (from {}) may only contain letters, digits, `_` and `-`",
+                    profile, self.sources["profile"]
+                ));
+            }
+        }
+        for (name, policy) in &config.sandbox.0 {
+            for problem in policy.problems() {
+                problems.push(format!("sandbox.{}: {} (from {})", name, problem, self.sources["sandbox"]));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::ConfigInvalid(problems.join("; ")))
+        }
+    }
+}
@@ -1,11 +1,14 @@
 use std::fs;
+use crate::error::{Error, Result};
 use std::path::Path;
-use std::error::Error;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use dialoguer::Input;
-use std::io::Write;
+use std::collections::{BTreeSet, HashMap};
+use crate::answers::Answers;
+use crate::dotenv;
+use crate::manifest::ModuleManifest;
+use crate::redact;
+use crate::utils::set_env_entries;
 
 /// Represents the configuration of an argument in a command.
 #[derive(Debug, Serialize, Deserialize)]
@@ -13,6 +16,9 @@ pub struct ArgConfig {
     pub type_: String,
     pub default: Option<String>,
     pub help: Option<String>,
+    /// Whether the parameter has no default value in the function signature.
+    #[serde(default)]
+    pub required: bool,
 }
 
 /// Represents the configuration of a command.
@@ -22,11 +28,80 @@ pub struct CommandConfig {
     pub args: HashMap<String, ArgConfig>,
 }
 
+impl CommandConfig {
+    /// Builds the command-line arguments for a call of the command from named values.
+    ///
+    /// Every value becomes a `--name value` pair, with underscores in the name turned into
+    /// dashes as typer does. Booleans become `--name` or `--no-name`, and arrays repeat the
+    /// option for each element. Only arguments declared by the command are accepted, and
+    /// each value is a single argv element, so it is never interpreted by a shell.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The argument values, keyed by parameter name.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the arguments, or an InvalidInput error for an unknown argument,
+    /// a missing required argument or a value that cannot be passed on the command line.
+    pub fn to_argv(&self, values: &HashMap<String, serde_json::Value>) -> Result<Vec<String>> {
+        let mut unknown: Vec<&String> = values.keys().filter(|name| !self.args.contains_key(*name)).collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            return Err(Error::InvalidInput(format!(
+                "Unknown argument(s) for {}: {}",
+                self.function,
+                unknown.iter().map(|name| name.as_str()).collect::<Vec<_>>().join(", ")
+            )));
+        }
+
+        let mut names: Vec<&String> = self.args.keys().collect();
+        names.sort();
+        let mut argv = Vec::new();
+        for name in names {
+            let option = name.replace('_', "-");
+            let value = match values.get(name) {
+                Some(value) => value,
+                None if self.args[name].required => {
+                    return Err(Error::InvalidInput(format!("Missing argument: {}", name)))
+                }
+                None => continue,
+            };
+            let items = match value {
+                serde_json::Value::Array(items) => items.iter().collect(),
+                value => vec![value],
+            };
+            for item in items {
+                match item {
+                    serde_json::Value::Bool(true) => argv.push(format!("--{}", option)),
+                    serde_json::Value::Bool(false) => argv.push(format!("--no-{}", option)),
+                    serde_json::Value::String(text) => argv.extend([format!("--{}", option), text.clone()]),
+                    serde_json::Value::Number(number) => argv.extend([format!("--{}", option), number.to_string()]),
+                    serde_json::Value::Null => {}
+                    _ => {
+                        return Err(Error::InvalidInput(format!(
+                            "Argument {} must be a string, number, boolean or array of those",
+                            name
+                        )))
+                    }
+                }
+            }
+        }
+        Ok(argv)
+    }
+}
+
 /// Represents the overall configuration of a module.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ModuleConfig {
     pub env_vars: HashMap<String, String>,
     pub commands: HashMap<String, CommandConfig>,
+    /// Descriptions of environment variables, taken from the module manifest.
+    #[serde(default)]
+    pub env_descriptions: HashMap<String, String>,
+    /// Environment variables the module manifest marks as required.
+    #[serde(default)]
+    pub required_env: BTreeSet<String>,
 }
 
 /// Provides functionality for parsing and manipulating module configurations.
@@ -42,25 +117,24 @@ pub struct ConfigParser;
     ///
     /// A Result containing the parsed ModuleConfig if successful, or an error if parsing fails.
 impl ConfigParser {
-    pub fn parse_commands(file_dir: &Path) -> Result<ModuleConfig, Box<dyn Error>> {
+    pub fn parse_commands(file_dir: &Path) -> Result<ModuleConfig> {
         println!("Parsing commands from directory: {:?}", file_dir);
         
         let mut config = ModuleConfig {
             env_vars: HashMap::new(),
             commands: HashMap::new(),
+            env_descriptions: HashMap::new(),
+            required_env: BTreeSet::new(),
         };
 
-        // Parse .env file
+        // Parse .env.example file
         let env_example_file = file_dir.join(".env.example");
         println!("Checking for .env file: {:?}", env_example_file);
         if env_example_file.exists() {
             println!(".env.example file found, parsing...");
-            let env_content = fs::read_to_string(&env_example_file)?;
-            for line in env_content.lines() {
-                if let Some((key, value)) = line.split_once('=') {
-                    config.env_vars.insert(key.trim().to_string(), value.trim().to_string());
-                    println!("Added env var: {} = {}", key.trim(), value.trim());
-                }
+            for (key, value) in dotenv::parse_file(&env_example_file, |name| std::env::var(name).ok())? {
+                println!("Added env var: {} = {}", key, redact::value(&key, &value));
+                config.env_vars.insert(key, value);
             }
         } else {
             println!(".env.example file not found");
@@ -72,7 +146,7 @@ impl ConfigParser {
             let entry = entry?;
             let path = entry.path();
             println!("Examining file: {:?}", path);
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "py") {
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "py") {
                 println!("Parsing Python file: {:?}", path);
                 Self::parse_python_file(&path, &mut config)?;
             } else {
@@ -80,6 +154,24 @@ impl ConfigParser {
             }
         }
 
+        // A manifest that declares env vars replaces the ones guessed from .env.example and argparse
+        if let Some(manifest) = ModuleManifest::load(file_dir)?.filter(|manifest| !manifest.env.is_empty()) {
+            println!("Using environment variables declared in the module manifest");
+            config.env_vars.clear();
+            for (key, spec) in manifest.env {
+                if spec.secret {
+                    redact::declare([key.clone()]);
+                }
+                config.env_vars.insert(key.clone(), spec.default.unwrap_or_default());
+                if spec.required {
+                    config.required_env.insert(key.clone());
+                }
+                if let Some(description) = spec.description {
+                    config.env_descriptions.insert(key, description);
+                }
+            }
+        }
+
         println!("Parsing complete. Found {} env vars and {} commands", 
 
                  config.env_vars.len(), config.commands.len());
@@ -87,21 +179,24 @@ impl ConfigParser {
         Ok(config)
     }
 
-    fn parse_python_file(file_path: &Path, config: &mut ModuleConfig) -> Result<(), Box<dyn Error>> {
+    fn parse_python_file(file_path: &Path, config: &mut ModuleConfig) -> Result<()> {
         println!("Parsing Python file: {:?}", file_path);
         let content = fs::read_to_string(file_path)?;
 
         // Parse argparse arguments
-        let argparse_regex = Regex::new(r#"parser\.add_argument\(['"](--[\w-]+)['"].*?(?:default=(.*?))?(?:,|\))"#)?;
+        let argparse_regex = Regex::new(r#"parser\.add_argument\(['"](--[\w-]+)['"].*?(?:default=(.*?))?(?:,|\))"#).unwrap();
         for cap in argparse_regex.captures_iter(&content) {
             let key = cap[1].trim_start_matches("--").replace("-", "_");
             let default = cap.get(2).map(|m| m.as_str().trim().trim_matches(|c| c == '\'' || c == '"').to_string());
             config.env_vars.insert(key.clone(), default.clone().unwrap_or_default());
-            println!("Found argparse argument: {} = {:?}", key, default);
+            println!("Found argparse argument: {} = {:?}", key, default.as_deref().map(|default| redact::value(&key, default)));
         }
 
         // Parse typer commands
-        let typer_regex = Regex::new(r#"@app\.command\(['"]([\w-]+)['"].*?\)\s*def\s+(\w+)\((.*?)\):"#)?;
+        let typer_regex = Regex::new(r#"@app\.command\(['"]([\w-]+)['"].*?\)\s*def\s+(\w+)\((.*?)\):"#).unwrap();
+        let arg_regex = Regex::new(r#"(\w+):\s*(?:Optional\[)?(\w+)(?:\])?(\s*=\s*(?:typer\.(?:Argument|Option)\((.*?)\))?)?"#).unwrap();
+        let default_regex = Regex::new(r#"default\s*=\s*['"](.*?)['"]"#).unwrap();
+        let help_regex = Regex::new(r#"help\s*=\s*['"](.*?)['"]"#).unwrap();
         for cap in typer_regex.captures_iter(&content) {
             let command_name = cap[1].to_string();
             let function_name = cap[2].to_string();
@@ -114,11 +209,11 @@ impl ConfigParser {
                 args: HashMap::new(),
             };
 
-            let arg_regex = Regex::new(r#"(\w+):\s*(?:Optional\[)?(\w+)(?:\])?\s*=\s*(?:typer\.(?:Argument|Option)\((.*?)\))?"#)?;
             for arg_cap in arg_regex.captures_iter(args) {
                 let arg_name = arg_cap[1].to_string();
                 let arg_type = arg_cap[2].to_string();
-                let arg_options = arg_cap.get(3).map(|m| m.as_str()).unwrap_or("");
+                let required = arg_cap.get(3).is_none();
+                let arg_options = arg_cap.get(4).map(|m| m.as_str()).unwrap_or("");
 
                 println!("  Argument: {} (type: {})", arg_name, arg_type);
 
@@ -126,17 +221,16 @@ impl ConfigParser {
                     type_: arg_type,
                     default: None,
                     help: None,
+                    required,
                 };
 
                 if !arg_options.is_empty() {
                     // Parse additional options like default value, help text, etc.
-                    let default_regex = Regex::new(r#"default\s*=\s*['"](.*?)['"]"#)?;
                     if let Some(default_cap) = default_regex.captures(arg_options) {
                         arg_config.default = Some(default_cap[1].to_string());
                         println!("    Default value: {:?}", arg_config.default);
                     }
 
-                    let help_regex = Regex::new(r#"help\s*=\s*['"](.*?)['"]"#)?;
                     if let Some(help_cap) = help_regex.captures(arg_options) {
                         arg_config.help = Some(help_cap[1].to_string());
                         println!("    Help text: {:?}", arg_config.help);
@@ -154,45 +248,53 @@ impl ConfigParser {
         Ok(())
     }
 
-    /// Prompts the user for values for environment variables.
+    /// Fills in the environment variables from the answers, prompting for the rest when allowed.
+    ///
+    /// Without prompting, unanswered variables keep their defaults; required variables
+    /// left empty are reported together.
     ///
     /// # Arguments
     ///
     /// * `config` - The ModuleConfig containing the environment variables to prompt for.
+    /// * `answers` - Values given ahead of time with `--set` or `--answers`.
     ///
     /// # Returns
     ///
-    /// A Result indicating success or failure of the prompting operation.
-    pub fn prompt_for_env_vars(config: &mut ModuleConfig) -> Result<(), Box<dyn Error>> {
-        for (key, value) in &mut config.env_vars {
-            let default = value.clone();
-            let prompt = format!("Enter value for {} (default: {})", key, default);
-            let input: String = Input::new()
-                .with_prompt(&prompt)
-                .default(default.clone())
-                .interact_text()?;
-            *value = input;
+    /// A Result indicating success, or a MissingValues error listing the required variables without a value.
+    pub fn prompt_for_env_vars(config: &mut ModuleConfig, answers: &Answers) -> Result<()> {
+        let mut keys: Vec<String> = config.env_vars.keys().cloned().collect();
+        keys.sort();
+        let mut missing = Vec::new();
+        for key in keys {
+            let default = config.env_vars[&key].clone();
+            let shown_default = redact::value(&key, &default);
+            let prompt = match config.env_descriptions.get(&key) {
+                Some(description) => format!("Enter value for {} - {} (default: {})", key, description, shown_default),
+                None => format!("Enter value for {} (default: {})", key, shown_default),
+            };
+            let value = answers.ask(&key, &prompt, Some(&default))?.unwrap_or_default();
+            if value.is_empty() && config.required_env.contains(&key) {
+                missing.push(key.clone());
+            }
+            config.env_vars.insert(key, value);
+        }
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::MissingValues(missing))
         }
-        Ok(())
     }
 
-    pub fn save_config(config: &ModuleConfig, module_dir: &Path) -> Result<(), Box<dyn Error>> {
+    pub fn save_config(config: &ModuleConfig, module_dir: &Path) -> Result<()> {
         let env_file_path = module_dir.join(".env");
-        if !env_file_path.exists() {
-            let mut save_file = fs::File::create(env_file_path)?;
-            for (key, value) in &config.env_vars {
-                writeln!(save_file, "{}={}", key, value)?;
-            }
-        } else {
-            let mut save_file = fs::OpenOptions::new()
-                .write(true)
-                .append(true)
-                .open(env_file_path)?;
-            for (key, value) in &config.env_vars {
-                writeln!(save_file, "{}={}", key, value)?;
-            }
-        }
-        println!("Configuration saved to: {:?}", module_dir.join(".env"));
+        let mut entries: Vec<(String, String)> = config
+            .env_vars
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        entries.sort();
+        set_env_entries(&env_file_path, &entries)?;
+        println!("Configuration saved to: {:?}", env_file_path);
         Ok(())
     }
 }
@@ -0,0 +1,143 @@
+//! Database module for the Module Validator application.
+//!
+//! This module defines the storage interface behind the module registry and
+//! picks a backend from the database URL: Postgres for `postgres://` URLs and
+//! a local SQLite file for `sqlite://` URLs.
+
+pub mod postgres;
+pub mod sqlite;
+
+use crate::error::{Error, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+
+/// The kind of module tracked by the registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleType {
+    /// A module installed from the registrar into `modules/`.
+    Inference,
+    /// A subnet repository cloned into `subnets/`.
+    Subnet,
+}
+
+impl ModuleType {
+    /// Returns the value stored in the `module_type` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModuleType::Inference => "inference",
+            ModuleType::Subnet => "subnet",
+        }
+    }
+
+    /// Returns the directory modules of this type are installed into.
+    pub fn directory(&self) -> &'static str {
+        match self {
+            ModuleType::Inference => "modules",
+            ModuleType::Subnet => "subnets",
+        }
+    }
+}
+
+impl fmt::Display for ModuleType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ModuleType {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "inference" => Ok(ModuleType::Inference),
+            "subnet" | "subnets" => Ok(ModuleType::Subnet),
+            other => Err(format!("Unknown module type: {}", other)),
+        }
+    }
+}
+
+/// The details recorded when a module is installed.
+#[derive(Debug, Clone)]
+pub struct NewModule {
+    pub name: String,
+    pub module_type: ModuleType,
+    pub version: Option<String>,
+    pub entry_point: Option<String>,
+    pub install_path: String,
+    /// The version of the interpreter the module's environment was created with.
+    pub python_version: Option<String>,
+    /// The path of the interpreter the module's environment was created with.
+    pub python_path: Option<String>,
+}
+
+/// A row of the `modules` table.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleRecord {
+    pub name: String,
+    pub module_type: Option<ModuleType>,
+    pub version: Option<String>,
+    pub entry_point: Option<String>,
+    pub install_path: Option<String>,
+    pub python_version: Option<String>,
+    pub python_path: Option<String>,
+    pub installed_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Columns selected when reading a ModuleRecord, shared by every backend.
+pub(crate) const MODULE_COLUMNS: &str =
+    "name, module_type, version, entry_point, install_path, python_version, python_path, installed_at, updated_at, last_used_at";
+
+/// Storage operations the module registry needs from a database backend.
+#[async_trait]
+pub trait ModuleStore: Send + Sync {
+    /// Brings the schema up to date by running the backend's embedded migrations.
+    async fn migrate(&self) -> Result<()>;
+
+    /// Inserts a module, or refreshes its row if it is already present.
+    async fn upsert_module(&self, module: &NewModule) -> Result<()>;
+
+    /// Fetches a single module by name.
+    async fn get_module(&self, name: &str) -> Result<Option<ModuleRecord>>;
+
+    /// Fetches every module ordered by name, optionally restricted to one type.
+    async fn list_modules(&self, module_type: Option<ModuleType>) -> Result<Vec<ModuleRecord>>;
+
+    /// Stamps the module's `last_used_at` column with the current time.
+    async fn touch_module(&self, name: &str) -> Result<()>;
+
+    /// Deletes a module's row, returning whether one existed.
+    async fn delete_module(&self, name: &str) -> Result<bool>;
+}
+
+/// Connects to the database at the given URL and runs its migrations.
+///
+/// # Arguments
+///
+/// * `database_url` - A `postgres://` or `sqlite://` connection string.
+///
+/// # Returns
+///
+/// A Result containing the connected store, or an error if the scheme is unsupported or the connection fails.
+pub async fn connect(database_url: &str) -> Result<Box<dyn ModuleStore>> {
+    let store: Box<dyn ModuleStore> = if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+        Box::new(PostgresStore::connect(database_url).await?)
+    } else if database_url.starts_with("sqlite:") {
+        Box::new(SqliteStore::connect(database_url).await?)
+    } else {
+        return Err(Error::ConfigInvalid(format!(
+            "Unsupported database URL: {} (expected postgres:// or sqlite://)",
+            database_url
+        )));
+    };
+    store.migrate().await?;
+    Ok(store)
+}
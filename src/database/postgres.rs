@@ -0,0 +1,127 @@
+//! Postgres backend for the module registry.
+//!
+//! Works against the `modules` table that `scripts/setup_database.sh` creates.
+
+use crate::error::Result;
+use super::{ModuleRecord, ModuleStore, ModuleType, NewModule, MODULE_COLUMNS};
+use async_trait::async_trait;
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use sqlx::Row;
+
+/// A connection pool to a Postgres module database.
+#[derive(Clone)]
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    /// Connects to the Postgres database at the given URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `database_url` - A `postgres://` connection string.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the PostgresStore if successful, or an error if the connection fails.
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(database_url)
+            .await?;
+        Ok(PostgresStore { pool })
+    }
+}
+
+fn record_from_row(row: &PgRow) -> std::result::Result<ModuleRecord, sqlx::Error> {
+    let module_type: Option<String> = row.try_get("module_type")?;
+    Ok(ModuleRecord {
+        name: row.try_get("name")?,
+        module_type: module_type.and_then(|t| t.parse().ok()),
+        version: row.try_get("version")?,
+        entry_point: row.try_get("entry_point")?,
+        install_path: row.try_get("install_path")?,
+        python_version: row.try_get("python_version")?,
+        python_path: row.try_get("python_path")?,
+        installed_at: row.try_get("installed_at")?,
+        updated_at: row.try_get("updated_at")?,
+        last_used_at: row.try_get("last_used_at")?,
+    })
+}
+
+#[async_trait]
+impl ModuleStore for PostgresStore {
+    async fn migrate(&self) -> Result<()> {
+        sqlx::migrate!("./migrations/postgres").run(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn upsert_module(&self, module: &NewModule) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO modules (name, module_type, version, entry_point, install_path, python_version, python_path, installed_at, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+             ON CONFLICT (name) DO UPDATE SET
+                 module_type = EXCLUDED.module_type,
+                 version = EXCLUDED.version,
+                 entry_point = EXCLUDED.entry_point,
+                 install_path = EXCLUDED.install_path,
+                 python_version = EXCLUDED.python_version,
+                 python_path = EXCLUDED.python_path,
+                 updated_at = EXCLUDED.updated_at",
+        )
+        .bind(&module.name)
+        .bind(module.module_type.as_str())
+        .bind(&module.version)
+        .bind(&module.entry_point)
+        .bind(&module.install_path)
+        .bind(&module.python_version)
+        .bind(&module.python_path)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_module(&self, name: &str) -> Result<Option<ModuleRecord>> {
+        let row = sqlx::query(&format!("SELECT {} FROM modules WHERE name = $1", MODULE_COLUMNS))
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(record_from_row).transpose()?)
+    }
+
+    async fn list_modules(&self, module_type: Option<ModuleType>) -> Result<Vec<ModuleRecord>> {
+        let rows = match module_type {
+            Some(module_type) => {
+                sqlx::query(&format!(
+                    "SELECT {} FROM modules WHERE module_type = $1 ORDER BY name",
+                    MODULE_COLUMNS
+                ))
+                .bind(module_type.as_str())
+                .fetch_all(&self.pool)
+                .await?
+            }
+            None => {
+                sqlx::query(&format!("SELECT {} FROM modules ORDER BY name", MODULE_COLUMNS))
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+        };
+        Ok(rows.iter().map(record_from_row).collect::<std::result::Result<_, _>>()?)
+    }
+
+    async fn touch_module(&self, name: &str) -> Result<()> {
+        sqlx::query("UPDATE modules SET last_used_at = CURRENT_TIMESTAMP WHERE name = $1")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_module(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM modules WHERE name = $1")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,140 @@
+//! SQLite backend for the module registry.
+//!
+//! Keeps the registry in a local file, by default `data/modules.db`, so a fresh
+//! box works without an external database.
+
+use crate::error::Result;
+use super::{ModuleRecord, ModuleStore, ModuleType, NewModule, MODULE_COLUMNS};
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::Row;
+use std::fs;
+use std::str::FromStr;
+
+/// A connection pool to a SQLite module database.
+#[derive(Clone)]
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Opens the SQLite database at the given URL, creating the file and its directory if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `database_url` - A `sqlite://` connection string, e.g. `sqlite://data/modules.db`.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the SqliteStore if successful, or an error if the database cannot be opened.
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(database_url)?.create_if_missing(true);
+        if let Some(parent) = options.get_filename().parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let pool = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await?;
+        Ok(SqliteStore { pool })
+    }
+}
+
+fn record_from_row(row: &SqliteRow) -> std::result::Result<ModuleRecord, sqlx::Error> {
+    let module_type: Option<String> = row.try_get("module_type")?;
+    Ok(ModuleRecord {
+        name: row.try_get("name")?,
+        module_type: module_type.and_then(|t| t.parse().ok()),
+        version: row.try_get("version")?,
+        entry_point: row.try_get("entry_point")?,
+        install_path: row.try_get("install_path")?,
+        python_version: row.try_get("python_version")?,
+        python_path: row.try_get("python_path")?,
+        installed_at: row.try_get("installed_at")?,
+        updated_at: row.try_get("updated_at")?,
+        last_used_at: row.try_get("last_used_at")?,
+    })
+}
+
+#[async_trait]
+impl ModuleStore for SqliteStore {
+    async fn migrate(&self) -> Result<()> {
+        sqlx::migrate!("./migrations/sqlite").run(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn upsert_module(&self, module: &NewModule) -> Result<()> {
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO modules (name, module_type, version, entry_point, install_path, python_version, python_path, installed_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)
+             ON CONFLICT (name) DO UPDATE SET
+                 module_type = excluded.module_type,
+                 version = excluded.version,
+                 entry_point = excluded.entry_point,
+                 install_path = excluded.install_path,
+                 python_version = excluded.python_version,
+                 python_path = excluded.python_path,
+                 updated_at = excluded.updated_at",
+        )
+        .bind(&module.name)
+        .bind(module.module_type.as_str())
+        .bind(&module.version)
+        .bind(&module.entry_point)
+        .bind(&module.install_path)
+        .bind(&module.python_version)
+        .bind(&module.python_path)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_module(&self, name: &str) -> Result<Option<ModuleRecord>> {
+        let row = sqlx::query(&format!("SELECT {} FROM modules WHERE name = ?1", MODULE_COLUMNS))
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(record_from_row).transpose()?)
+    }
+
+    async fn list_modules(&self, module_type: Option<ModuleType>) -> Result<Vec<ModuleRecord>> {
+        let rows = match module_type {
+            Some(module_type) => {
+                sqlx::query(&format!(
+                    "SELECT {} FROM modules WHERE module_type = ?1 ORDER BY name",
+                    MODULE_COLUMNS
+                ))
+                .bind(module_type.as_str())
+                .fetch_all(&self.pool)
+                .await?
+            }
+            None => {
+                sqlx::query(&format!("SELECT {} FROM modules ORDER BY name", MODULE_COLUMNS))
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+        };
+        Ok(rows.iter().map(record_from_row).collect::<std::result::Result<_, _>>()?)
+    }
+
+    async fn touch_module(&self, name: &str) -> Result<()> {
+        sqlx::query("UPDATE modules SET last_used_at = ?1 WHERE name = ?2")
+            .bind(Utc::now())
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_module(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM modules WHERE name = ?1")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,450 @@
+//! `.env` file parsing and the layered environment of module processes.
+//!
+//! The variables a module's commands see are composed from these layers, each
+//! overriding the ones before it:
+//!
+//! 1. The environment of this process
+//! 2. The global `.env` in the root directory
+//! 3. The module's `.env`
+//! 4. The profile's `.env.<profile>` in the module directory, when a profile is selected
+//! 5. Overrides given for the call, e.g. with `--set`
+//!
+//! `.env` files hold `KEY=VALUE` lines. Lines may start with `export`, `#` starts a
+//! comment, and values may be single-quoted (taken literally), double-quoted (with
+//! escapes such as `\n`) or unquoted. Quoted values can span several lines. `${VAR}`,
+//! `${VAR:-default}` and `$VAR` in double-quoted and unquoted values are replaced by
+//! the variable's value from earlier in the file or from the earlier layers.
+
+use crate::error::{Error, Result};
+use crate::manifest::ModuleManifest;
+use crate::redact;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the value of a module environment variable came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvSource {
+    /// The environment of this process.
+    Process,
+    /// The global `.env` file.
+    Global(PathBuf),
+    /// The module's `.env` file.
+    Module(PathBuf),
+    /// The `.env.<profile>` file of the selected profile.
+    Profile(PathBuf),
+    /// A value given for the call.
+    Override,
+}
+
+impl fmt::Display for EnvSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvSource::Process => write!(f, "process"),
+            EnvSource::Global(path) => write!(f, "global {}", path.display()),
+            EnvSource::Module(path) => write!(f, "module {}", path.display()),
+            EnvSource::Profile(path) => write!(f, "profile {}", path.display()),
+            EnvSource::Override => write!(f, "override"),
+        }
+    }
+}
+
+/// The composed environment of a module, with the source of each variable.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedEnv {
+    vars: BTreeMap<String, (String, EnvSource)>,
+}
+
+impl ResolvedEnv {
+    /// Starts an environment from the variables of a process.
+    ///
+    /// # Arguments
+    ///
+    /// * `process` - The variables of the process, usually [`env::vars`].
+    pub fn new(process: impl IntoIterator<Item = (String, String)>) -> Self {
+        ResolvedEnv {
+            vars: process
+                .into_iter()
+                .map(|(key, value)| (key, (value, EnvSource::Process)))
+                .collect(),
+        }
+    }
+
+    /// Applies a `.env` file over the variables set so far; a missing file is skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the `.env` file.
+    /// * `source` - The source recorded for the file's variables.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating whether the file could be read and parsed.
+    pub fn apply_file(&mut self, path: &Path, source: EnvSource) -> Result<()> {
+        if !path.is_file() {
+            return Ok(());
+        }
+        let entries = parse_file(path, |name| self.get(name).map(str::to_string))?;
+        self.apply(entries, source);
+        Ok(())
+    }
+
+    /// Sets variables over the ones set so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The keys and values to set.
+    /// * `source` - The source recorded for them.
+    pub fn apply(&mut self, entries: impl IntoIterator<Item = (String, String)>, source: EnvSource) {
+        for (key, value) in entries {
+            self.vars.insert(key, (value, source.clone()));
+        }
+    }
+
+    /// Returns the value of a variable.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(|(value, _)| value.as_str())
+    }
+
+    /// Returns where the value of a variable came from.
+    pub fn source(&self, name: &str) -> Option<&EnvSource> {
+        self.vars.get(name).map(|(_, source)| source)
+    }
+
+    /// Returns every variable with its value and source, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, &EnvSource)> {
+        self.vars.iter().map(|(key, (value, source))| (key.as_str(), value.as_str(), source))
+    }
+
+    /// Returns the variables set by a layer after the process environment, which are the
+    /// ones to pass to a child process that inherits (or deliberately drops) the rest.
+    pub fn layered(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.iter()
+            .filter(|(_, _, source)| **source != EnvSource::Process)
+            .map(|(key, value, _)| (key, value))
+    }
+}
+
+/// Composes the environment of a module's processes from every layer.
+///
+/// The values of its secret variables, including the ones the module manifest declares
+/// secret, are masked in output from then on.
+///
+/// # Arguments
+///
+/// * `root_dir` - The directory holding the global `.env`.
+/// * `module_dir` - The module directory, holding its `.env` and profile files.
+/// * `profile` - The selected profile, if any.
+/// * `overrides` - The values given for the call.
+///
+/// # Returns
+///
+/// A Result containing the environment, or a ConfigInvalid error naming the file and
+/// line of a `.env` entry that cannot be parsed.
+pub fn resolve(
+    root_dir: &Path,
+    module_dir: &Path,
+    profile: Option<&str>,
+    overrides: &BTreeMap<String, String>,
+) -> Result<ResolvedEnv> {
+    let mut resolved = ResolvedEnv::new(env::vars());
+    let global = root_dir.join(".env");
+    resolved.apply_file(&global, EnvSource::Global(global.clone()))?;
+    let module = module_dir.join(".env");
+    resolved.apply_file(&module, EnvSource::Module(module.clone()))?;
+    if let Some(profile) = profile {
+        let profile = module_dir.join(format!(".env.{}", profile));
+        resolved.apply_file(&profile, EnvSource::Profile(profile.clone()))?;
+    }
+    resolved.apply(overrides.clone(), EnvSource::Override);
+
+    if let Some(manifest) = ModuleManifest::load(module_dir)? {
+        redact::declare(manifest.env.into_iter().filter(|(_, spec)| spec.secret).map(|(key, _)| key));
+    }
+    redact::learn_env(&resolved);
+    Ok(resolved)
+}
+
+/// Sets the variables of a `.env` file in this process's environment, keeping the ones
+/// that are already set. A missing file is skipped.
+///
+/// # Arguments
+///
+/// * `path` - The path to the `.env` file.
+///
+/// # Returns
+///
+/// A Result indicating whether the file could be read and parsed.
+pub fn load_into_process(path: &Path) -> Result<()> {
+    if !path.is_file() {
+        return Ok(());
+    }
+    for (key, value) in parse_file(path, |name| env::var(name).ok())? {
+        if env::var_os(&key).is_none() {
+            env::set_var(key, value);
+        }
+    }
+    Ok(())
+}
+
+/// Parses a `.env` file.
+///
+/// # Arguments
+///
+/// * `path` - The path to the `.env` file.
+/// * `lookup` - Looks up variables referenced with `${VAR}` that the file does not set itself.
+///
+/// # Returns
+///
+/// A Result containing the entries in file order, or a ConfigInvalid error naming the
+/// file and line of an entry that cannot be parsed.
+pub fn parse_file(path: &Path, lookup: impl Fn(&str) -> Option<String>) -> Result<Vec<(String, String)>> {
+    let content = fs::read_to_string(path)?;
+    parse(&content, lookup).map_err(|e| match e {
+        Error::ConfigInvalid(reason) => Error::ConfigInvalid(format!("{}: {}", path.display(), reason)),
+        e => e,
+    })
+}
+
+/// Parses the content of a `.env` file.
+///
+/// # Arguments
+///
+/// * `content` - The content of the file.
+/// * `lookup` - Looks up variables referenced with `${VAR}` that the content does not set itself.
+///
+/// # Returns
+///
+/// A Result containing the entries in order, or a ConfigInvalid error naming the line of
+/// an entry that cannot be parsed. A key set twice appears twice; the last value wins.
+pub fn parse(content: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<Vec<(String, String)>> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut parser = Parser { chars: &chars, pos: 0, line: 1 };
+    let mut entries: Vec<(String, String)> = Vec::new();
+    loop {
+        parser.skip_blank();
+        if parser.peek().is_none() {
+            return Ok(entries);
+        }
+        let line = parser.line;
+        let lookup = |name: &str| {
+            entries
+                .iter()
+                .rev()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+                .or_else(|| lookup(name))
+        };
+        let entry = parser
+            .entry(&lookup)
+            .map_err(|reason| Error::ConfigInvalid(format!("line {}: {}", line, reason)))?;
+        entries.push(entry);
+    }
+}
+
+type Lookup<'a> = dyn Fn(&str) -> Option<String> + 'a;
+
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    /// Skips to the start of the next line.
+    fn skip_line(&mut self) {
+        while let Some(c) = self.bump() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    /// Skips blank lines and comment lines.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some('\n' | '\r') => {
+                    self.bump();
+                }
+                Some('#') => self.skip_line(),
+                _ => return,
+            }
+        }
+    }
+
+    fn word(&mut self, is_word: fn(char) -> bool) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek().filter(|c| is_word(*c)) {
+            word.push(c);
+            self.bump();
+        }
+        word
+    }
+
+    fn entry(&mut self, lookup: &Lookup) -> std::result::Result<(String, String), String> {
+        let mut key = self.word(is_key_char);
+        if key == "export" && matches!(self.peek(), Some(' ' | '\t')) {
+            self.skip_spaces();
+            key = self.word(is_key_char);
+        }
+        if key.is_empty() || key.starts_with(|c: char| c.is_ascii_digit()) {
+            let rest: String = self.chars[self.pos..].iter().take_while(|c| **c != '\n').collect();
+            return Err(format!("expected a variable name, found `{}{}`", key, rest.trim_end()));
+        }
+        self.skip_spaces();
+        if self.peek() != Some('=') {
+            return Err(format!("expected `=` after {}", key));
+        }
+        self.bump();
+        self.skip_spaces();
+        let value = match self.peek() {
+            Some('\'') => {
+                self.bump();
+                let value = self.single_quoted()?;
+                self.end_of_value()?;
+                value
+            }
+            Some('"') => {
+                self.bump();
+                let value = self.double_quoted(lookup)?;
+                self.end_of_value()?;
+                value
+            }
+            _ => self.unquoted(lookup)?,
+        };
+        Ok((key, value))
+    }
+
+    /// Skips the rest of the line after a quoted value, which may only hold a comment.
+    fn end_of_value(&mut self) -> std::result::Result<(), String> {
+        self.skip_spaces();
+        match self.peek() {
+            None | Some('\n' | '\r' | '#') => {
+                self.skip_line();
+                Ok(())
+            }
+            Some(c) => Err(format!("unexpected `{}` after the closing quote", c)),
+        }
+    }
+
+    fn single_quoted(&mut self) -> std::result::Result<String, String> {
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None => return Err("unterminated single quote".to_string()),
+                Some('\'') => return Ok(value),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn double_quoted(&mut self, lookup: &Lookup) -> std::result::Result<String, String> {
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None => return Err("unterminated double quote".to_string()),
+                Some('"') => return Ok(value),
+                Some('\\') => match self.bump() {
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some(c @ ('"' | '\\' | '$')) => value.push(c),
+                    Some(c) => {
+                        value.push('\\');
+                        value.push(c);
+                    }
+                    None => return Err("unterminated double quote".to_string()),
+                },
+                Some('$') => self.expand(lookup, &mut value)?,
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn unquoted(&mut self, lookup: &Lookup) -> std::result::Result<String, String> {
+        let mut value = String::new();
+        while let Some(c) = self.peek() {
+            if c == '\n' {
+                break;
+            }
+            // `#` starts a comment only after whitespace, so `KEY=a#b` keeps it
+            if c == '#' && matches!(self.chars.get(self.pos.wrapping_sub(1)), Some(' ' | '\t')) {
+                break;
+            }
+            self.bump();
+            match c {
+                '$' => self.expand(lookup, &mut value)?,
+                '\\' if self.peek() == Some('$') => {
+                    self.bump();
+                    value.push('$');
+                }
+                c => value.push(c),
+            }
+        }
+        self.skip_line();
+        Ok(value.trim_end().to_string())
+    }
+
+    /// Appends the value of the variable referenced after a `$`.
+    fn expand(&mut self, lookup: &Lookup, value: &mut String) -> std::result::Result<(), String> {
+        if self.peek() != Some('{') {
+            let name = self.word(is_name_char);
+            if name.is_empty() {
+                value.push('$');
+            } else {
+                value.push_str(&lookup(&name).unwrap_or_default());
+            }
+            return Ok(());
+        }
+        self.bump();
+        let name = self.word(is_name_char);
+        match self.bump() {
+            Some('}') => value.push_str(&lookup(&name).unwrap_or_default()),
+            Some(':') if self.peek() == Some('-') => {
+                self.bump();
+                let mut default = String::new();
+                loop {
+                    match self.bump() {
+                        Some('}') => break,
+                        Some(c) => default.push(c),
+                        None => return Err(format!("unterminated `${{{}`", name)),
+                    }
+                }
+                value.push_str(&lookup(&name).filter(|found| !found.is_empty()).unwrap_or(default));
+            }
+            _ => return Err(format!("unterminated `${{{}`", name)),
+        }
+        Ok(())
+    }
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-')
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
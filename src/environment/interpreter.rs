@@ -0,0 +1,317 @@
+//! Python interpreter discovery.
+//!
+//! Modules can constrain the Python version their environment is created with, through
+//! the `python` field of their manifest or `requires-python` in their `pyproject.toml`.
+//! [`find`] looks for interpreters named `python3` or `python3.X` on PATH, in pyenv's
+//! shims and versions, and in common install prefixes, and picks one that satisfies
+//! the constraint.
+
+use crate::error::{Error, Result};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// The interpreter used when it satisfies the module's constraint, as before discovery existed.
+pub const DEFAULT_PYTHON: &str = "python3";
+
+/// Directories searched after PATH and pyenv.
+const COMMON_PREFIXES: [&str; 5] = ["/usr/local/bin", "/usr/bin", "/bin", "/opt/homebrew/bin", "/opt/local/bin"];
+
+/// A Python release version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PythonVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl PythonVersion {
+    /// Parses the output of `python --version`, e.g. `Python 3.11.2` or `Python 3.13.0rc1`.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The version text, with or without the `Python` prefix.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let text = text.strip_prefix("Python").unwrap_or(text).trim();
+        let mut parts = text.splitn(3, '.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+        // A pre-release suffix such as `0rc1` is ignored
+        let patch = parts
+            .next()
+            .map(|patch| patch.chars().take_while(char::is_ascii_digit).collect::<String>())
+            .and_then(|digits| digits.parse().ok())
+            .unwrap_or(0);
+        Some(PythonVersion { major, minor, patch })
+    }
+
+    fn release(&self) -> [u32; 3] {
+        [self.major, self.minor, self.patch]
+    }
+}
+
+impl fmt::Display for PythonVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// A constraint on the Python version, in the PEP 440 syntax of `requires-python`.
+///
+/// Clauses are separated by commas and all must hold, e.g. `>=3.9,<3.12`. The operators
+/// `>=`, `>`, `<=`, `<`, `==`, `!=` and `~=` are supported, `==` and `!=` with a trailing
+/// `.*`. A bare version such as `3.10` is read as `==3.10.*`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionSpec {
+    text: String,
+    clauses: Vec<Clause>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Clause {
+    operator: Operator,
+    release: Vec<u32>,
+    wildcard: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    Compatible,
+}
+
+impl VersionSpec {
+    /// Returns whether a version satisfies every clause.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The version to check.
+    pub fn matches(&self, version: PythonVersion) -> bool {
+        self.clauses.iter().all(|clause| clause.matches(version))
+    }
+}
+
+impl Clause {
+    fn matches(&self, version: PythonVersion) -> bool {
+        let ordering = compare(&version.release(), &self.release);
+        match self.operator {
+            Operator::Greater => ordering == Ordering::Greater,
+            Operator::GreaterEqual => ordering != Ordering::Less,
+            Operator::Less => ordering == Ordering::Less,
+            Operator::LessEqual => ordering != Ordering::Greater,
+            Operator::Equal if self.wildcard => has_prefix(version, &self.release),
+            Operator::Equal => ordering == Ordering::Equal,
+            Operator::NotEqual if self.wildcard => !has_prefix(version, &self.release),
+            Operator::NotEqual => ordering != Ordering::Equal,
+            // `~=3.9.1` means `>=3.9.1,==3.9.*`
+            Operator::Compatible => {
+                ordering != Ordering::Less && has_prefix(version, &self.release[..self.release.len() - 1])
+            }
+        }
+    }
+}
+
+/// Compares release segments, padding the shorter one with zeros.
+fn compare(version: &[u32], release: &[u32]) -> Ordering {
+    let len = version.len().max(release.len());
+    (0..len)
+        .map(|i| version.get(i).unwrap_or(&0).cmp(release.get(i).unwrap_or(&0)))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn has_prefix(version: PythonVersion, prefix: &[u32]) -> bool {
+    prefix.len() <= 3 && version.release()[..prefix.len()] == *prefix
+}
+
+impl FromStr for VersionSpec {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let invalid = |reason: &str| Error::ConfigInvalid(format!("Python version constraint `{}`: {}", text, reason));
+        let mut clauses = Vec::new();
+        for clause in text.split(',').map(str::trim) {
+            let operators = [
+                ("~=", Operator::Compatible),
+                ("==", Operator::Equal),
+                ("!=", Operator::NotEqual),
+                (">=", Operator::GreaterEqual),
+                ("<=", Operator::LessEqual),
+                (">", Operator::Greater),
+                ("<", Operator::Less),
+            ];
+            let (operator, version, bare) = match operators.iter().find(|(symbol, _)| clause.starts_with(symbol)) {
+                Some((symbol, operator)) => (*operator, clause[symbol.len()..].trim(), false),
+                None => (Operator::Equal, clause, true),
+            };
+            let (version, wildcard) = match version.strip_suffix(".*") {
+                Some(version) => (version, true),
+                None => (version, bare),
+            };
+            if wildcard && !matches!(operator, Operator::Equal | Operator::NotEqual) {
+                return Err(invalid("`.*` only follows `==` or `!=`"));
+            }
+            let release = version
+                .split('.')
+                .map(|segment| segment.parse::<u32>())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|_| invalid(&format!("`{}` is not a version", clause)))?;
+            if operator == Operator::Compatible && release.len() < 2 {
+                return Err(invalid("`~=` needs at least two version segments"));
+            }
+            clauses.push(Clause { operator, release, wildcard });
+        }
+        Ok(VersionSpec { text: text.trim().to_string(), clauses })
+    }
+}
+
+impl fmt::Display for VersionSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// A Python interpreter found on this machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interpreter {
+    pub path: PathBuf,
+    pub version: PythonVersion,
+}
+
+impl Interpreter {
+    /// Runs an interpreter to find out its version.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The interpreter, as a path or a name looked up on PATH.
+    ///
+    /// # Returns
+    ///
+    /// The interpreter, or None if it cannot be run or its version is not understood.
+    pub fn probe(path: &Path) -> Option<Self> {
+        let output = Command::new(path).arg("--version").output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        // Python 2 printed its version to stderr
+        let version = PythonVersion::parse(&String::from_utf8_lossy(&output.stdout))
+            .or_else(|| PythonVersion::parse(&String::from_utf8_lossy(&output.stderr)))?;
+        Some(Interpreter { path: path.to_path_buf(), version })
+    }
+}
+
+impl fmt::Display for Interpreter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Python {} ({})", self.version, self.path.display())
+    }
+}
+
+/// Returns the directories searched for interpreters: PATH, pyenv's shims and
+/// installed versions, then [`COMMON_PREFIXES`].
+pub fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = env::var_os("PATH").map(|path| env::split_paths(&path).collect()).unwrap_or_default();
+
+    let pyenv_root = env::var_os("PYENV_ROOT")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".pyenv")));
+    if let Some(pyenv_root) = pyenv_root {
+        dirs.push(pyenv_root.join("shims"));
+        if let Ok(entries) = fs::read_dir(pyenv_root.join("versions")) {
+            let mut versions: Vec<PathBuf> = entries.flatten().map(|entry| entry.path().join("bin")).collect();
+            versions.sort();
+            dirs.extend(versions);
+        }
+    }
+
+    dirs.extend(COMMON_PREFIXES.iter().map(PathBuf::from));
+    dirs
+}
+
+/// Lists the interpreters named `python3` or `python3.X` in the given directories, in
+/// search order, skipping ones that cannot be run and duplicates of the same file.
+///
+/// # Arguments
+///
+/// * `dirs` - The directories to search.
+pub fn discover(dirs: &[PathBuf]) -> Vec<Interpreter> {
+    let mut seen = HashSet::new();
+    let mut interpreters = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir) else { continue };
+        let mut paths: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(is_interpreter_name))
+            .collect();
+        // `python3` before `python3.X`, so the default is found first
+        paths.sort_by_key(|path| (path.file_name() != Some(DEFAULT_PYTHON.as_ref()), path.clone()));
+        for path in paths {
+            let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            // pyenv shims are one script dispatching on its name, so they differ by name only
+            let key = if dir.ends_with("shims") { path.clone() } else { canonical };
+            if !seen.insert(key) {
+                continue;
+            }
+            if let Some(interpreter) = Interpreter::probe(&path) {
+                interpreters.push(interpreter);
+            }
+        }
+    }
+    interpreters
+}
+
+fn is_interpreter_name(name: &str) -> bool {
+    match name.strip_prefix(DEFAULT_PYTHON) {
+        Some("") => true,
+        Some(minor) => minor.strip_prefix('.').is_some_and(|minor| !minor.is_empty() && minor.bytes().all(|b| b.is_ascii_digit())),
+        None => false,
+    }
+}
+
+/// Picks the interpreter for a module from the given directories.
+///
+/// The first `python3` found is used when it satisfies the constraint, so modules
+/// without one keep the interpreter they always had; otherwise the newest satisfying
+/// interpreter is chosen.
+///
+/// # Arguments
+///
+/// * `requirement` - The module's constraint on the Python version, if any.
+/// * `dirs` - The directories to search, usually [`search_dirs`].
+///
+/// # Returns
+///
+/// A Result containing the interpreter, or an InterpreterNotFound error listing the
+/// interpreters found when none satisfies the constraint.
+pub fn find(requirement: Option<&VersionSpec>, dirs: &[PathBuf]) -> Result<Interpreter> {
+    let interpreters = discover(dirs);
+    let satisfies = |interpreter: &&Interpreter| requirement.is_none_or(|spec| spec.matches(interpreter.version));
+
+    let default = interpreters.iter().find(|interpreter| interpreter.path.file_name() == Some(DEFAULT_PYTHON.as_ref()));
+    if let Some(default) = default.filter(satisfies) {
+        return Ok(default.clone());
+    }
+    // The first of equally new interpreters wins, as max_by_key would pick the last
+    let newest = interpreters
+        .iter()
+        .filter(satisfies)
+        .fold(None, |newest: Option<&Interpreter>, interpreter| match newest {
+            Some(newest) if newest.version >= interpreter.version => Some(newest),
+            _ => Some(interpreter),
+        });
+    newest.cloned().ok_or_else(|| Error::InterpreterNotFound {
+        requirement: requirement.map_or_else(|| "any version".to_string(), ToString::to_string),
+        found: interpreters.iter().map(ToString::to_string).collect(),
+    })
+}
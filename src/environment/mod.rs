@@ -0,0 +1,349 @@
+//! Python environment provisioning for the Module Validator application.
+//!
+//! Every module runs in its own Python environment in `.<name>`, made by the
+//! [`EnvProvider`](provider::EnvProvider) chosen for it in `config.yaml` or its manifest
+//! (a venv by default). Provisioning it (creating it and installing the module's requirements) takes seconds
+//! and needs the network, so [`ModuleEnv::provision`] only does it when something the
+//! environment depends on has changed. After a successful provisioning, a marker file in
+//! the environment records the provider, the version of the interpreter it was created
+//! with and a fingerprint of those and the module's requirement files. While the
+//! fingerprint matches, provisioning returns at once.
+//!
+//! The base interpreter is discovered by [`interpreter::find`] to satisfy the module's
+//! Python version constraint, and kept while it still does. A new provider or interpreter
+//! version recreates the environment; changed requirement files only reinstall them.
+//! [`ModuleEnv::rebuild`] recreates the environment unconditionally.
+
+pub mod interpreter;
+pub mod provider;
+
+use crate::error::{Error, Result};
+use crate::manifest::ModuleManifest;
+use crate::sandbox::SandboxPolicy;
+use crate::utils::sha256_hex;
+use interpreter::{Interpreter, VersionSpec};
+use provider::EnvProviderKind;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The marker file, inside the virtual environment, recording what it was provisioned for.
+pub const MARKER_FILE: &str = "module_validator.json";
+
+/// The files in the module directory that determine what is installed in the environment.
+const REQUIREMENT_FILES: [&str; 4] = ["requirements.txt", "pyproject.toml", "setup.py", "environment.yml"];
+
+/// What a virtual environment was provisioned for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvMarker {
+    /// The provider that made the environment; environments from before providers are venvs.
+    #[serde(default)]
+    pub provider: EnvProviderKind,
+    /// The version of the interpreter the environment was created with, e.g. `Python 3.11.2`.
+    pub interpreter: String,
+    /// The path of the interpreter the environment was created with.
+    #[serde(default)]
+    pub python: Option<PathBuf>,
+    /// The SHA-256 of the provider, the interpreter version and the module's requirement files.
+    pub fingerprint: String,
+}
+
+impl EnvMarker {
+    /// Returns the interpreter version without the `Python` prefix, as recorded in the registry.
+    pub fn python_version(&self) -> String {
+        self.interpreter.trim_start_matches("Python").trim().to_string()
+    }
+}
+
+/// What [`ModuleEnv::provision`] had to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provisioning {
+    /// The environment matched its marker and was left alone.
+    UpToDate,
+    /// The existing environment's requirements were reinstalled.
+    Updated,
+    /// The environment was created, or recreated for a new provider or interpreter.
+    Created,
+}
+
+impl fmt::Display for Provisioning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Provisioning::UpToDate => write!(f, "up to date"),
+            Provisioning::Updated => write!(f, "requirements reinstalled"),
+            Provisioning::Created => write!(f, "created"),
+        }
+    }
+}
+
+/// The virtual environment of a module.
+#[derive(Debug, Clone)]
+pub struct ModuleEnv {
+    venv_dir: PathBuf,
+    module_dir: PathBuf,
+    base_python: Option<PathBuf>,
+    requires_python: Option<VersionSpec>,
+    provider: EnvProviderKind,
+}
+
+impl ModuleEnv {
+    /// Describes the virtual environment of a module, made by the provider its manifest
+    /// names for the Python versions its manifest or `pyproject.toml` allows.
+    ///
+    /// # Arguments
+    ///
+    /// * `venv_dir` - The virtual environment directory, usually `.<name>`.
+    /// * `module_dir` - The module directory holding its requirement files.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the ModuleEnv, or an error if the module's manifest, `pyproject.toml`
+    /// or version constraint is invalid.
+    pub fn new(venv_dir: impl Into<PathBuf>, module_dir: impl Into<PathBuf>) -> Result<Self> {
+        let module_dir = module_dir.into();
+        let manifest = ModuleManifest::load(&module_dir)?;
+        let provider = manifest.as_ref().and_then(|manifest| manifest.environment).unwrap_or_default();
+        let requires_python = match manifest.and_then(|manifest| manifest.python) {
+            Some(python) => Some(python),
+            None => pyproject_requires_python(&module_dir)?,
+        };
+        Ok(ModuleEnv {
+            venv_dir: venv_dir.into(),
+            module_dir,
+            base_python: None,
+            requires_python: requires_python.map(|spec| spec.parse()).transpose()?,
+            provider,
+        })
+    }
+
+    /// Uses another provider than the manifest's, e.g. the one chosen in `config.yaml`.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The provider to use; None keeps the manifest's.
+    pub fn with_provider(mut self, provider: Option<EnvProviderKind>) -> Self {
+        if let Some(provider) = provider {
+            self.provider = provider;
+        }
+        self
+    }
+
+    /// Keeps the provider the environment was last provisioned with, if it has been.
+    ///
+    /// Running a module must not switch its provider behind the operator's back, so the
+    /// executor uses this; `update` and `env rebuild` apply a new choice.
+    pub fn with_recorded_provider(self) -> Self {
+        let recorded = self.marker().map(|marker| marker.provider);
+        self.with_provider(recorded)
+    }
+
+    /// Uses the given interpreter to create the environment instead of discovering one.
+    ///
+    /// # Arguments
+    ///
+    /// * `python` - The interpreter to create the environment with.
+    pub fn with_base_python(mut self, python: impl Into<PathBuf>) -> Self {
+        self.base_python = Some(python.into());
+        self
+    }
+
+    /// Returns the module's constraint on the Python version, if it has one.
+    pub fn requires_python(&self) -> Option<&VersionSpec> {
+        self.requires_python.as_ref()
+    }
+
+    /// Returns the virtual environment directory.
+    pub fn venv_dir(&self) -> &Path {
+        &self.venv_dir
+    }
+
+    /// Returns the provider that makes the environment.
+    pub fn provider(&self) -> EnvProviderKind {
+        self.provider
+    }
+
+    /// Returns the environment's Python interpreter.
+    pub fn python(&self) -> PathBuf {
+        self.provider.provider().interpreter(&self.venv_dir)
+    }
+
+    /// Reads the marker left by the last successful provisioning, if any.
+    pub fn marker(&self) -> Option<EnvMarker> {
+        self::marker(&self.venv_dir)
+    }
+
+    /// Computes the marker the environment should have for the current provider, interpreter
+    /// and requirement files.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the expected marker, or an error if no suitable interpreter is found.
+    pub fn expected_marker(&self) -> Result<EnvMarker> {
+        let base = self.base_interpreter()?;
+        let interpreter = format!("Python {}", base.version);
+        let mut fingerprint = format!("{}\0{}", self.provider, interpreter).into_bytes();
+        for file in REQUIREMENT_FILES {
+            fingerprint.push(0);
+            fingerprint.extend_from_slice(file.as_bytes());
+            fingerprint.push(0);
+            match fs::read(self.module_dir.join(file)) {
+                Ok(content) => fingerprint.extend_from_slice(&content),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(EnvMarker {
+            provider: self.provider,
+            interpreter,
+            python: Some(base.path),
+            fingerprint: sha256_hex(fingerprint),
+        })
+    }
+
+    /// Chooses the interpreter the environment is based on.
+    ///
+    /// An interpreter given with [`ModuleEnv::with_base_python`] must satisfy the module's
+    /// constraint. Otherwise the one recorded in the marker is kept while it still runs and
+    /// satisfies it, so discovery only happens when the environment is first provisioned.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the interpreter, or an error if none satisfies the constraint.
+    pub fn base_interpreter(&self) -> Result<Interpreter> {
+        let satisfies = |interpreter: &Interpreter| {
+            self.requires_python.as_ref().is_none_or(|spec| spec.matches(interpreter.version))
+        };
+        if let Some(path) = &self.base_python {
+            let base = Interpreter::probe(path).ok_or_else(|| Error::VenvCreationFailed {
+                path: self.venv_dir.clone(),
+                stderr: format!("cannot run {} --version", path.display()),
+            })?;
+            if !satisfies(&base) {
+                return Err(Error::InterpreterNotFound {
+                    requirement: self.requires_python.as_ref().map(ToString::to_string).unwrap_or_default(),
+                    found: vec![base.to_string()],
+                });
+            }
+            return Ok(base);
+        }
+        let recorded = self.marker().and_then(|marker| marker.python).and_then(|path| Interpreter::probe(&path));
+        match recorded.filter(satisfies) {
+            Some(recorded) => Ok(recorded),
+            None => interpreter::find(self.requires_python.as_ref(), &interpreter::search_dirs()),
+        }
+    }
+
+    /// Makes sure the environment exists and has the module's requirements installed.
+    ///
+    /// Nothing is run when the marker matches the current provider, interpreter and
+    /// requirement files. Otherwise the environment is created if needed (or recreated
+    /// when the provider or interpreter version changed) and the requirements are
+    /// installed; the marker is only written once all of that succeeded.
+    ///
+    /// # Arguments
+    ///
+    /// * `sandbox` - The module's sandbox policy, which confines installing its requirements.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing what had to be done, or an error if a step failed.
+    pub fn provision(&self, sandbox: Option<&SandboxPolicy>) -> Result<Provisioning> {
+        let expected = self.expected_marker()?;
+        let marker = self.marker();
+        let python = self.python();
+        if python.exists() && marker.as_ref() == Some(&expected) {
+            return Ok(Provisioning::UpToDate);
+        }
+
+        // An environment without a marker predates markers and is reused if it works
+        let outdated = marker
+            .as_ref()
+            .is_some_and(|marker| marker.provider != expected.provider || marker.interpreter != expected.interpreter);
+        if self.venv_dir.exists() && (outdated || !python.exists()) {
+            println!(
+                "Recreating virtual environment {:?} with {} for {}",
+                self.venv_dir, expected.provider, expected.interpreter
+            );
+            destroy(&self.venv_dir)?;
+        }
+
+        let created = !self.venv_dir.exists();
+        let provider = self.provider.provider();
+        if created {
+            println!("Creating virtual environment at {:?} with {}", self.venv_dir, self.provider);
+            let base_python = expected.python.as_deref().unwrap_or(Path::new(interpreter::DEFAULT_PYTHON));
+            provider.create(&self.venv_dir, base_python, &expected.interpreter)?;
+        } else {
+            // A failed provisioning must not leave the old marker behind
+            match fs::remove_file(self.venv_dir.join(MARKER_FILE)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        provider.install_requirements(&self.venv_dir, &std::path::absolute(&self.module_dir)?, sandbox)?;
+
+        let marker = serde_json::to_string_pretty(&expected).map_err(io::Error::other)?;
+        fs::write(self.venv_dir.join(MARKER_FILE), marker)?;
+        Ok(if created { Provisioning::Created } else { Provisioning::Updated })
+    }
+
+    /// Deletes the environment and provisions it from scratch.
+    ///
+    /// # Arguments
+    ///
+    /// * `sandbox` - The module's sandbox policy, which confines installing its requirements.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success, or an error if a step failed.
+    pub fn rebuild(&self, sandbox: Option<&SandboxPolicy>) -> Result<()> {
+        if self.venv_dir.exists() {
+            println!("Removing virtual environment {:?}", self.venv_dir);
+            destroy(&self.venv_dir)?;
+        }
+        self.provision(sandbox).map(|_| ())
+    }
+}
+
+/// Deletes a module's environment with the provider that made it, as recorded in its marker.
+///
+/// # Arguments
+///
+/// * `venv_dir` - The environment directory.
+///
+/// # Returns
+///
+/// A Result indicating success, or an error if the environment cannot be removed.
+pub fn destroy(venv_dir: &Path) -> Result<()> {
+    let made_by = marker(venv_dir).map(|marker| marker.provider).unwrap_or_default();
+    made_by.provider().destroy(venv_dir)
+}
+
+/// Reads the marker of a module's environment, if it has been provisioned.
+///
+/// # Arguments
+///
+/// * `venv_dir` - The environment directory.
+pub fn marker(venv_dir: &Path) -> Option<EnvMarker> {
+    let content = fs::read_to_string(venv_dir.join(MARKER_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Reads `requires-python` from the `[project]` table of the module's `pyproject.toml`.
+fn pyproject_requires_python(module_dir: &Path) -> Result<Option<String>> {
+    let path = module_dir.join("pyproject.toml");
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let pyproject: toml::Value =
+        toml::from_str(&content).map_err(|e| Error::ConfigInvalid(format!("{}: {}", path.display(), e)))?;
+    Ok(pyproject
+        .get("project")
+        .and_then(|project| project.get("requires-python"))
+        .and_then(|requires| requires.as_str())
+        .map(str::to_string))
+}
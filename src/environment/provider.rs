@@ -0,0 +1,334 @@
+//! The tools that create Python environments for modules.
+//!
+//! An [`EnvProvider`] creates an environment in a module's `.<name>` directory,
+//! installs the module's requirements into it and deletes it again. Every provider
+//! lays the environment out so that its interpreter is `bin/python3`, which is what
+//! the executor, the manifest setup steps and the workers run.
+//!
+//! Installing requirements runs the module's own build code (`setup.py`, its build
+//! backend, conda post-link scripts), so it runs under the module's sandbox policy when
+//! it has one, like its setup scripts.
+
+use crate::error::{Error, Result};
+use crate::sandbox::SandboxPolicy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The key of the provider used for modules that have none of their own.
+pub const ALL_MODULES: &str = "*";
+
+/// Creates, fills and deletes the Python environment of a module.
+pub trait EnvProvider {
+    /// Creates an empty environment.
+    ///
+    /// # Arguments
+    ///
+    /// * `env_dir` - The directory to create the environment in; it does not exist yet.
+    /// * `base_python` - The interpreter the environment is based on.
+    /// * `version` - The version reported by `base_python`, e.g. `Python 3.11.2`.
+    fn create(&self, env_dir: &Path, base_python: &Path, version: &str) -> Result<()>;
+
+    /// Installs the module's package and requirement files into the environment.
+    ///
+    /// # Arguments
+    ///
+    /// * `env_dir` - The environment directory.
+    /// * `module_dir` - The absolute path of the module directory.
+    /// * `sandbox` - The module's sandbox policy, if it is sandboxed.
+    fn install_requirements(&self, env_dir: &Path, module_dir: &Path, sandbox: Option<&SandboxPolicy>) -> Result<()>;
+
+    /// Returns the environment's interpreter.
+    ///
+    /// # Arguments
+    ///
+    /// * `env_dir` - The environment directory.
+    fn interpreter(&self, env_dir: &Path) -> PathBuf {
+        if cfg!(windows) {
+            env_dir.join("Scripts").join("python.exe")
+        } else {
+            env_dir.join("bin").join("python3")
+        }
+    }
+
+    /// Deletes the environment.
+    ///
+    /// # Arguments
+    ///
+    /// * `env_dir` - The environment directory.
+    fn destroy(&self, env_dir: &Path) -> Result<()> {
+        fs::remove_dir_all(env_dir)?;
+        Ok(())
+    }
+}
+
+/// The available environment providers, as named in `config.yaml` and module manifests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvProviderKind {
+    /// `python -m venv` and pip.
+    #[default]
+    Venv,
+    /// `uv venv` and `uv pip`.
+    Uv,
+    /// A conda environment created with `conda create --prefix`.
+    Conda,
+    /// A conda environment created with mamba.
+    Mamba,
+    /// No environment: the base interpreter itself, with requirements installed into it.
+    System,
+}
+
+impl EnvProviderKind {
+    /// Returns the provider implementing this kind.
+    pub fn provider(self) -> Box<dyn EnvProvider> {
+        match self {
+            EnvProviderKind::Venv => Box::new(VenvProvider),
+            EnvProviderKind::Uv => Box::new(UvProvider),
+            EnvProviderKind::Conda => Box::new(CondaProvider { executable: "conda" }),
+            EnvProviderKind::Mamba => Box::new(CondaProvider { executable: "mamba" }),
+            EnvProviderKind::System => Box::new(SystemProvider),
+        }
+    }
+}
+
+impl fmt::Display for EnvProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EnvProviderKind::Venv => "venv",
+            EnvProviderKind::Uv => "uv",
+            EnvProviderKind::Conda => "conda",
+            EnvProviderKind::Mamba => "mamba",
+            EnvProviderKind::System => "system",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The environment providers chosen in `config.yaml`, keyed by module name.
+///
+/// The provider under [`ALL_MODULES`] applies to modules without one of their own.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EnvProviders(pub BTreeMap<String, EnvProviderKind>);
+
+impl EnvProviders {
+    /// Returns the provider configured for a module, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the module.
+    pub fn for_module(&self, name: &str) -> Option<EnvProviderKind> {
+        self.0.get(name).or_else(|| self.0.get(ALL_MODULES)).copied()
+    }
+}
+
+impl fmt::Display for EnvProviders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "none");
+        }
+        let providers: Vec<String> = self.0.iter().map(|(name, kind)| format!("{}: {}", name, kind)).collect();
+        write!(f, "{}", providers.join("; "))
+    }
+}
+
+/// Virtual environments created with the base interpreter's `venv` module.
+struct VenvProvider;
+
+impl EnvProvider for VenvProvider {
+    fn create(&self, env_dir: &Path, base_python: &Path, _version: &str) -> Result<()> {
+        run(Command::new(base_python).arg("-m").arg("venv").arg(env_dir))
+            .map_err(|stderr| creation_failed(env_dir, stderr))
+    }
+
+    fn install_requirements(&self, env_dir: &Path, module_dir: &Path, sandbox: Option<&SandboxPolicy>) -> Result<()> {
+        let python = self.interpreter(env_dir);
+        pip(&python, "pip", &["install", "--upgrade", "pip"], module_dir, sandbox)?;
+        pip_install_module(&python, module_dir, sandbox)
+    }
+}
+
+/// Virtual environments created and filled by uv.
+struct UvProvider;
+
+impl EnvProvider for UvProvider {
+    fn create(&self, env_dir: &Path, base_python: &Path, _version: &str) -> Result<()> {
+        // Seeding pip keeps `python -m pip` in manifest setup steps working
+        run(Command::new("uv").arg("venv").arg("--seed").arg("--python").arg(base_python).arg(env_dir))
+            .map_err(|stderr| creation_failed(env_dir, stderr))
+    }
+
+    fn install_requirements(&self, env_dir: &Path, module_dir: &Path, sandbox: Option<&SandboxPolicy>) -> Result<()> {
+        let python = std::path::absolute(self.interpreter(env_dir))?;
+        let uv_pip = |target: &Path, args: &[&str]| {
+            let mut command = Command::new("uv");
+            command.arg("pip").arg("install").arg("--python").arg(&python).args(args).arg(target);
+            run(confine(&mut command, module_dir, sandbox)?)
+                .map_err(|stderr| Error::PipInstallFailed { target: target.display().to_string(), stderr })
+        };
+        if has_package(module_dir) {
+            println!("Installing {:?} in editable mode", module_dir);
+            uv_pip(module_dir, &["-e"])?;
+        }
+        let requirements = module_dir.join("requirements.txt");
+        if requirements.exists() {
+            println!("Installing requirements from {:?}", requirements);
+            uv_pip(&requirements, &["-r"])?;
+        }
+        Ok(())
+    }
+}
+
+/// Conda environments in the module's `.<name>` directory, managed by conda or mamba.
+struct CondaProvider {
+    executable: &'static str,
+}
+
+impl EnvProvider for CondaProvider {
+    fn create(&self, env_dir: &Path, _base_python: &Path, version: &str) -> Result<()> {
+        // Conda installs its own interpreter, of the same minor version as the base one
+        let version = version.trim_start_matches("Python").trim();
+        let minor = version.splitn(3, '.').take(2).collect::<Vec<_>>().join(".");
+        run(Command::new(self.executable)
+            .args(["create", "--yes", "--quiet", "--prefix"])
+            .arg(env_dir)
+            .arg(format!("python={}", minor)))
+        .map_err(|stderr| creation_failed(env_dir, stderr))
+    }
+
+    fn install_requirements(&self, env_dir: &Path, module_dir: &Path, sandbox: Option<&SandboxPolicy>) -> Result<()> {
+        let environment_file = module_dir.join("environment.yml");
+        if environment_file.exists() {
+            println!("Installing conda packages from {:?}", environment_file);
+            let mut command = Command::new(self.executable);
+            command
+                .args(["env", "update", "--quiet", "--prefix"])
+                .arg(std::path::absolute(env_dir)?)
+                .arg("--file")
+                .arg(&environment_file);
+            run(confine(&mut command, module_dir, sandbox)?)
+                .map_err(|stderr| Error::PipInstallFailed { target: environment_file.display().to_string(), stderr })?;
+        }
+        pip_install_module(&self.interpreter(env_dir), module_dir, sandbox)
+    }
+
+    fn destroy(&self, env_dir: &Path) -> Result<()> {
+        // Removing the prefix through conda also drops it from conda's list of environments
+        if let Err(stderr) = run(Command::new(self.executable).args(["env", "remove", "--yes", "--prefix"]).arg(env_dir)) {
+            eprintln!("Warning: {} could not remove {:?}: {}", self.executable, env_dir, stderr.trim());
+        }
+        if env_dir.exists() {
+            fs::remove_dir_all(env_dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// The base interpreter itself, for hosts where the modules' packages are managed outside
+/// the validator, e.g. in a container image.
+///
+/// The environment directory only holds links to the interpreter (and the marker file),
+/// so `bin/python3` works as for the other providers. Requirements are installed into the
+/// interpreter's own site-packages.
+struct SystemProvider;
+
+impl EnvProvider for SystemProvider {
+    fn create(&self, env_dir: &Path, base_python: &Path, _version: &str) -> Result<()> {
+        let python = find_executable(base_python)
+            .ok_or_else(|| creation_failed(env_dir, format!("{} not found", base_python.display())))?;
+        let link = self.interpreter(env_dir);
+        if let Some(bin_dir) = link.parent() {
+            fs::create_dir_all(bin_dir)?;
+            link_interpreter(&python, &bin_dir.join("python"))?;
+        }
+        link_interpreter(&python, &link)
+    }
+
+    fn install_requirements(&self, env_dir: &Path, module_dir: &Path, sandbox: Option<&SandboxPolicy>) -> Result<()> {
+        pip_install_module(&self.interpreter(env_dir), module_dir, sandbox)
+    }
+}
+
+#[cfg(unix)]
+fn link_interpreter(python: &Path, link: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(python, link)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn link_interpreter(python: &Path, link: &Path) -> Result<()> {
+    fs::copy(python, link)?;
+    Ok(())
+}
+
+/// Resolves a program name to its path on PATH; paths with a directory are returned as they are.
+///
+/// # Arguments
+///
+/// * `program` - The program name or path.
+pub fn find_executable(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return program.is_file().then(|| program.to_path_buf());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Returns whether the module can be installed as a package.
+fn has_package(module_dir: &Path) -> bool {
+    module_dir.join("pyproject.toml").exists() || module_dir.join("setup.py").exists()
+}
+
+/// Installs the module package in editable mode and its `requirements.txt` with pip, when present.
+fn pip_install_module(python: &Path, module_dir: &Path, sandbox: Option<&SandboxPolicy>) -> Result<()> {
+    if has_package(module_dir) {
+        println!("Installing {:?} in editable mode", module_dir);
+        let target = module_dir.to_string_lossy();
+        pip(python, &target, &["install", "-e", &target], module_dir, sandbox)?;
+    }
+    let requirements = module_dir.join("requirements.txt");
+    if requirements.exists() {
+        println!("Installing requirements from {:?}", requirements);
+        let target = requirements.to_string_lossy();
+        pip(python, &target, &["install", "-r", &target], module_dir, sandbox)?;
+    }
+    Ok(())
+}
+
+/// Runs pip with an interpreter, under the module's sandbox policy if it has one.
+fn pip(python: &Path, target: &str, args: &[&str], module_dir: &Path, sandbox: Option<&SandboxPolicy>) -> Result<()> {
+    let mut command = Command::new(std::path::absolute(python)?);
+    command.arg("-m").arg("pip").args(args);
+    run(confine(&mut command, module_dir, sandbox)?)
+        .map_err(|stderr| Error::PipInstallFailed { target: target.to_string(), stderr })
+}
+
+/// Applies the module's sandbox policy to an install command, if it has one.
+///
+/// The policy runs the command from the module directory, so its paths must be absolute.
+fn confine<'a>(command: &'a mut Command, module_dir: &Path, sandbox: Option<&SandboxPolicy>) -> Result<&'a mut Command> {
+    if let Some(policy) = sandbox {
+        policy.apply(command, module_dir)?;
+    }
+    Ok(command)
+}
+
+fn creation_failed(env_dir: &Path, stderr: String) -> Error {
+    Error::VenvCreationFailed { path: env_dir.to_path_buf(), stderr }
+}
+
+/// Runs a provider command, returning its stderr when it fails.
+fn run(command: &mut Command) -> std::result::Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().map_err(|e| format!("cannot run {}: {}", program, e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
@@ -0,0 +1,241 @@
+//! Error module for the Module Validator application.
+//!
+//! Every fallible function in the library returns [`Result`], whose [`Error`] says
+//! what went wrong in a way callers can act on: the CLI maps it to an exit code and
+//! the API server to an HTTP status.
+
+use crate::inference::process::exit_signal;
+use crate::redact;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::Duration;
+use thiserror::Error;
+
+/// The number of trailing stderr lines kept in [`Error::ProcessFailed`].
+const STDERR_TAIL_LINES: usize = 20;
+
+/// A specialized Result type for Module Validator operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The errors returned by the Module Validator library.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The named module is not installed or not known to any catalog.
+    #[error("Module {0} is not installed")]
+    ModuleNotFound(String),
+
+    /// A script the module needs could not be found.
+    #[error("{role} script not found: {}", path.display())]
+    ScriptNotFound { role: String, path: PathBuf },
+
+    /// The module's Python virtual environment could not be created.
+    #[error("Failed to create virtual environment {}: {stderr}", path.display())]
+    VenvCreationFailed { path: PathBuf, stderr: String },
+
+    /// No Python interpreter on this machine satisfies the module's version constraint.
+    #[error("No Python interpreter matches {requirement}; found: {}", describe_found(found))]
+    InterpreterNotFound { requirement: String, found: Vec<String> },
+
+    /// Installing Python packages into a virtual environment failed.
+    #[error("Failed to install {target}: {stderr}")]
+    PipInstallFailed { target: String, stderr: String },
+
+    /// An external process exited unsuccessfully.
+    #[error("{command} {}{}", describe_exit(*exit_code, *signal), format_stderr_tail(stderr_tail))]
+    ProcessFailed {
+        command: String,
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+        stderr_tail: String,
+    },
+
+    /// An external process ran longer than its timeout and was stopped.
+    #[error("{command} timed out after {}s and was stopped", timeout.as_secs_f64())]
+    Timeout { command: String, timeout: Duration },
+
+    /// An external process was stopped because the caller cancelled it.
+    #[error("{command} was cancelled")]
+    Cancelled { command: String },
+
+    /// Python code running in the embedded interpreter raised an exception.
+    #[error("Python error in module {module}: {message}")]
+    PythonFailed { module: String, message: String },
+
+    /// The module registry database could not be reached or queried.
+    #[error("Module registry unavailable: {0}")]
+    RegistryUnavailable(#[from] sqlx::Error),
+
+    /// A configuration, manifest or catalog file is missing required values or cannot be parsed.
+    #[error("Invalid configuration: {0}")]
+    ConfigInvalid(String),
+
+    /// The module has local changes that an operation would overwrite.
+    #[error("Module {name} has local changes; commit, stash or discard them first:\n{changes}")]
+    LocalChanges { name: String, changes: String },
+
+    /// Values needed to continue were not given and prompting is disabled.
+    #[error("Missing required values: {} (pass them with --set KEY=VALUE or --answers FILE)", .0.join(", "))]
+    MissingValues(Vec<String>),
+
+    /// The caller passed an argument that cannot be used.
+    #[error("{0}")]
+    InvalidInput(String),
+
+    /// A request to a remote registrar failed.
+    #[error("Request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// Reading or writing a file failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl Error {
+    /// Builds a [`Error::ProcessFailed`] from an exit status and the captured stderr,
+    /// keeping only its last lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - A short description of the command that was run.
+    /// * `status` - The exit status of the process.
+    /// * `stderr` - Everything the process wrote to stderr.
+    pub fn process_failed(command: impl Into<String>, status: ExitStatus, stderr: &str) -> Self {
+        let lines: Vec<&str> = stderr.lines().collect();
+        let start = lines.len().saturating_sub(STDERR_TAIL_LINES);
+        Error::ProcessFailed {
+            command: command.into(),
+            exit_code: status.code(),
+            signal: exit_signal(&status),
+            stderr_tail: lines[start..].join("\n"),
+        }
+    }
+
+    /// Returns a short, stable name for the error kind, used in API responses.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::ModuleNotFound(_) => "module_not_found",
+            Error::ScriptNotFound { .. } => "script_not_found",
+            Error::VenvCreationFailed { .. } => "venv_creation_failed",
+            Error::InterpreterNotFound { .. } => "interpreter_not_found",
+            Error::PipInstallFailed { .. } => "pip_install_failed",
+            Error::ProcessFailed { .. } => "process_failed",
+            Error::PythonFailed { .. } => "python_failed",
+            Error::Timeout { .. } => "timeout",
+            Error::Cancelled { .. } => "cancelled",
+            Error::RegistryUnavailable(_) => "registry_unavailable",
+            Error::ConfigInvalid(_) => "config_invalid",
+            Error::LocalChanges { .. } => "local_changes",
+            Error::MissingValues(_) => "missing_values",
+            Error::InvalidInput(_) => "invalid_input",
+            Error::Http(_) => "http",
+            Error::Io(_) => "io",
+        }
+    }
+
+    /// Returns the process exit code the CLI uses for this error.
+    ///
+    /// | Code | Error |
+    /// |------|-------|
+    /// | 1 | I/O and HTTP failures |
+    /// | 2 | Invalid input or missing values |
+    /// | 3 | Module not found |
+    /// | 4 | Script not found |
+    /// | 5 | Invalid configuration |
+    /// | 6 | Registry unavailable |
+    /// | 7 | Virtual environment, interpreter or pip failure |
+    /// | 8 | External process or embedded Python failure |
+    /// | 9 | Local changes in the way |
+    /// | 10 | External process timed out |
+    /// | 11 | External process cancelled |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Http(_) | Error::Io(_) => 1,
+            Error::InvalidInput(_) | Error::MissingValues(_) => 2,
+            Error::ModuleNotFound(_) => 3,
+            Error::ScriptNotFound { .. } => 4,
+            Error::ConfigInvalid(_) => 5,
+            Error::RegistryUnavailable(_) => 6,
+            Error::VenvCreationFailed { .. } | Error::InterpreterNotFound { .. } | Error::PipInstallFailed { .. } => 7,
+            Error::ProcessFailed { .. } | Error::PythonFailed { .. } => 8,
+            Error::LocalChanges { .. } => 9,
+            Error::Timeout { .. } => 10,
+            Error::Cancelled { .. } => 11,
+        }
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(e: url::ParseError) -> Self {
+        Error::InvalidInput(format!("Invalid URL: {}", e))
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for Error {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        Error::RegistryUnavailable(sqlx::Error::Migrate(Box::new(e)))
+    }
+}
+
+fn describe_exit(exit_code: Option<i32>, signal: Option<i32>) -> String {
+    match (exit_code, signal) {
+        (Some(code), _) => format!("failed with exit code {}", code),
+        (None, Some(signal)) => format!("was killed by signal {}", signal),
+        (None, None) => "failed with exit code unknown".to_string(),
+    }
+}
+
+fn describe_found(found: &[String]) -> String {
+    if found.is_empty() {
+        "none".to_string()
+    } else {
+        found.join(", ")
+    }
+}
+
+fn format_stderr_tail(stderr_tail: &str) -> String {
+    if stderr_tail.trim().is_empty() {
+        String::new()
+    } else {
+        format!(":\n{}", stderr_tail)
+    }
+}
+
+/// The JSON body returned by the API for a failed request.
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+    error: &'a str,
+    message: String,
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::ModuleNotFound(_) | Error::ScriptNotFound { .. } => StatusCode::NOT_FOUND,
+            Error::InvalidInput(_) | Error::MissingValues(_) => StatusCode::BAD_REQUEST,
+            Error::LocalChanges { .. } => StatusCode::CONFLICT,
+            Error::ConfigInvalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::RegistryUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Http(_) => StatusCode::BAD_GATEWAY,
+            Error::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            // Only seen in logs: the client that cancelled is gone (nginx's "client closed request")
+            Error::Cancelled { .. } => StatusCode::from_u16(499).unwrap(),
+            Error::VenvCreationFailed { .. }
+            | Error::InterpreterNotFound { .. }
+            | Error::PipInstallFailed { .. }
+            | Error::ProcessFailed { .. }
+            | Error::PythonFailed { .. }
+            | Error::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ErrorResponse {
+            error: self.kind(),
+            message: redact::text(&self.to_string()),
+        })
+    }
+}
@@ -0,0 +1,285 @@
+//! Embedded Python runtime for the Module Validator application.
+//!
+//! [`PythonExecutor`](crate::inference::python_executor::PythonExecutor) starts a new
+//! interpreter for every call, which re-imports the module and reloads its model each
+//! time. The [`EmbeddedExecutor`] instead runs the module inside this process: it adds
+//! the module's virtual environment to `sys.path`, imports the module once and then
+//! calls its `process` function for every request, following the contract of
+//! `src/modules/module_wrapper.py`:
+//!
+//! * `load()` - optional, called once after the import
+//! * `process(*args, **kwargs)` - called for every request
+//! * `unload()` - optional, called when the executor is dropped
+//!
+//! Each executor owns a dedicated thread, and every call into Python goes through it
+//! while holding the GIL, so callers on any thread are serialized. Arguments and return
+//! values cross the boundary as JSON.
+//!
+//! There is a single interpreter per process, so `sys.path` and `os.environ` are shared
+//! by every embedded module. Modules whose virtual environments need conflicting
+//! packages, or a different Python version than the one this binary links against,
+//! should run out of process instead.
+
+use crate::error::{Error, Result};
+use crate::manifest::ModuleManifest;
+use crate::dotenv;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyTuple};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+/// A call handed to the interpreter thread, with the channel its result is sent back on.
+struct Call {
+    args: Value,
+    reply: mpsc::Sender<Result<Value>>,
+}
+
+/// The files an embedded module is loaded from.
+#[derive(Debug, Clone)]
+struct ModulePaths {
+    name: String,
+    module_dir: PathBuf,
+    script: PathBuf,
+    venv_dir: PathBuf,
+    /// The module's environment, whose layers over this process's are set in `os.environ`.
+    env: dotenv::ResolvedEnv,
+}
+
+/// Runs an inference module inside this process and keeps it loaded between calls.
+pub struct EmbeddedExecutor {
+    name: String,
+    calls: Option<mpsc::Sender<Call>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EmbeddedExecutor {
+    /// Loads an installed inference module into the embedded interpreter.
+    ///
+    /// # Arguments
+    ///
+    /// * `module_name` - The name of the inference module.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the EmbeddedExecutor once the module is imported and loaded.
+    pub fn start(module_name: &str) -> Result<Self> {
+        let root_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into()));
+        Self::start_in(&root_dir, module_name)
+    }
+
+    /// Loads an inference module found under the given root directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_dir` - The directory containing `modules/<name>` and the `.<name>` virtual environment.
+    /// * `module_name` - The name of the inference module.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the EmbeddedExecutor once the module is imported and loaded.
+    pub fn start_in(root_dir: &Path, module_name: &str) -> Result<Self> {
+        let module_dir = root_dir.join("modules").join(module_name);
+        if !module_dir.is_dir() {
+            return Err(Error::ModuleNotFound(module_name.to_string()));
+        }
+        let script = ModuleManifest::load(&module_dir)?
+            .and_then(|manifest| manifest.entry_point(&module_dir, "inference"))
+            .unwrap_or_else(|| module_dir.join(format!("{}.py", module_name)));
+        if !script.is_file() {
+            return Err(Error::ScriptNotFound {
+                role: "Inference".to_string(),
+                path: script,
+            });
+        }
+        let env = dotenv::resolve(root_dir, &module_dir, None, &Default::default())?;
+        let paths = ModulePaths {
+            name: module_name.to_string(),
+            env,
+            module_dir,
+            script,
+            venv_dir: root_dir.join(format!(".{}", module_name)),
+        };
+
+        let (calls, receiver) = mpsc::channel();
+        let (ready, loaded) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name(format!("python-{}", module_name))
+            .spawn(move || serve(paths, ready, receiver))?;
+        let executor = EmbeddedExecutor {
+            name: module_name.to_string(),
+            calls: Some(calls),
+            thread: Some(thread),
+        };
+        loaded
+            .recv()
+            .unwrap_or_else(|_| Err(executor.stopped()))?;
+        Ok(executor)
+    }
+
+    /// Returns the name of the loaded module.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Calls the module's `process` function.
+    ///
+    /// A JSON object is passed as keyword arguments, an array as positional arguments
+    /// and any other value as the single argument.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The arguments for `process`.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the return value of `process` as JSON, or an error if it raised.
+    pub fn process(&self, args: Value) -> Result<Value> {
+        let (reply, result) = mpsc::channel();
+        self.calls
+            .as_ref()
+            .ok_or_else(|| self.stopped())?
+            .send(Call { args, reply })
+            .map_err(|_| self.stopped())?;
+        result.recv().unwrap_or_else(|_| Err(self.stopped()))
+    }
+
+    fn stopped(&self) -> Error {
+        Error::PythonFailed {
+            module: self.name.clone(),
+            message: "the interpreter thread has stopped".to_string(),
+        }
+    }
+}
+
+impl Drop for EmbeddedExecutor {
+    fn drop(&mut self) {
+        // Closing the channel ends the interpreter thread's loop, which then unloads the module
+        self.calls.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The body of the interpreter thread: loads the module, serves calls until the channel closes, then unloads it.
+fn serve(paths: ModulePaths, ready: mpsc::Sender<Result<()>>, calls: mpsc::Receiver<Call>) {
+    let module = match Python::with_gil(|py| load_module(py, &paths)) {
+        Ok(module) => module,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    let _ = ready.send(Ok(()));
+
+    for call in calls {
+        let result = Python::with_gil(|py| call_process(py, &paths.name, module.as_ref(py), &call.args));
+        let _ = call.reply.send(result);
+    }
+
+    Python::with_gil(|py| {
+        let module = module.as_ref(py);
+        let unloaded = match module.hasattr("unload") {
+            Ok(true) => module.call_method0("unload").map(|_| ()),
+            Ok(false) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = unloaded {
+            eprintln!("Warning: {}", python_error(py, &paths.name, e));
+        }
+    });
+}
+
+/// Prepares the interpreter for the module, imports it and calls its `load` function.
+fn load_module(py: Python<'_>, paths: &ModulePaths) -> Result<Py<PyModule>> {
+    let site_packages = site_packages(py, &paths.venv_dir)?;
+    let loaded = (|| -> PyResult<Py<PyModule>> {
+        py.import("site")?
+            .call_method1("addsitedir", (site_packages.to_string_lossy().as_ref(),))?;
+        let sys_path: &PyList = py.import("sys")?.getattr("path")?.downcast()?;
+        sys_path.insert(0, paths.module_dir.to_string_lossy().as_ref())?;
+
+        let environ = py.import("os")?.getattr("environ")?;
+        for (key, value) in paths.env.layered() {
+            environ.set_item(key, value)?;
+        }
+
+        let code = fs::read_to_string(&paths.script)?;
+        let module = PyModule::from_code(py, &code, &paths.script.to_string_lossy(), &paths.name)?;
+        if module.hasattr("load")? {
+            module.call_method0("load")?;
+        }
+        Ok(module.into())
+    })()
+    .map_err(|e| python_error(py, &paths.name, e))?;
+
+    if !loaded.as_ref(py).hasattr("process").unwrap_or(false) {
+        return Err(Error::PythonFailed {
+            module: paths.name.clone(),
+            message: format!("{} does not define a process function", paths.script.display()),
+        });
+    }
+    Ok(loaded)
+}
+
+/// Finds the site-packages directory of a virtual environment built for the embedded Python version.
+fn site_packages(py: Python<'_>, venv_dir: &Path) -> Result<PathBuf> {
+    let version = py.version_info();
+    let expected = format!("python{}.{}", version.major, version.minor);
+    let lib_dir = venv_dir.join("lib");
+    let found: Vec<String> = fs::read_dir(&lib_dir)
+        .map_err(|_| Error::ConfigInvalid(format!("Virtual environment {} not found", venv_dir.display())))?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.starts_with("python"))
+        .collect();
+    if found.contains(&expected) {
+        Ok(lib_dir.join(expected).join("site-packages"))
+    } else {
+        Err(Error::ConfigInvalid(format!(
+            "Virtual environment {} was built for {} but the embedded interpreter is {}",
+            venv_dir.display(),
+            found.join(", "),
+            expected
+        )))
+    }
+}
+
+/// Calls `process` with the JSON arguments and converts its return value back to JSON.
+fn call_process(py: Python<'_>, name: &str, module: &PyModule, args: &Value) -> Result<Value> {
+    let returned = (|| -> PyResult<String> {
+        let json = py.import("json")?;
+        let args_json = serde_json::to_string(args).map_err(std::io::Error::other)?;
+        let decoded = json.call_method1("loads", (args_json,))?;
+        let process = module.getattr("process")?;
+        let result = match args {
+            Value::Object(_) => process.call((), Some(decoded.downcast::<PyDict>()?))?,
+            Value::Array(_) => process.call1(PyTuple::new(py, decoded.downcast::<PyList>()?))?,
+            _ => process.call1((decoded,))?,
+        };
+        // Values JSON cannot represent are passed back as their str()
+        let options = PyDict::new(py);
+        options.set_item("default", py.import("builtins")?.getattr("str")?)?;
+        json.call_method("dumps", (result,), Some(options))?.extract()
+    })()
+    .map_err(|e| python_error(py, name, e))?;
+    serde_json::from_str(&returned).map_err(|e| Error::PythonFailed {
+        module: name.to_string(),
+        message: format!("process returned invalid JSON: {}", e),
+    })
+}
+
+/// Formats a Python exception, with its traceback when there is one.
+fn python_error(py: Python<'_>, name: &str, error: PyErr) -> Error {
+    let traceback = error
+        .traceback(py)
+        .and_then(|traceback| traceback.format().ok())
+        .unwrap_or_default();
+    Error::PythonFailed {
+        module: name.to_string(),
+        message: format!("{}{}", traceback, error),
+    }
+}
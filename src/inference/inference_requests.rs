@@ -17,12 +17,12 @@ impl DynamicInferenceRequest {
     ///
     /// # Arguments
     ///
-    /// * `data` - The initial data string (currently unused).
+    /// * `_data` - The initial data string (currently unused).
     ///
     /// # Returns
     ///
     /// A new DynamicInferenceRequest instance.
-    pub fn new(data: String) -> Self {
+    pub fn new(_data: String) -> Self {
         Self {
             data: HashMap::new(),
         }
@@ -40,19 +40,19 @@ impl DynamicInferenceRequest {
     /// A Result indicating success or failure of adding the parameter.
     pub fn add_param<T: Serialize>(&mut self, key: &str, value: T) -> Result<(), serde_json::Error> {
         let json_value = serde_json::to_value(value)?;
-        self.params.insert(key.to_string(), json_value);
+        self.data.insert(key.to_string(), json_value);
         Ok(())
     }
 }
 
-/// Represents an inference request.
+/// Represents an inference request. Its data is passed to the module's `process` function as keyword arguments.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InferenceRequest {
     pub data: HashMap<String, serde_json::Value>,
 }
 
-/// Represents an inference response.
+/// Represents an inference response: the value returned by the module's `process` function.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InferenceResponse {
-    pub data: String,
+    pub data: serde_json::Value,
 }
@@ -1,2 +1,6 @@
+pub mod embedded;
+pub mod inference_requests;
+pub mod process;
 pub mod python_executor;
-pub mod translation;
\ No newline at end of file
+pub mod translation;
+pub mod worker;
@@ -0,0 +1,783 @@
+//! Process control module for the Module Validator application.
+//!
+//! Module scripts may hang, and may start processes of their own. The helpers here
+//! run a child in its own process group, wait for it with an optional deadline and a
+//! [`CancelHandle`], and stop the whole group when needed: SIGTERM first, then SIGKILL
+//! if the group is still alive after a grace period. A [`ProcessGroup`] that is dropped
+//! before its child was reaped kills the group, so an abandoned call never leaves
+//! processes behind.
+//!
+//! [`RunningCommand`] is the async handle to such a child: its stdout and stderr are read
+//! on the tokio runtime as they are produced and can be followed as a stream of tagged
+//! lines, its stdin can be written to, and waiting for it yields an [`ExecutionResult`]
+//! describing the run: its output, how it ended, how long it took and how much memory
+//! it used.
+
+use crate::error::{Error, Result};
+use crate::logs::RotatingLog;
+use crate::redact;
+use serde::{Serialize, Serializer};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tokio_stream::Stream;
+
+/// How long a process group gets to exit after SIGTERM before it is sent SIGKILL.
+pub const TERMINATE_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// How often the peak memory of a running child is sampled.
+const MEMORY_SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long the output of a command is still read after its process group was killed.
+const OUTPUT_CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The number of interleaved output lines kept in [`ExecutionResult::output_tail`].
+pub const OUTPUT_TAIL_LINES: usize = 50;
+
+/// The most of each of stdout and stderr kept in an [`ExecutionResult`], in bytes. Longer
+/// output loses its start.
+pub const OUTPUT_CAPTURE_BYTES: usize = 1024 * 1024;
+
+/// The longest output line, in bytes. Longer lines are split.
+const OUTPUT_LINE_BYTES: u64 = 64 * 1024;
+
+/// The number of output lines [`OutputLines`] holds for a follower that is behind.
+const OUTPUT_LINES_BUFFER: usize = 1024;
+
+/// The number of trailing stderr lines put in the error of a failed run.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// A shared flag that asks a running command to stop.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<CancelState>);
+
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancelHandle {
+    /// Creates a handle that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks every command watching this handle to stop.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    /// Returns whether the handle was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the handle is cancelled.
+    pub async fn cancelled(&self) {
+        let notified = self.0.notify.notified();
+        tokio::pin!(notified);
+        // Registered before the flag is checked, so a cancel in between is not missed
+        notified.as_mut().enable();
+        if !self.is_cancelled() {
+            notified.await;
+        }
+    }
+}
+
+/// Cancels its handle when dropped, e.g. when an API request future is dropped because the client disconnected.
+#[derive(Debug)]
+pub struct CancelOnDrop(pub CancelHandle);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// How a waited-for child ended.
+#[derive(Debug)]
+pub enum WaitOutcome {
+    /// The child exited on its own.
+    Exited(ExitStatus),
+    /// The deadline passed and the process group was stopped.
+    TimedOut,
+    /// The cancel handle was triggered and the process group was stopped.
+    Cancelled,
+}
+
+/// A child process running in its own process group, killed with its group when dropped unless reaped.
+#[derive(Debug)]
+pub struct ProcessGroup {
+    child: Child,
+    /// The process id of the child, which is also the process group id.
+    pid: u32,
+    /// The exit status, once the child has been reaped.
+    status: Option<ExitStatus>,
+    peak_memory_kb: Option<u64>,
+}
+
+impl ProcessGroup {
+    /// Spawns a command as the leader of a new process group.
+    ///
+    /// Must be called within a tokio runtime, which reaps the child.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to spawn, with its stdio already configured.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the ProcessGroup, or an error if the command cannot be started.
+    pub fn spawn(command: &mut Command) -> io::Result<Self> {
+        #[cfg(unix)]
+        command.process_group(0);
+        let child = command.spawn()?;
+        let pid = child.id().ok_or_else(|| io::Error::other("the child process exited before it was started"))?;
+        Ok(ProcessGroup {
+            child,
+            pid,
+            status: None,
+            peak_memory_kb: None,
+        })
+    }
+
+    /// Returns the process id of the child, which is also its process group id.
+    pub fn id(&self) -> u32 {
+        self.pid
+    }
+
+    /// Returns the peak resident memory of the child, as last sampled while it ran.
+    pub fn peak_memory_kb(&self) -> Option<u64> {
+        self.peak_memory_kb
+    }
+
+    /// Returns the child process, e.g. to take its stdio handles.
+    pub fn child(&mut self) -> &mut Child {
+        &mut self.child
+    }
+
+    /// Waits for the child to exit, stopping the process group on timeout or cancellation.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long the child may run, if limited.
+    /// * `cancel` - A handle that stops the child when cancelled.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing how the child ended, or an error if waiting fails.
+    pub async fn wait(&mut self, timeout: Option<Duration>, cancel: Option<&CancelHandle>) -> io::Result<WaitOutcome> {
+        let outcome = tokio::select! {
+            status = self.reap() => WaitOutcome::Exited(status?),
+            _ = sleep_for(timeout) => WaitOutcome::TimedOut,
+            _ = cancelled(cancel) => WaitOutcome::Cancelled,
+        };
+        if !matches!(outcome, WaitOutcome::Exited(_)) {
+            self.terminate(TERMINATE_GRACE_PERIOD).await?;
+        }
+        Ok(outcome)
+    }
+
+    /// Sends SIGTERM to the process group, then SIGKILL if the child has not exited after the grace period.
+    ///
+    /// # Arguments
+    ///
+    /// * `grace` - How long to wait between SIGTERM and SIGKILL.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the exit status of the child.
+    pub async fn terminate(&mut self, grace: Duration) -> io::Result<ExitStatus> {
+        if self.status.is_none() {
+            self.signal_group(Signal::Terminate);
+            let _ = tokio::time::timeout(grace, self.reap()).await;
+        }
+        self.kill().await
+    }
+
+    /// Sends SIGKILL to the process group and reaps the child.
+    async fn kill(&mut self) -> io::Result<ExitStatus> {
+        // Grandchildren that ignored SIGTERM go down with the group
+        self.signal_group(Signal::Kill);
+        self.reap().await
+    }
+
+    /// Waits for the child to exit and reaps it, sampling its memory while it runs.
+    ///
+    /// Cancel safe: dropping the future before the child exited leaves it running.
+    async fn reap(&mut self) -> io::Result<ExitStatus> {
+        if let Some(status) = self.status {
+            return Ok(status);
+        }
+        let mut samples = tokio::time::interval(MEMORY_SAMPLE_INTERVAL);
+        samples.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let status = loop {
+            tokio::select! {
+                status = self.child.wait() => break status?,
+                _ = samples.tick() => {
+                    if let Some(peak) = peak_memory_kb(self.pid) {
+                        self.peak_memory_kb = Some(peak.max(self.peak_memory_kb.unwrap_or_default()));
+                    }
+                }
+            }
+        };
+        self.status = Some(status);
+        Ok(status)
+    }
+
+    #[cfg(unix)]
+    fn signal_group(&mut self, signal: Signal) {
+        let signal = match signal {
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Kill => libc::SIGKILL,
+        };
+        // The child is the group leader, so its pid is the process group id
+        unsafe {
+            libc::killpg(self.pid as libc::pid_t, signal);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn signal_group(&mut self, _signal: Signal) {
+        let _ = self.child.start_kill();
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        // The runtime reaps the killed child in the background
+        if self.status.is_none() {
+            self.signal_group(Signal::Kill);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Signal {
+    Terminate,
+    Kill,
+}
+
+/// Resolves after the timeout, or never if there is none.
+async fn sleep_for(timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => tokio::time::sleep(timeout).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Resolves once the handle is cancelled, or never if there is none.
+async fn cancelled(cancel: Option<&CancelHandle>) {
+    match cancel {
+        Some(cancel) => cancel.cancelled().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Reads the peak resident memory of a running process, in kilobytes.
+#[cfg(target_os = "linux")]
+fn peak_memory_kb(pid: u32) -> Option<u64> {
+    // A zombie has no memory left and reports no VmHWM line
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find_map(|line| line.strip_prefix("VmHWM:"))?;
+    line.trim().trim_end_matches("kB").trim().parse().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peak_memory_kb(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// The stream an output line was written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    /// Returns the name of the stream, as it appears in logs and API responses.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+/// A line of output, tagged with the stream it was written to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    pub line: String,
+}
+
+/// The output lines of a [`RunningCommand`], in the order they were read.
+///
+/// The stream ends once the child and everything it started have closed stdout and stderr.
+/// It holds up to 1024 lines the follower has not read yet; lines read while it is full are
+/// skipped rather than slowing the command down.
+pub struct OutputLines(mpsc::Receiver<OutputLine>);
+
+impl Stream for OutputLines {
+    type Item = OutputLine;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<OutputLine>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+/// Reads the stdout and stderr of a child on the tokio runtime.
+struct OutputCapture {
+    readers: [JoinHandle<()>; 2],
+    captured: Arc<Mutex<CapturedOutput>>,
+}
+
+/// The output read so far from both streams of a child.
+#[derive(Default)]
+struct CapturedOutput {
+    stdout: String,
+    stderr: String,
+    tail: VecDeque<OutputLine>,
+    /// Whether the start of stdout or stderr was dropped to stay within [`OUTPUT_CAPTURE_BYTES`].
+    truncated: bool,
+}
+
+impl CapturedOutput {
+    /// Records a line read from one of the streams.
+    fn push(&mut self, line: &OutputLine) {
+        let output = match line.stream {
+            OutputStream::Stdout => &mut self.stdout,
+            OutputStream::Stderr => &mut self.stderr,
+        };
+        output.push_str(&line.line);
+        output.push('\n');
+        if output.len() > OUTPUT_CAPTURE_BYTES {
+            // Dropping a quarter at once, on a line boundary, keeps this from moving the whole buffer for every line
+            let cut = output.len() - OUTPUT_CAPTURE_BYTES * 3 / 4;
+            let cut = output[cut..].find('\n').map_or(output.len(), |end| cut + end + 1);
+            output.drain(..cut);
+            self.truncated = true;
+        }
+        if self.tail.len() == OUTPUT_TAIL_LINES {
+            self.tail.pop_front();
+        }
+        self.tail.push_back(line.clone());
+    }
+}
+
+impl OutputCapture {
+    /// Starts reading the piped stdout and stderr of a child, sending each line as it arrives
+    /// and appending it to the log, if any.
+    fn start(child: &mut Child, lines: mpsc::Sender<OutputLine>, log: Option<RotatingLog>) -> Self {
+        let captured = Arc::new(Mutex::new(CapturedOutput::default()));
+        let log = log.map(|log| Arc::new(Mutex::new(log)));
+        let stdout = child.stdout.take().expect("Failed to capture stdout");
+        let stderr = child.stderr.take().expect("Failed to capture stderr");
+        OutputCapture {
+            readers: [
+                read_lines(stdout, OutputStream::Stdout, captured.clone(), lines.clone(), log.clone()),
+                read_lines(stderr, OutputStream::Stderr, captured.clone(), lines, log),
+            ],
+            captured,
+        }
+    }
+
+    /// Waits until both streams are closed, i.e. until every process that inherited them has exited.
+    async fn closed(&mut self) {
+        for reader in &mut self.readers {
+            if !reader.is_finished() {
+                let _ = reader.await;
+            }
+        }
+    }
+
+    /// Waits until both streams are closed or the timeout passed.
+    ///
+    /// # Returns
+    ///
+    /// Whether both streams were closed.
+    async fn closed_within(&mut self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, self.closed()).await.is_ok()
+    }
+
+    /// Stops reading and returns what was read.
+    ///
+    /// # Returns
+    ///
+    /// The captured output.
+    fn finish(self) -> CapturedOutput {
+        for reader in &self.readers {
+            reader.abort();
+        }
+        std::mem::take(&mut *self.captured.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+fn read_lines(
+    stream: impl AsyncRead + Unpin + Send + 'static,
+    tag: OutputStream,
+    captured: Arc<Mutex<CapturedOutput>>,
+    lines: mpsc::Sender<OutputLine>,
+    log: Option<Arc<Mutex<RotatingLog>>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(stream);
+        let mut buffer = Vec::new();
+        // Lines that are not valid UTF-8 are read lossily, so a stray byte never stops the reader
+        while matches!((&mut reader).take(OUTPUT_LINE_BYTES).read_until(b'\n', &mut buffer).await, Ok(read) if read > 0) {
+            let text = String::from_utf8_lossy(&buffer);
+            let line = text.strip_suffix('\n').unwrap_or(&text);
+            let line = line.strip_suffix('\r').unwrap_or(line).to_string();
+            buffer.clear();
+
+            if let Some(log) = &log {
+                // A full disk must not stop the command
+                let _ = log.lock().unwrap_or_else(|e| e.into_inner()).write_line(tag.as_str(), &line);
+            }
+            let line = OutputLine { stream: tag, line };
+            captured.lock().unwrap_or_else(|e| e.into_inner()).push(&line);
+            // Nobody may be following the output, or the follower may be behind
+            let _ = lines.try_send(line);
+        }
+    })
+}
+
+/// An async handle to a command running in its own process group.
+///
+/// Dropping the handle before the command finished kills its process group.
+pub struct RunningCommand {
+    command: String,
+    process: ProcessGroup,
+    stdin: Option<ChildStdin>,
+    output: OutputCapture,
+    lines: Option<OutputLines>,
+    started: Instant,
+    timeout: Option<Duration>,
+    cancel: Option<CancelHandle>,
+}
+
+impl RunningCommand {
+    /// Spawns a command with piped stdio as the leader of a new process group.
+    ///
+    /// Must be called within a tokio runtime, which reads the command's output.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - A short description of what is run, used in results and errors.
+    /// * `command` - The command to spawn.
+    /// * `timeout` - How long the command may run, if limited.
+    /// * `cancel` - A handle that stops the command when cancelled.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the RunningCommand, or an error if the command cannot be started.
+    pub fn spawn(
+        description: String,
+        command: &mut Command,
+        timeout: Option<Duration>,
+        cancel: Option<CancelHandle>,
+    ) -> io::Result<Self> {
+        Self::spawn_logged(description, command, timeout, cancel, None)
+    }
+
+    /// Spawns a command like [`RunningCommand::spawn`], appending its output lines to a log.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - A short description of what is run, used in results and errors.
+    /// * `command` - The command to spawn.
+    /// * `timeout` - How long the command may run, if limited.
+    /// * `cancel` - A handle that stops the command when cancelled.
+    /// * `log` - The log the command's stdout and stderr are written to, if any.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the RunningCommand, or an error if the command cannot be started.
+    pub fn spawn_logged(
+        description: String,
+        command: &mut Command,
+        timeout: Option<Duration>,
+        cancel: Option<CancelHandle>,
+        log: Option<RotatingLog>,
+    ) -> io::Result<Self> {
+        command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        let started = Instant::now();
+        let mut process = ProcessGroup::spawn(command)?;
+        let stdin = process.child().stdin.take();
+        let (sender, receiver) = mpsc::channel(OUTPUT_LINES_BUFFER);
+        let output = OutputCapture::start(process.child(), sender, log);
+        Ok(RunningCommand {
+            command: description,
+            process,
+            stdin,
+            output,
+            lines: Some(OutputLines(receiver)),
+            started,
+            timeout,
+            cancel,
+        })
+    }
+
+    /// Returns the process id of the command, which is also its process group id.
+    pub fn id(&self) -> u32 {
+        self.process.id()
+    }
+
+    /// Takes the stream of output lines, tagged with the stream they were written to.
+    ///
+    /// # Returns
+    ///
+    /// The stream on the first call, None afterwards.
+    pub fn output(&mut self) -> Option<OutputLines> {
+        self.lines.take()
+    }
+
+    /// Writes to the command's stdin.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The bytes to write.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success, or an error if stdin was closed or the command stopped reading.
+    pub async fn write_stdin(&mut self, data: &[u8]) -> io::Result<()> {
+        let stdin = self
+            .stdin
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "stdin is closed"))?;
+        stdin.write_all(data).await?;
+        stdin.flush().await
+    }
+
+    /// Takes the command's stdin, e.g. to write to it from another task.
+    pub fn take_stdin(&mut self) -> Option<ChildStdin> {
+        self.stdin.take()
+    }
+
+    /// Closes the command's stdin, so that it reads end of file.
+    pub fn close_stdin(&mut self) {
+        self.stdin.take();
+    }
+
+    /// Waits for the command to finish, stopping it on timeout or cancellation.
+    ///
+    /// Processes the command started that still hold its stdout or stderr after it exited
+    /// are killed with its process group once the timeout or the grace period runs out.
+    ///
+    /// Like [`std::process::Child::wait`], this closes stdin first unless it was taken.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the ExecutionResult of the run, or an error if waiting fails.
+    pub async fn wait(mut self) -> io::Result<ExecutionResult> {
+        self.close_stdin();
+        let outcome = self.process.wait(self.timeout, self.cancel.as_ref()).await?;
+        // Processes the command started may still hold its stdout and stderr open. They get
+        // what is left of the timeout, up to the grace period, before the group is killed.
+        let remaining = self.timeout.map_or(TERMINATE_GRACE_PERIOD, |timeout| timeout.saturating_sub(self.started.elapsed()));
+        if !self.output.closed_within(remaining.min(TERMINATE_GRACE_PERIOD)).await {
+            self.process.signal_group(Signal::Kill);
+            // A process that left the group keeps the streams open; its output is not waited for
+            self.output.closed_within(OUTPUT_CLOSE_TIMEOUT).await;
+        }
+        let output = self.output.finish();
+        Ok(ExecutionResult::new(self.command, &self.process, outcome, output, self.started, self.timeout))
+    }
+}
+
+/// The outcome of running a module's script.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionResult {
+    /// A short description of what was run.
+    pub command: String,
+    /// What was written to stdout, up to its last [`OUTPUT_CAPTURE_BYTES`].
+    pub stdout: String,
+    /// What was written to stderr, up to its last [`OUTPUT_CAPTURE_BYTES`].
+    pub stderr: String,
+    /// Whether the start of stdout or stderr was dropped because it was too long.
+    pub output_truncated: bool,
+    /// The exit code, if the process exited normally.
+    pub exit_code: Option<i32>,
+    /// The signal that ended the process, if any.
+    pub signal: Option<i32>,
+    /// Whether the process was stopped because it ran past its timeout.
+    pub timed_out: bool,
+    /// Whether the process was stopped because the run was cancelled.
+    pub cancelled: bool,
+    /// The timeout the process ran with, if any.
+    #[serde(serialize_with = "serialize_optional_secs")]
+    pub timeout: Option<Duration>,
+    /// How long the process ran.
+    #[serde(serialize_with = "serialize_secs")]
+    pub wall_time: Duration,
+    /// The peak resident memory of the process, in kilobytes, as last sampled while it ran.
+    pub peak_memory_kb: Option<u64>,
+    /// The last lines of stdout and stderr, interleaved in the order they were read.
+    pub output_tail: Vec<OutputLine>,
+}
+
+impl ExecutionResult {
+    /// Builds the result of a finished run.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - A short description of what was run.
+    /// * `process` - The reaped process group.
+    /// * `outcome` - How waiting for the process ended.
+    /// * `output` - The captured output.
+    /// * `started` - When the process was started.
+    /// * `timeout` - The timeout the process ran with.
+    fn new(
+        command: String,
+        process: &ProcessGroup,
+        outcome: WaitOutcome,
+        output: CapturedOutput,
+        started: Instant,
+        timeout: Option<Duration>,
+    ) -> Self {
+        let (exit_code, signal) = match &outcome {
+            WaitOutcome::Exited(status) => (status.code(), exit_signal(status)),
+            WaitOutcome::TimedOut | WaitOutcome::Cancelled => (None, None),
+        };
+        ExecutionResult {
+            command,
+            stdout: output.stdout,
+            stderr: output.stderr,
+            output_truncated: output.truncated,
+            exit_code,
+            signal,
+            timed_out: matches!(outcome, WaitOutcome::TimedOut),
+            cancelled: matches!(outcome, WaitOutcome::Cancelled),
+            timeout,
+            wall_time: started.elapsed(),
+            peak_memory_kb: process.peak_memory_kb(),
+            output_tail: output.tail.into(),
+        }
+    }
+
+    /// Returns whether the process exited with code 0.
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0) && !self.timed_out && !self.cancelled
+    }
+
+    /// Turns an unsuccessful run into the matching error.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the result if the process succeeded, or a ProcessFailed, Timeout or Cancelled error.
+    pub fn check(self) -> Result<Self> {
+        if self.success() {
+            return Ok(self);
+        }
+        Err(if self.timed_out {
+            Error::Timeout {
+                command: self.command,
+                timeout: self.timeout.unwrap_or_default(),
+            }
+        } else if self.cancelled {
+            Error::Cancelled { command: self.command }
+        } else {
+            let lines: Vec<&str> = self.stderr.lines().collect();
+            let start = lines.len().saturating_sub(STDERR_TAIL_LINES);
+            Error::ProcessFailed {
+                command: self.command.clone(),
+                exit_code: self.exit_code,
+                signal: self.signal,
+                stderr_tail: lines[start..].join("\n"),
+            }
+        })
+    }
+}
+
+impl ExecutionResult {
+    /// Returns the result with secrets masked in the command and its output, for
+    /// responses and logs.
+    pub fn redacted(self) -> Self {
+        ExecutionResult {
+            command: redact::text(&self.command),
+            stdout: redact::text(&self.stdout),
+            stderr: redact::text(&self.stderr),
+            output_tail: self.output_tail.iter().map(OutputLine::redacted).collect(),
+            ..self
+        }
+    }
+}
+
+impl OutputLine {
+    /// Returns the line with secrets masked, for output leaving the process.
+    pub fn redacted(&self) -> Self {
+        OutputLine {
+            stream: self.stream,
+            line: redact::text(&self.line),
+        }
+    }
+}
+
+impl fmt::Display for ExecutionResult {
+    /// Formats a one-line summary, followed by the output tail when the run failed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.command)?;
+        match (self.exit_code, self.signal) {
+            _ if self.timed_out => write!(f, "timed out")?,
+            _ if self.cancelled => write!(f, "was cancelled")?,
+            (Some(code), _) => write!(f, "exited with code {}", code)?,
+            (None, Some(signal)) => write!(f, "was killed by signal {}", signal)?,
+            (None, None) => write!(f, "ended")?,
+        }
+        write!(f, " after {:.1}s", self.wall_time.as_secs_f64())?;
+        if let Some(peak) = self.peak_memory_kb {
+            write!(f, ", peak memory {:.1} MB", peak as f64 / 1024.0)?;
+        }
+        if !self.success() && !self.output_tail.is_empty() {
+            write!(f, "\nLast output:")?;
+            for line in &self.output_tail {
+                write!(f, "\n  [{}] {}", line.stream.as_str(), line.line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the signal that ended a process, if it was killed by one.
+pub fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        None
+    }
+}
+
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+fn serialize_optional_secs<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => serializer.serialize_some(&duration.as_secs_f64()),
+        None => serializer.serialize_none(),
+    }
+}
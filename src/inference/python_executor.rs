@@ -2,21 +2,54 @@
 //!
 //! This module provides functionality for executing Python code and managing Python environments.
 
-use std::path::PathBuf;
-use std::process::Command;
-use std::error::Error;
+use crate::error::{Error, Result};
+use std::path::{Path, PathBuf};
 use std::env;
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader};
-use std::process::Stdio;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio_stream::StreamExt;
+use crate::inference::process::{CancelHandle, ExecutionResult, OutputStream, RunningCommand};
+use crate::environment::ModuleEnv;
+use crate::manifest::ModuleManifest;
+use crate::sandbox::SandboxPolicy;
+use crate::dotenv::{self, ResolvedEnv};
+use crate::logs::{LogSettings, RotatingLog};
+use crate::redact;
 
 /// Represents a Python executor for running Python code in a specific environment.
 pub struct PythonExecutor {
     venv_path: PathBuf,
-    stored_env: Option<HashMap<String, String>>,
+    env_overrides: BTreeMap<String, String>,
     pub python: String,
+    root_dir: PathBuf,
     active_module_dir: PathBuf,
     target_script_path: PathBuf,
+    default_timeout: Option<Duration>,
+    module_name: String,
+    /// The name of the module's log the output is written to: `inference`, or the script's name.
+    log_role: String,
+}
+
+/// Options for a single run of a Python command.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// How long the command may run; overrides the executor's default timeout.
+    pub timeout: Option<Duration>,
+    /// Stops the command when cancelled.
+    pub cancel: Option<CancelHandle>,
+    /// Confines the command; None runs it with this process's environment and privileges.
+    pub sandbox: Option<SandboxPolicy>,
+    /// The profile whose `.env.<profile>` file overrides the module's `.env`.
+    pub profile: Option<String>,
+    /// Variables set for this call only, overriding every other layer.
+    pub env: BTreeMap<String, String>,
+    /// Where the command's output is logged, in `<module>/<role>.log`; None does not log it.
+    pub log: Option<LogSettings>,
+    /// The command runs under a supervisor, which logs this process's output: its lines are
+    /// echoed as they are, without the `stdout:`/`stderr:` prefix.
+    pub supervised: bool,
 }
 
 impl PythonExecutor {
@@ -27,273 +60,249 @@ impl PythonExecutor {
     /// * `active_module_name` - The name of the active module.
     /// * `active_module_type` - The type of the active module.
     /// * `target_script_path` - The path to the target Python script.
+    /// * `sandbox` - The module's sandbox policy, which confines reinstalling its requirements.
     ///
     /// # Returns
     ///
     /// A Result containing the PythonExecutor if successful, or an error if creation fails.
-    pub fn new(active_module_name: String, active_module_type: String, target_script_path: String) -> Result<Self, Box<dyn Error>> {
+    pub fn new(
+        active_module_name: String,
+        active_module_type: String,
+        target_script_path: String,
+        sandbox: Option<&SandboxPolicy>,
+    ) -> Result<Self> {
         let root_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into()));
-        let venv_path = root_dir.join(format!(".{}", &active_module_name));
-        let python = activate_env(&venv_path)?;
-        
+        Self::new_in(&root_dir, active_module_name, active_module_type, target_script_path, sandbox)
+    }
+
+    /// Creates a PythonExecutor for a module installed under the given root directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_dir` - The directory holding `subnets/`, `modules/`, the virtual environments and `.env`.
+    /// * `active_module_name` - The name of the active module.
+    /// * `active_module_type` - The type of the active module.
+    /// * `target_script_path` - The path to the target Python script.
+    /// * `sandbox` - The module's sandbox policy, which confines reinstalling its requirements.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the PythonExecutor if successful, or an error if creation fails.
+    pub fn new_in(
+        root_dir: &Path,
+        active_module_name: String,
+        active_module_type: String,
+        target_script_path: String,
+        sandbox: Option<&SandboxPolicy>,
+    ) -> Result<Self> {
+        let root_dir = root_dir.to_path_buf();
+        // Absolute, since commands run from the module directory
+        let venv_path = std::path::absolute(root_dir.join(format!(".{}", &active_module_name)))?;
+
         let active_module_dir = if active_module_type == "inference" {
             root_dir.join("modules").join(&active_module_name)
         } else {
             root_dir.join("subnets").join(&active_module_name)
         };
+
+        // Only does work when the interpreter or the module's requirements changed
+        let module_env = ModuleEnv::new(&venv_path, &active_module_dir)?.with_recorded_provider();
+        module_env.provision(sandbox)?;
+        let python = module_env.python().to_string_lossy().into_owned();
         
+        let manifest = ModuleManifest::load(&active_module_dir)?;
         let target_script_path = if active_module_type == "inference" {
-            active_module_dir.join(format!("{}.py", &active_module_name))
+            manifest
+                .as_ref()
+                .and_then(|manifest| manifest.entry_point(&active_module_dir, "inference"))
+                .unwrap_or_else(|| active_module_dir.join(format!("{}.py", &active_module_name)))
         } else {
             PathBuf::from(target_script_path)
         };
-        let mut executor = Self {
+        let default_timeout = manifest.and_then(|manifest| manifest.timeout).map(Duration::from_secs);
+        let log_role = if active_module_type == "inference" {
+            "inference".to_string()
+        } else {
+            target_script_path
+                .file_stem()
+                .map_or_else(|| "script".to_string(), |stem| stem.to_string_lossy().into_owned())
+        };
+        Ok(Self {
             venv_path,
-            stored_env: None,
+            env_overrides: BTreeMap::new(),
             python,
+            root_dir,
             active_module_dir,
             target_script_path,
-        };
-        executor.source_env()?;
-        Ok(executor)
+            default_timeout,
+            module_name: active_module_name,
+            log_role,
+        })
     }
 
-    /// Runs a Python command in the executor's environment.
+    /// Runs a Python command in the executor's environment, with the module's default timeout.
     ///
     /// # Arguments
     ///
-    /// * `args` - The arguments to pass to the Python command.
+    /// * `args` - The arguments to pass to the Python module, one element per argument.
     ///
     /// # Returns
     ///
     /// A Result containing the output of the command if successful, or an error if the command fails.
-    pub fn run_command(&self, args: String) -> Result<String, Box<dyn Error>> {
-        let target_script_path = self.target_script_path.to_str().unwrap().replace(".py", "").replace("/", ".");
-        let command_str = if cfg!(windows) {
-            format!("{} && {} -m {}", 
-                self.venv_path.join("Scripts").join("activate.bat").to_str().unwrap(),
-                &self.python,
-                target_script_path)
-        } else {
-            format!("source {} && {} -m {}", 
-                self.venv_path.join("bin").join("activate").to_str().unwrap(),
-                &self.python,
-                target_script_path)
-        };
-
-        println!("Executing command: {}", command_str);
-
-        let mut command = if cfg!(windows) {
-            let mut cmd = Command::new("cmd");
-            cmd.args(&["/C", &command_str]);
-            cmd
-        } else {
-            let mut cmd = Command::new("bash");
-            cmd.args(&["-c", &command_str]);
-            cmd
-        };
-
-        command.args([&args])
-               .current_dir(&self.active_module_dir)
-               .stdout(Stdio::piped())
-               .stderr(Stdio::piped());
-
-        if let Some(env_vars) = &self.stored_env {
-            for (key, value) in env_vars {
-                command.env(key, value);
-                println!("Debug: Applied env var: {}={}", key, value);
-            }
-        } else {
-            println!("Debug: No stored environment variables found");
-        }
-
-        let mut child = command.spawn()?;
-
-        let stdout = child.stdout.take().expect("Failed to capture stdout");
-        let stderr = child.stderr.take().expect("Failed to capture stderr");
+    pub async fn run_command(&self, args: &[String]) -> Result<String> {
+        Ok(self.run_command_with(args, &RunOptions::default()).await?.check()?.stdout)
+    }
 
-        let mut output = String::new();
+    /// Sets the timeout used when a call does not give one, replacing the module manifest's `timeout`.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long a command may run, or None for no limit.
+    pub fn set_default_timeout(&mut self, timeout: Option<Duration>) {
+        self.default_timeout = timeout;
+    }
 
-        // Read stdout in a separate thread
-        let stdout_thread = std::thread::spawn(move || {
-            let reader = BufReader::new(stdout);
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    println!("stdout: {}", line);
-                    output.push_str(&line);
-                    output.push('\n');
+    /// Runs a Python command in the executor's environment, echoing its output as it is produced.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The arguments to pass to the Python module, one element per argument.
+    /// * `options` - The timeout and cancel handle for this call.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the ExecutionResult of the run, whether or not the command
+    /// succeeded, or an error if it could not be started. Use [`ExecutionResult::check`]
+    /// to turn a failed run into an error.
+    pub async fn run_command_with(&self, args: &[String], options: &RunOptions) -> Result<ExecutionResult> {
+        let mut running = self.spawn(args, options)?;
+        let mut lines = running.output().expect("output is taken once");
+        let echo = async {
+            while let Some(line) = lines.next().await {
+                match (line.stream, options.supervised) {
+                    (OutputStream::Stdout, false) => println!("stdout: {}", redact::text(&line.line)),
+                    (OutputStream::Stderr, false) => eprintln!("stderr: {}", redact::text(&line.line)),
+                    (OutputStream::Stdout, true) => println!("{}", redact::text(&line.line)),
+                    (OutputStream::Stderr, true) => eprintln!("{}", redact::text(&line.line)),
                 }
             }
-            output
-        });
+        };
+        let (result, ()) = tokio::join!(running.wait(), echo);
+        Ok(result?)
+    }
 
-        // Read stderr in the main thread
-        let reader = BufReader::new(stderr);
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                eprintln!("stderr: {}", line);
-            }
+    /// Starts a Python command in the executor's environment and returns without waiting for it.
+    ///
+    /// The venv's interpreter is started directly, without a shell: `args` reach the
+    /// module's `sys.argv` exactly as given and are never interpreted by a shell.
+    /// `VIRTUAL_ENV` and `PATH` are set as `activate` would set them, after the variables
+    /// of [`resolve_env`](Self::resolve_env).
+    ///
+    /// The command runs in its own process group. When it times out or is cancelled the
+    /// group receives SIGTERM, then SIGKILL if it is still running after a grace period,
+    /// so processes started by the script are stopped too. With a sandbox policy in
+    /// `options`, the command only sees the allowlisted variables of this process's
+    /// environment besides the ones set by the `.env` files and overrides. Must be called
+    /// within a tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The arguments to pass to the Python module, one element per argument.
+    /// * `options` - The timeout, cancel handle, sandbox and environment for this call.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the RunningCommand, whose output can be followed and whose stdin
+    /// can be written to, or an error if the command could not be started.
+    pub fn spawn(&self, args: &[String], options: &RunOptions) -> Result<RunningCommand> {
+        let target_script_path = self.target_script_path.to_str().unwrap().replace(".py", "").replace("/", ".");
+        let env_vars = self.resolve_env(options)?;
+        println!("Executing command: {}", redact::text(&format!("{} -m {} {:?}", self.python, target_script_path, args)));
+
+        let mut command = Command::new(&self.python);
+        command.arg("-m")
+               .arg(&target_script_path)
+               .args(args)
+               .current_dir(&self.active_module_dir);
+        if let Some(policy) = &options.sandbox {
+            // Scrubs the environment, so it comes before the module's own variables
+            policy.apply(command.as_std_mut(), &self.active_module_dir)?;
         }
 
-        // Wait for the command to finish and get the exit status
-        let status = child.wait()?;
+        command.envs(env_vars.layered())
+               .envs(self.venv_env(&env_vars)?)
+               .env_remove("PYTHONHOME");
 
-        // Collect stdout from the thread
-        let stdout_output = stdout_thread.join().expect("Failed to join stdout thread");
+        // The command runs without its log rather than not at all
+        let log = options.log.as_ref().and_then(|settings| {
+            RotatingLog::open(settings, &self.module_name, &self.log_role)
+                .inspect_err(|e| log::warn!("Not logging the output of {}: {}", target_script_path, e))
+                .ok()
+        });
+        Ok(RunningCommand::spawn_logged(
+            format!("Python module {}", target_script_path),
+            &mut command,
+            options.timeout.or(self.default_timeout),
+            options.cancel.clone(),
+            log,
+        )?)
+    }
 
-        if status.success() {
-            Ok(stdout_output)
-        } else {
-            Err(format!("Command failed with exit code: {}", status).into())
-        }
+    /// Sets an environment variable for every command of this executor, overriding the
+    /// `.env` files. Variables given in [`RunOptions::env`] take precedence.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The name of the variable.
+    /// * `value` - The value of the variable.
+    pub fn set_env(&mut self, key: &str, value: &str) {
+        self.env_overrides.insert(key.to_string(), value.to_string());
     }
 
-    /// Sources the environment variables for the Python environment.
+    /// Returns what `source activate` would set: `VIRTUAL_ENV` pointing at the venv and
+    /// `PATH` with the venv's scripts first.
+    ///
+    /// # Arguments
+    ///
+    /// * `env_vars` - The environment of the command, giving the `PATH` to extend.
     ///
     /// # Returns
     ///
-    /// A Result containing the output of the sourcing operation if successful, or an error if it fails.
-    pub fn source_env(&mut self) -> Result<String, std::io::Error> {
-        let activate_script = if cfg!(windows) {
-            self.venv_path.join("Scripts").join("activate.bat")
-        } else {
-            self.venv_path.join("bin").join("activate")
-        };
-        let dot_env_path = self.active_module_dir.to_str().unwrap();
-        let command_str = if cfg!(windows) {
-            format!("{} && set", activate_script.to_str().unwrap())
-        } else {
-            format!("source {} && cat {}/.env", activate_script.to_str().unwrap(), dot_env_path)
-        };
-
-        let output = if cfg!(windows) {
-            Command::new("cmd")
-                .args(&["/C", &command_str])
-                .output()?
-        } else {
-            Command::new("bash")
-                .arg("-c")
-                .arg(&command_str)
-                .output()?
-        };
+    /// A Result containing the two variables, or an InvalidInput error if `PATH` cannot be joined.
+    pub fn venv_env(&self, env_vars: &ResolvedEnv) -> Result<[(&'static str, OsString); 2]> {
+        let path = env_vars.get("PATH").map(OsString::from).unwrap_or_default();
+        let path = env::join_paths(std::iter::once(self.venv_bin_dir()).chain(env::split_paths(&path)))
+            .map_err(|e| Error::InvalidInput(format!("Invalid PATH: {}", e)))?;
+        Ok([("VIRTUAL_ENV", self.venv_path.clone().into_os_string()), ("PATH", path)])
+    }
 
-        if output.status.success() {
-            let env_output = String::from_utf8_lossy(&output.stdout);
-            let mut env_vars = HashMap::new();
-    
-            for line in env_output.lines() {
-                if let Some((key, value)) = line.split_once('=') {
-                    env_vars.insert(key.to_string(), value.to_string());
-                    println!("Debug: Captured env var: {}={}", key, value);  // Debug line
-                }
-            }
-    
-            self.stored_env = Some(env_vars);
-            println!("Successfully activated");
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    /// Returns the directory holding the venv's interpreter and scripts.
+    fn venv_bin_dir(&self) -> PathBuf {
+        if cfg!(windows) {
+            self.venv_path.join("Scripts")
         } else {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to source environment",
-            ))
+            self.venv_path.join("bin")
         }
     }
-}
-
-/// Activates a Python virtual environment.
-///
-/// # Arguments
-///
-/// * `venv_path` - The path to the virtual environment.
-///
-/// # Returns
-///
-/// A Result containing the path to the Python executable if successful, or an error if activation fails.
-pub fn activate_env(venv_path: &PathBuf) -> Result<String, Box<dyn Error>> {
-    let root_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into()));
 
-    if !venv_path.exists() {
-        println!("Creating virtual environment at {:?}", venv_path);
-        let output = Command::new("python3")
-            .args(&["-m", "venv", venv_path.to_str().unwrap()])
-            .current_dir(&root_dir)
-            .output()?;
-
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(format!("Failed to create virtual environment: {}", error).into());
-        }
-        println!("Created Python virtual environment");
+    /// Returns the directory module commands are resolved from.
+    pub fn root_dir(&self) -> &Path {
+        &self.root_dir
     }
 
-    let python_executable = venv_path.join("bin").join("python3");
-
-    // Upgrade pip
-    let output = Command::new(&python_executable)
-        .args(&["-m", "pip", "install", "--upgrade", "pip"])
-        .current_dir(&root_dir)
-        .output()?;
-
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Failed to upgrade pip: {}", error).into());
+    /// Composes the environment of a command from this process's environment, the global
+    /// and module `.env` files, the profile's `.env.<profile>` and the overrides.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The profile and per-call variables of the command.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the environment, or an error if a `.env` file cannot be parsed.
+    pub fn resolve_env(&self, options: &RunOptions) -> Result<ResolvedEnv> {
+        let mut overrides = self.env_overrides.clone();
+        overrides.extend(options.env.clone());
+        dotenv::resolve(&self.root_dir, &self.active_module_dir, options.profile.as_deref(), &overrides)
     }
-
-    Ok(python_executable.to_string_lossy().into_owned())
 }
-
-/// Installs Python requirements for a module.
-///
-/// # Arguments
-///
-/// * `venv_path` - The path to the virtual environment.
-/// * `python_executable` - The path to the Python executable.
-///
-/// # Returns
-///
-/// A Result indicating success or failure of the installation.
-pub fn install_requirements(venv_path: &PathBuf, python_executable: &str) -> Result<(), Box<dyn Error>> {
-    let root_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into()));
-    let module_name = venv_path.file_name().unwrap().to_str().unwrap().trim_start_matches('.');
-    let mut command = Command::new(python_executable);
-
-    let module_path = if PathBuf::from("modules").join(module_name).exists() {
-        PathBuf::from("modules").join(module_name)
-    } else if PathBuf::from("subnets").join(module_name).exists() {
-        PathBuf::from("subnets").join(module_name)
-    } else {
-        println!("No module found for module: {}", module_name);
-        return Ok(());
-    };
-
-    let output = command.args(["-m", "pip", "install", "-e", module_path.to_str().unwrap()])
-        .current_dir(&root_dir)
-        .output()?;
-
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Failed to install {}: {}", module_name, error).into());
-    }
-    println!("Installed {} in virtual environment", module_name);
-
-    let requirements = module_path.join("requirements.txt");
-
-    if !requirements.exists() {
-        println!("requirements.txt not found at {:?}", requirements);
-    }
-
-
-    println!("Installing requirements from {:?}", requirements);
-    let output = Command::new(python_executable)
-        .args(&["-m", "pip", "install", "-r", requirements.to_str().unwrap()])
-        .current_dir(&root_dir)
-        .output()?;
-
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Failed to install {}: {}", module_name, error).into());
-    }
-    println!("Installed {} in virtual environment", module_name);
-
-    Ok(())
-}
\ No newline at end of file
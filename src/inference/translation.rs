@@ -1,20 +1,41 @@
+//! Translation API module for the Module Validator application.
+//!
+//! The Translation API is a long-running HTTP server from the `translation` inference
+//! module, run in the background by the [`Supervisor`].
+
 use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
 
-use crate::inference::python_executor::{PythonExecutor};
+use crate::error::Result;
+use crate::inference::python_executor::{PythonExecutor, RunOptions};
+use crate::sandbox::SandboxPolicy;
+use crate::supervisor::{ProcessRole, ProcessSpec, RestartPolicy, Supervisor};
 
-#[cfg(windows)]
-use winapi;
+/// The name the Translation API is supervised under.
+pub const PROCESS_NAME: &str = "translation-api";
 
+#[allow(dead_code)]
 pub struct TranslationAPI {
     executor: PythonExecutor,
     module_path: String,
     target_script: String,
     host_ip: String,
-    port: String
+    port: String,
+    sandbox: Option<SandboxPolicy>,
 }
 
 impl TranslationAPI {
-    pub fn new() -> Self {
+    /// Creates the Translation API, provisioning the translation module's virtual environment.
+    ///
+    /// # Arguments
+    ///
+    /// * `sandbox` - The translation module's sandbox policy, if it is sandboxed.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the TranslationAPI, or an error if the module's environment cannot be set up.
+    pub fn new(sandbox: Option<&SandboxPolicy>) -> Result<Self> {
 
         let module_path = "modules/translation".to_string();
         let target_script = "translation_api.py".to_string();
@@ -25,92 +46,83 @@ impl TranslationAPI {
         let executor = PythonExecutor::new(
             module_name,
             inference_type,
-            target_script.clone()
-        ).unwrap();
+            target_script.clone(),
+            sandbox,
+        )?;
 
-        Self {
+        Ok(Self {
             executor,
             module_path,
             target_script,
             host_ip,
-            port
-        }
+            port,
+            sandbox: sandbox.cloned(),
+        })
     }
 
-    pub fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
-        use tokio::process::Command;
-        
-
-        // Get the host and port from environment variables
-        let host = env::var("TRANSLATION_API_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-        let port = env::var("TRANSLATION_API_PORT").unwrap_or_else(|_| "8070".to_string());
-
-        // Construct the command
-        let mut cmd = Command::new("pm2");
-        cmd.args(&[
-            "start",
-            format!("python -m modules.translation.translation_api --port {} --host {}", &port, &host).as_str()
-        ]);
-
-        // Spawn the child process
-        let child = Command::new("your_command")
-            .args(&["arg1", "arg2"])
-            .spawn()
-            .expect("Failed to spawn child process");
-
-        // On Windows, disassociate the child process from the parent's console
-        #[cfg(windows)]
-        {
-            use std::os::windows::process::CommandExt;
-            child.spawn_config.creation_flags |= winapi::um::winbase::DETACHED_PROCESS;
-        }
-
-        // Don't wait for the child process
-        std::mem::forget(child);
-
-        println!("Translation API started on {}:{}", host, port);
-
-        Ok(())
+    /// Starts the Translation API in the background under the supervisor, as the
+    /// [`PROCESS_NAME`] process, listening on `TRANSLATION_API_HOST:TRANSLATION_API_PORT`
+    /// from the module's environment.
+    ///
+    /// The server runs in the module's venv and, when the module is sandboxed, under its
+    /// sandbox policy, which the supervisor applies to every run.
+    ///
+    /// # Arguments
+    ///
+    /// * `supervisor` - The supervisor keeping the state of background processes.
+    /// * `restart` - When to restart the API after it exits.
+    /// * `profile` - The profile whose `.env.<profile>` overrides the module's `.env`.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the pid of the supervisor, or an error if the API is already
+    /// running or cannot be started.
+    pub fn start(&self, supervisor: &Supervisor, restart: RestartPolicy, profile: Option<String>) -> Result<u32> {
+        let options = RunOptions {
+            profile,
+            ..Default::default()
+        };
+        let env_vars = self.executor.resolve_env(&options)?;
+
+        // Get the host and port from the module's environment
+        let host = env_vars.get("TRANSLATION_API_HOST").unwrap_or("0.0.0.0").to_string();
+        let port = env_vars.get("TRANSLATION_API_PORT").unwrap_or("8070").to_string();
+
+        // The server runs from the module directory like every module command, in the venv,
+        // and imports the module by its package path from the root directory
+        let root_dir = std::path::absolute(self.executor.root_dir())?;
+        let mut env: Vec<(String, OsString)> = env_vars.layered().map(|(k, v)| (k.to_string(), v.into())).collect();
+        env.extend(self.executor.venv_env(&env_vars)?.map(|(k, v)| (k.to_string(), v)));
+        env.push(("PYTHONPATH".to_string(), root_dir.clone().into_os_string()));
+
+        // The supervisor scrubs its environment down to the allowlist, which has to keep these
+        let sandbox = self.sandbox.clone().map(|mut policy| {
+            policy.env_allowlist.extend(env.iter().map(|(k, _)| k.clone()));
+            policy
+        });
+
+        let spec = ProcessSpec {
+            name: PROCESS_NAME.to_string(),
+            role: ProcessRole::Inference,
+            module: "translation".to_string(),
+            program: PathBuf::from(&self.executor.python),
+            args: vec![
+                "-m".to_string(),
+                "modules.translation.translation_api".to_string(),
+                "--port".to_string(),
+                port.clone(),
+                "--host".to_string(),
+                host.clone(),
+            ],
+            cwd: root_dir.join(&self.module_path),
+            restart,
+            port: port.parse().ok(),
+            sandbox,
+        };
+        let pid = supervisor.start(&spec, env)?;
+
+        println!("Translation API starting on {}:{}", host, port);
+
+        Ok(pid)
     }
-
-    pub fn start_with_pm2(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        use std::process::Command;
-
-        let result = self.executor.source_env()?;
-        println!("{}", result);
-        
-
-        // Get the host and port from environment variables
-        let host = env::var("TRANSLATION_API_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-        let port = env::var("TRANSLATION_API_PORT").unwrap_or_else(|_| "8070".to_string());
-
-        // Construct the command
-        let mut cmd = Command::new("pm2");
-        cmd.args(&[
-            "start",
-            "python",
-            "-n", "translation",
-            "--",
-            "-m", "modules.translation.translation_api",
-            "--port", &port,
-            "--host", &host,
-        ]);
-
-        // Spawn the child process
-        let child = cmd.spawn()?;
-
-        // On Windows, disassociate the child process from the parent's console
-        #[cfg(windows)]
-        {
-            use std::os::windows::process::CommandExt;
-            cmd.creation_flags(winapi::um::winbase::DETACHED_PROCESS);
-        }
-
-        // Don't wait for the child process
-        std::mem::forget(child);
-
-        println!("Translation API started on {}:{}", host, port);
-
-        Ok(())
-    }
-}
\ No newline at end of file
+}
@@ -0,0 +1,398 @@
+//! Python worker processes for the Module Validator application.
+//!
+//! A worker is a long-lived Python process started inside the module's virtual
+//! environment. It imports the module once through `ModuleWrapper` (see
+//! `src/modules/module_wrapper.py`), calls `load()`, and then answers newline-delimited
+//! JSON-RPC 2.0 requests on stdin/stdout until it is shut down, when it calls `unload()`.
+//!
+//! Unlike the [`EmbeddedExecutor`](crate::inference::embedded::EmbeddedExecutor), every
+//! worker has its own interpreter, so modules with conflicting packages or different
+//! Python versions can be served side by side. A [`WorkerPool`] keeps several warm
+//! workers per module, replaces workers that crash and recycles each worker after a
+//! configurable number of requests.
+
+use crate::config::Config;
+use crate::dotenv;
+use crate::error::{Error, Result};
+use crate::inference::inference_requests::{InferenceRequest, InferenceResponse};
+use crate::manifest::ModuleManifest;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+/// The script run by every worker: the `ModuleWrapper` class followed by the JSON-RPC loop.
+const BOOTSTRAP: &str = concat!(
+    include_str!("../modules/module_wrapper.py"),
+    "\n",
+    include_str!("worker_bootstrap.py")
+);
+
+/// Sizing of a [`WorkerPool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkerPoolOptions {
+    /// The number of workers kept running per module.
+    pub size: usize,
+    /// The number of requests a worker serves before it is replaced; 0 never recycles.
+    pub max_requests: usize,
+}
+
+impl Default for WorkerPoolOptions {
+    fn default() -> Self {
+        WorkerPoolOptions {
+            size: 2,
+            max_requests: 0,
+        }
+    }
+}
+
+impl WorkerPoolOptions {
+    /// Takes the pool sizing from the application configuration.
+    pub fn from_config(config: &Config) -> Self {
+        WorkerPoolOptions {
+            size: config.worker_pool_size,
+            max_requests: config.worker_max_requests,
+        }
+    }
+}
+
+/// A message written by a worker on stdout.
+#[derive(Debug, Deserialize)]
+struct RpcMessage {
+    id: Option<u64>,
+    method: Option<String>,
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    message: String,
+    data: Option<String>,
+}
+
+/// Where a module's workers are started from.
+#[derive(Debug, Clone)]
+struct WorkerCommand {
+    module: String,
+    python: PathBuf,
+    import_name: String,
+    import_dir: PathBuf,
+    module_dir: PathBuf,
+    /// The variables set by the global and module `.env` files.
+    env: Vec<(String, String)>,
+}
+
+impl WorkerCommand {
+    fn new(root_dir: &Path, module_name: &str) -> Result<Self> {
+        let module_dir = root_dir.join("modules").join(module_name);
+        if !module_dir.is_dir() {
+            return Err(Error::ModuleNotFound(module_name.to_string()));
+        }
+        let script = ModuleManifest::load(&module_dir)?
+            .and_then(|manifest| manifest.entry_point(&module_dir, "inference"))
+            .unwrap_or_else(|| module_dir.join(format!("{}.py", module_name)));
+        if !script.is_file() {
+            return Err(Error::ScriptNotFound {
+                role: "Inference".to_string(),
+                path: script,
+            });
+        }
+        let python = root_dir.join(format!(".{}", module_name)).join("bin").join("python3");
+        if !python.exists() {
+            return Err(Error::ConfigInvalid(format!(
+                "Virtual environment for {} not found at {}",
+                module_name,
+                python.display()
+            )));
+        }
+        let env = dotenv::resolve(root_dir, &module_dir, None, &Default::default())?
+            .layered()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        Ok(WorkerCommand {
+            module: module_name.to_string(),
+            env,
+            python,
+            import_name: script.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+            import_dir: script.parent().unwrap_or(&module_dir).to_path_buf(),
+            module_dir,
+        })
+    }
+
+    fn failed(&self, message: impl Into<String>) -> Error {
+        Error::PythonFailed {
+            module: self.module.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+/// A running worker process.
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+    served: usize,
+    alive: bool,
+}
+
+impl Worker {
+    /// Starts a worker and waits until the module is loaded.
+    fn spawn(command: &WorkerCommand) -> Result<Self> {
+        let mut child = Command::new(&command.python)
+            .arg("-u")
+            .arg("-c")
+            .arg(BOOTSTRAP)
+            .arg(&command.import_name)
+            .arg(&command.import_dir)
+            .current_dir(&command.module_dir)
+            .envs(command.env.clone())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("Failed to open worker stdin");
+        let stdout = BufReader::new(child.stdout.take().expect("Failed to open worker stdout"));
+        let mut worker = Worker {
+            child,
+            stdin,
+            stdout,
+            next_id: 1,
+            served: 0,
+            alive: true,
+        };
+
+        match worker.read_message(command) {
+            Ok(message) if message.method.as_deref() == Some("ready") => Ok(worker),
+            Ok(message) => {
+                worker.kill();
+                Err(rpc_failure(command, message.error))
+            }
+            Err(e) => {
+                worker.kill();
+                Err(e)
+            }
+        }
+    }
+
+    /// Sends one request and waits for its response.
+    fn call(&mut self, command: &WorkerCommand, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let written = writeln!(self.stdin, "{}", request).and_then(|_| self.stdin.flush());
+        if written.is_err() {
+            self.alive = false;
+            return Err(command.failed("worker exited before the request was sent"));
+        }
+        loop {
+            let message = self.read_message(command)?;
+            if message.id != Some(id) {
+                continue;
+            }
+            return match message.error {
+                Some(error) => Err(rpc_failure(command, Some(error))),
+                None => Ok(message.result.unwrap_or(Value::Null)),
+            };
+        }
+    }
+
+    fn read_message(&mut self, command: &WorkerCommand) -> Result<RpcMessage> {
+        let mut line = String::new();
+        let read = self.stdout.read_line(&mut line);
+        if !matches!(read, Ok(n) if n > 0) {
+            self.alive = false;
+            let status = self
+                .child
+                .wait()
+                .map(|status| status.to_string())
+                .unwrap_or_else(|_| "unknown status".to_string());
+            return Err(command.failed(format!("worker exited unexpectedly ({})", status)));
+        }
+        serde_json::from_str(&line).map_err(|e| command.failed(format!("invalid message from worker: {}", e)))
+    }
+
+    /// Asks the worker to unload the module and exit, killing it if it does not answer.
+    fn shutdown(mut self, command: &WorkerCommand) {
+        if self.alive && self.call(command, "shutdown", Value::Null).is_ok() {
+            let _ = self.child.wait();
+        } else {
+            self.kill();
+        }
+    }
+
+    fn kill(&mut self) {
+        self.alive = false;
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The workers of a pool that are not serving a request, and how many are running in total.
+struct Workers {
+    idle: Vec<Worker>,
+    running: usize,
+}
+
+/// A set of warm workers for one module.
+pub struct WorkerPool {
+    command: WorkerCommand,
+    options: WorkerPoolOptions,
+    workers: Mutex<Workers>,
+    available: Condvar,
+}
+
+impl WorkerPool {
+    /// Starts the workers for an installed inference module.
+    ///
+    /// # Arguments
+    ///
+    /// * `module_name` - The name of the inference module.
+    /// * `options` - The number of workers and when to recycle them.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the WorkerPool once every worker has loaded the module.
+    pub fn start(module_name: &str, options: WorkerPoolOptions) -> Result<Self> {
+        let root_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".into()));
+        Self::start_in(&root_dir, module_name, options)
+    }
+
+    /// Starts the workers for an inference module found under the given root directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_dir` - The directory containing `modules/<name>` and the `.<name>` virtual environment.
+    /// * `module_name` - The name of the inference module.
+    /// * `options` - The number of workers and when to recycle them.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the WorkerPool once every worker has loaded the module.
+    pub fn start_in(root_dir: &Path, module_name: &str, options: WorkerPoolOptions) -> Result<Self> {
+        let command = WorkerCommand::new(root_dir, module_name)?;
+        let idle = (0..options.size.max(1))
+            .map(|_| Worker::spawn(&command))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(WorkerPool {
+            command,
+            options,
+            workers: Mutex::new(Workers { running: idle.len(), idle }),
+            available: Condvar::new(),
+        })
+    }
+
+    /// Returns the name of the module served by the pool.
+    pub fn name(&self) -> &str {
+        &self.command.module
+    }
+
+    /// Runs an inference request, passing its data to `process` as keyword arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The inference request.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the return value of `process`, or an error if it raised or the worker crashed.
+    pub fn process(&self, request: &InferenceRequest) -> Result<InferenceResponse> {
+        let params = Value::Object(request.data.clone().into_iter().collect());
+        Ok(InferenceResponse {
+            data: self.call(params)?,
+        })
+    }
+
+    /// Calls `process` on the next free worker, waiting for one if all are busy.
+    ///
+    /// A JSON object is passed as keyword arguments, an array as positional arguments
+    /// and any other value as the single argument.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The arguments for `process`.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the return value of `process` as JSON.
+    pub fn call(&self, params: Value) -> Result<Value> {
+        let mut worker = self.checkout()?;
+        let result = worker.call(&self.command, "process", params);
+        worker.served += 1;
+
+        let recycle = self.options.max_requests > 0 && worker.served >= self.options.max_requests;
+        if !worker.alive {
+            // A crashed worker is replaced; the request that crashed it is not retried
+            eprintln!("Worker for {} crashed, starting a new one", self.command.module);
+            worker.kill();
+            self.replace();
+        } else if recycle {
+            worker.shutdown(&self.command);
+            self.replace();
+        } else {
+            self.checkin(worker);
+        }
+        result
+    }
+
+    /// Takes an idle worker, starting one if fewer than `size` are running, or waits for one to be returned.
+    fn checkout(&self) -> Result<Worker> {
+        let mut workers = self.lock();
+        loop {
+            if let Some(worker) = workers.idle.pop() {
+                return Ok(worker);
+            }
+            if workers.running < self.options.size.max(1) {
+                workers.running += 1;
+                drop(workers);
+                return Worker::spawn(&self.command).inspect_err(|_| self.lock().running -= 1);
+            }
+            workers = self.available.wait(workers).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn checkin(&self, worker: Worker) {
+        self.lock().idle.push(worker);
+        self.available.notify_one();
+    }
+
+    /// Starts a worker in place of one that crashed or was recycled.
+    fn replace(&self) {
+        match Worker::spawn(&self.command) {
+            Ok(worker) => self.checkin(worker),
+            Err(e) => {
+                // The next request tries again
+                eprintln!("Warning: could not restart a worker for {}: {}", self.command.module, e);
+                self.lock().running -= 1;
+                self.available.notify_one();
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Workers> {
+        self.workers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        let workers = self.workers.get_mut().unwrap_or_else(|e| e.into_inner());
+        for worker in std::mem::take(&mut workers.idle) {
+            worker.shutdown(&self.command);
+        }
+    }
+}
+
+fn rpc_failure(command: &WorkerCommand, error: Option<RpcError>) -> Error {
+    match error {
+        // The traceback ends with the exception itself, so it replaces the short message
+        Some(RpcError { data: Some(traceback), .. }) => command.failed(traceback.trim_end()),
+        Some(RpcError { message, data: None }) => command.failed(message),
+        None => command.failed("worker sent an unexpected message"),
+    }
+}
@@ -0,0 +1,84 @@
+"""Serves one inference module over newline-delimited JSON-RPC 2.0 on stdin/stdout.
+
+Run inside the module's virtual environment as
+
+    python -u -c <ModuleWrapper source + this file> <module name> <module dir>
+
+The module is imported once through ModuleWrapper and loaded; the worker then writes
+a "ready" notification and answers "process" requests until it receives "shutdown"
+or stdin closes, when the module is unloaded. Anything the module prints goes to
+stderr, so stdout only ever carries protocol messages.
+"""
+
+import json
+import os
+import sys
+import traceback
+
+
+def _send(channel, message):
+    channel.write(json.dumps(message, default=str) + "\n")
+    channel.flush()
+
+
+def _error(request_id, message, data=None):
+    error = {"code": -32000, "message": message}
+    if data is not None:
+        error["data"] = data
+    return {"jsonrpc": "2.0", "id": request_id, "error": error}
+
+
+def _process(wrapper, params):
+    if isinstance(params, dict):
+        return wrapper.process(**params)
+    if isinstance(params, list):
+        return wrapper.process(*params)
+    return wrapper.process(params)
+
+
+def main():
+    module_name, module_dir = sys.argv[1], sys.argv[2]
+
+    # Keep the real stdout for protocol messages and send everything else to stderr
+    channel = os.fdopen(os.dup(sys.stdout.fileno()), "w")
+    os.dup2(sys.stderr.fileno(), sys.stdout.fileno())
+    sys.stdout = sys.stderr
+
+    sys.path.insert(0, module_dir)
+    try:
+        wrapper = ModuleWrapper(module_name)  # noqa: F821 - prepended by the executor
+        if not hasattr(wrapper.module, "process"):
+            raise AttributeError("module %s does not define a process function" % module_name)
+        wrapper.load()
+    except BaseException as e:
+        _send(channel, _error(None, "%s: %s" % (type(e).__name__, e), traceback.format_exc()))
+        return 1
+    _send(channel, {"jsonrpc": "2.0", "method": "ready", "params": {"pid": os.getpid()}})
+
+    for line in sys.stdin:
+        if not line.strip():
+            continue
+        try:
+            request = json.loads(line)
+        except ValueError as e:
+            _send(channel, {"jsonrpc": "2.0", "id": None, "error": {"code": -32700, "message": str(e)}})
+            continue
+        request_id = request.get("id")
+        method = request.get("method")
+        if method == "shutdown":
+            _send(channel, {"jsonrpc": "2.0", "id": request_id, "result": None})
+            break
+        if method != "process":
+            _send(channel, {"jsonrpc": "2.0", "id": request_id, "error": {"code": -32601, "message": "unknown method %s" % method}})
+            continue
+        try:
+            result = _process(wrapper, request.get("params"))
+            _send(channel, {"jsonrpc": "2.0", "id": request_id, "result": result})
+        except Exception as e:
+            _send(channel, _error(request_id, "%s: %s" % (type(e).__name__, e), traceback.format_exc()))
+
+    wrapper.unload()
+    return 0
+
+
+sys.exit(main())
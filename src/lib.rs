@@ -7,16 +7,31 @@
 #[cfg(test)]
 mod tests;
 
+pub mod answers;
+pub mod catalog;
 pub mod config;
+pub mod error;
+pub mod database;
+pub mod registry;
 pub mod utils;
 pub mod modules;
 pub mod inference;
+pub mod manifest;
 pub mod validator;
 pub mod miner;
 pub mod cli;
+pub mod config_parser;
+pub mod dotenv;
 pub mod proxy;
+pub mod logs;
+pub mod redact;
+pub mod supervisor;
 pub mod api;
+pub mod sandbox;
+pub mod environment;
 
 pub use config::Config;
 pub use crate::modules::inference_module::InferenceModule;
 pub use crate::utils::parse_url;
+pub use crate::registry::ModuleRegistry;
+pub use crate::error::{Error, Result};
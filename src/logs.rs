@@ -0,0 +1,437 @@
+//! Log files module for the Module Validator application.
+//!
+//! The output of module scripts and supervised processes is kept in
+//! `data/logs/<module>/<role>.log`, one timestamped line per line of output, with
+//! secrets masked. A log is rotated once it is larger than `max_size_mb` or older than
+//! `max_age_hours`: it is renamed to `<role>-<time>.log`, gzipped when `compress` is set,
+//! and only the `retain` most recent rotated files are kept.
+//!
+//! Each line reads `<RFC 3339 time> <tag> <text>`, where the tag is `stdout`, `stderr`
+//! or `supervisor`, so logs can be filtered by time with [`filter_lines`].
+
+use crate::error::{Error, Result};
+use crate::redact;
+use crate::utils::check_name;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// The directory holding the module logs by default.
+pub const LOG_DIR: &str = "data/logs";
+
+/// The extension of log files.
+const LOG_EXTENSION: &str = "log";
+
+/// How module logs are rotated and how many rotated files are kept, the `logs` key of `config.yaml`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSettings {
+    /// The directory holding one subdirectory of logs per module.
+    pub dir: PathBuf,
+    /// Rotate a log once it is larger than this many megabytes; 0 never rotates by size.
+    pub max_size_mb: u64,
+    /// Rotate a log once its first line is older than this many hours; 0 never rotates by age.
+    pub max_age_hours: u64,
+    /// The number of rotated files kept per log; older ones are deleted.
+    pub retain: usize,
+    /// Whether rotated files are gzipped.
+    pub compress: bool,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        LogSettings {
+            dir: PathBuf::from(LOG_DIR),
+            max_size_mb: 10,
+            max_age_hours: 24,
+            retain: 5,
+            compress: true,
+        }
+    }
+}
+
+impl fmt::Display for LogSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "dir={} max_size_mb={} max_age_hours={} retain={} compress={}",
+            self.dir.display(),
+            self.max_size_mb,
+            self.max_age_hours,
+            self.retain,
+            self.compress
+        )
+    }
+}
+
+impl LogSettings {
+    /// Returns the log file of a module's role.
+    ///
+    /// # Arguments
+    ///
+    /// * `module` - The name of the module.
+    /// * `role` - What wrote the log: `miner`, `validator`, `inference`, a script or a supervised process.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the path, or an InvalidInput error if a name could escape the log directory.
+    pub fn path(&self, module: &str, role: &str) -> Result<PathBuf> {
+        check_name("module", module)?;
+        check_name("log", role)?;
+        Ok(self.dir.join(module).join(format!("{}.{}", role, LOG_EXTENSION)))
+    }
+
+    /// Lists the logs of a module, by role.
+    ///
+    /// # Arguments
+    ///
+    /// * `module` - The name of the module.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the roles that have a current log, ordered by name.
+    pub fn roles(&self, module: &str) -> Result<Vec<String>> {
+        check_name("module", module)?;
+        let entries = match fs::read_dir(self.dir.join(module)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut roles = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if let Some(role) = name.strip_suffix(".log") {
+                if !is_rotated_name(role) {
+                    roles.push(role.to_string());
+                }
+            }
+        }
+        roles.sort();
+        Ok(roles)
+    }
+}
+
+/// The format of the time in the name of a rotated log.
+const ROTATED_TIME_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// Returns whether a file stem is the stem of a rotated log, `<role>-<time>`.
+fn is_rotated_name(stem: &str) -> bool {
+    stem.rsplit_once('-')
+        .is_some_and(|(_, time)| chrono::NaiveDateTime::parse_from_str(time, ROTATED_TIME_FORMAT).is_ok())
+}
+
+/// Returns the rotated files of a log, oldest first.
+///
+/// # Arguments
+///
+/// * `path` - The current log file.
+pub fn rotated_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let (Some(dir), Some(stem)) = (path.parent(), path.file_stem().and_then(|stem| stem.to_str())) else {
+        return Ok(Vec::new());
+    };
+    let prefix = format!("{}-", stem);
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut rotated = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        let rotated_stem = name.strip_suffix(".log.gz").or_else(|| name.strip_suffix(".log"));
+        if let Some(time) = rotated_stem.and_then(|stem| stem.strip_prefix(&prefix)) {
+            if chrono::NaiveDateTime::parse_from_str(time, ROTATED_TIME_FORMAT).is_ok() {
+                rotated.push(path);
+            }
+        }
+    }
+    // The time in the names sorts chronologically
+    rotated.sort();
+    Ok(rotated)
+}
+
+/// Reads every line of a log, its rotated files first, decompressing the gzipped ones.
+///
+/// # Arguments
+///
+/// * `path` - The current log file.
+pub fn read_all(path: &Path) -> Result<Vec<String>> {
+    let mut lines = read_rotated(path)?;
+    lines.extend(LogTail::new(path).read_lines()?);
+    Ok(lines)
+}
+
+/// Reads the lines of the rotated files of a log, oldest first, decompressing the gzipped ones.
+///
+/// # Arguments
+///
+/// * `path` - The current log file.
+pub fn read_rotated(path: &Path) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    for rotated in rotated_files(path)? {
+        let file = File::open(&rotated)?;
+        let reader: Box<dyn Read> = if rotated.extension().is_some_and(|extension| extension == "gz") {
+            Box::new(GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        let mut content = Vec::new();
+        BufReader::new(reader).read_to_end(&mut content)?;
+        lines.extend(String::from_utf8_lossy(&content).lines().map(str::to_string));
+    }
+    Ok(lines)
+}
+
+/// A log file that rotates itself as lines are appended.
+///
+/// Several processes may append to the same log; whichever sees it over its limits rotates it.
+#[derive(Debug)]
+pub struct RotatingLog {
+    path: PathBuf,
+    settings: LogSettings,
+    file: Option<File>,
+    size: u64,
+    /// When the first line of the current file was written.
+    started_at: Option<DateTime<Utc>>,
+}
+
+impl RotatingLog {
+    /// Opens a module's log for appending, creating its directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Where logs are kept and how they are rotated.
+    /// * `module` - The name of the module.
+    /// * `role` - What writes the log.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the RotatingLog, or an error if a name is invalid or the file cannot be opened.
+    pub fn open(settings: &LogSettings, module: &str, role: &str) -> Result<Self> {
+        let path = settings.path(module, role)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut log = RotatingLog {
+            path,
+            settings: settings.clone(),
+            file: None,
+            size: 0,
+            started_at: None,
+        };
+        log.reopen()?;
+        Ok(log)
+    }
+
+    /// Returns the path of the current log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a timestamped line with secrets masked, rotating the log first if it is over its limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - What the line is: `stdout`, `stderr` or `supervisor`.
+    /// * `text` - The line, without its newline.
+    pub fn write_line(&mut self, tag: &str, text: &str) -> io::Result<()> {
+        let now = Utc::now();
+        if self.needs_rotation(now) {
+            self.rotate(now)?;
+        }
+        let line = format!("{} {} {}\n", now.format("%Y-%m-%dT%H:%M:%S%.3fZ"), tag, redact::text(text));
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                self.reopen()?;
+                self.file.as_mut().expect("reopen opens the file")
+            }
+        };
+        file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        self.started_at.get_or_insert(now);
+        Ok(())
+    }
+
+    /// Returns whether the current file is over the size or age limit.
+    fn needs_rotation(&self, now: DateTime<Utc>) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let too_large = self.settings.max_size_mb > 0 && self.size >= self.settings.max_size_mb * 1024 * 1024;
+        let too_old = self.settings.max_age_hours > 0
+            && self.started_at.is_some_and(|started_at| {
+                now - started_at >= chrono::Duration::hours(self.settings.max_age_hours as i64)
+            });
+        too_large || too_old
+    }
+
+    /// Opens the current file, reading its size and the time of its first line.
+    fn reopen(&mut self) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).read(true).open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.started_at = None;
+        if self.size > 0 {
+            file.seek(SeekFrom::Start(0))?;
+            let mut first_line = String::new();
+            BufReader::new(&file).read_line(&mut first_line)?;
+            self.started_at = line_timestamp(&first_line);
+        }
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Moves the current file aside, compresses it and deletes the rotated files beyond retention.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time in the rotated file's name.
+    pub fn rotate(&mut self, now: DateTime<Utc>) -> io::Result<()> {
+        self.file = None;
+        let stem = self.path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("log");
+        let rotated = self
+            .path
+            .with_file_name(format!("{}-{}.{}", stem, now.format(ROTATED_TIME_FORMAT), LOG_EXTENSION));
+        match fs::rename(&self.path, &rotated) {
+            Ok(()) => {}
+            // Another writer rotated it first
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return self.reopen(),
+            Err(e) => return Err(e),
+        }
+        self.reopen()?;
+
+        if self.settings.compress {
+            compress(&rotated)?;
+        }
+        let rotated_files = rotated_files(&self.path).map_err(io::Error::other)?;
+        let excess = rotated_files.len().saturating_sub(self.settings.retain);
+        for old in &rotated_files[..excess] {
+            fs::remove_file(old)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces a file with its gzipped copy, `<name>.gz`.
+fn compress(path: &Path) -> io::Result<()> {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".gz");
+    let mut encoder = GzEncoder::new(File::create(&compressed)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+/// Reads the lines appended to a log file since the last read, e.g. to follow it.
+///
+/// A file that became shorter than what was already read was truncated or rotated, and
+/// is read again from the start.
+#[derive(Debug, Clone)]
+pub struct LogTail {
+    path: PathBuf,
+    offset: u64,
+    /// The end of the file after its last newline, completed by a later read.
+    partial: Vec<u8>,
+}
+
+impl LogTail {
+    /// Creates a reader for a log file, starting at its beginning.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        LogTail {
+            path: path.into(),
+            offset: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Returns the complete lines written since the last read; none if the file does not exist.
+    pub fn read_lines(&mut self) -> Result<Vec<String>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        if file.metadata()?.len() < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut buffer = std::mem::take(&mut self.partial);
+        self.offset += file.read_to_end(&mut buffer)? as u64;
+
+        let complete = buffer.iter().rposition(|&b| b == b'\n').map_or(0, |end| end + 1);
+        self.partial = buffer.split_off(complete);
+        Ok(String::from_utf8_lossy(&buffer).lines().map(str::to_string).collect())
+    }
+}
+
+/// Returns the time a log line was written, from the timestamp it starts with.
+pub fn line_timestamp(line: &str) -> Option<DateTime<Utc>> {
+    let (timestamp, _) = line.split_once(' ')?;
+    DateTime::parse_from_rfc3339(timestamp).ok().map(|time| time.with_timezone(&Utc))
+}
+
+/// Keeps the log lines written at or after a time, then the last ones of those.
+///
+/// Lines without a timestamp belong to the line before them.
+///
+/// # Arguments
+///
+/// * `lines` - The lines of a log file, oldest first.
+/// * `since` - The time lines must be written at or after, if any.
+/// * `limit` - How many of the last lines to keep, if limited.
+pub fn filter_lines(lines: Vec<String>, since: Option<DateTime<Utc>>, limit: Option<usize>) -> Vec<String> {
+    let mut kept = Vec::new();
+    let mut keep = since.is_none();
+    for line in lines {
+        if let (Some(since), Some(time)) = (since, line_timestamp(&line)) {
+            keep = time >= since;
+        }
+        if keep {
+            kept.push(line);
+        }
+    }
+    if let Some(limit) = limit {
+        kept.drain(..kept.len().saturating_sub(limit));
+    }
+    kept
+}
+
+/// Parses a `--since` value: how long ago, such as `30s`, `10m`, `2h` or `1d`, or an RFC 3339 timestamp.
+///
+/// # Arguments
+///
+/// * `value` - The value to parse.
+/// * `now` - The time durations are counted back from.
+///
+/// # Returns
+///
+/// A Result containing the time, or an InvalidInput error if the value is neither.
+pub fn parse_since(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let invalid = || {
+        Error::InvalidInput(format!(
+            "Invalid --since `{}`: use a duration such as 30s, 10m, 2h or 1d, or an RFC 3339 time",
+            value
+        ))
+    };
+    let split = value.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let amount: i64 = value[..split].parse().map_err(|_| invalid())?;
+    let duration = match &value[split..] {
+        "s" => chrono::Duration::try_seconds(amount),
+        "m" => chrono::Duration::try_minutes(amount),
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        _ => None,
+    };
+    duration.and_then(|duration| now.checked_sub_signed(duration)).ok_or_else(invalid)
+}
@@ -4,104 +4,268 @@
 //! It provides a command-line interface for interacting with the module system.
 
 use clap::Parser;
-mod cli;
-mod config_parser;
-mod inference;
-mod modules;
-mod utils;
-mod validator;
-mod miner;
-mod proxy;
-mod api;
-use crate::api::API;
-
-use cli::{Cli, Commands};
-use dotenv::dotenv;
+use module_validator::answers::Answers;
+use module_validator::api::API;
+use module_validator::catalog::{CatalogEntry, Catalogs, CATALOG_DIR};
+use module_validator::cli::{AnswerArgs, CatalogCommands, Cli, Commands, ConfigCommands, DetachArgs, EnvCommands};
+use module_validator::config::{Config, LoadedConfig};
+use module_validator::config_parser::{self, ConfigParser};
+use module_validator::dotenv::{self, EnvSource};
+use module_validator::environment;
+use module_validator::error::{Error, Result};
+use module_validator::inference::embedded::EmbeddedExecutor;
+use module_validator::inference::process::CancelHandle;
+use module_validator::inference::python_executor::{PythonExecutor, RunOptions};
+use module_validator::inference::translation::{self, TranslationAPI};
+use module_validator::logs::{self, filter_lines, parse_since, LogTail};
+use module_validator::miner::Miner;
+use module_validator::modules::inference_module::InferenceModule;
+use module_validator::modules::InstallOptions;
+use module_validator::modules::subnet_module::SubnetModule;
+use module_validator::modules::uninstall::UninstallPlan;
+use module_validator::proxy;
+use module_validator::redact;
+use module_validator::supervisor::{ManagedProcess, ProcessRole, ProcessSpec, ProcessState, Supervisor};
+use module_validator::registry::{ModuleRecord, ModuleRegistry, ModuleType};
+use module_validator::validator::Validator;
+
+use chrono::{DateTime, Utc};
+use dialoguer::Confirm;
+use std::collections::BTreeMap;
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 
-use crate::miner::Miner;
-use crate::config_parser::ConfigParser;
-use crate::inference::python_executor::{activate_env, PythonExecutor};
-use crate::modules::inference_module::InferenceModule;
-use crate::modules::subnet_module::SubnetModule;
-use crate::validator::Validator;
-use crate::inference::translation::TranslationAPI;
+/// How many of the last lines `logs` prints without `--since` or `--lines`.
+const DEFAULT_LOG_LINES: usize = 100;
+
+/// How often `logs --follow` checks the log for new lines.
+const LOG_FOLLOW_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
 
 /// Main entry point for the Module Validator application.
+///
+/// Errors are printed and mapped to the exit code given by [`Error::exit_code`].
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Load environment variables from .env file
-    dotenv().ok();
-
+async fn main() {
     // Parse command-line arguments
     let cli = Cli::parse();
+    redact::set_show_secrets(cli.show_secrets);
 
-    // Initialize the module registry with the test flag
-    let mut module_type = String::new();
-    let mut module_name = String::new();
+    // Load environment variables from .env file, keeping the ones already set
+    if let Err(e) = dotenv::load_into_process(Path::new(".env")) {
+        eprintln!("Warning: {}", redact::text(&e.to_string()));
+    }
+    redact::learn(std::env::vars());
 
+    let loaded = match Config::load(&cli.config_overrides()) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error: {}", redact::text(&e.to_string()));
+            std::process::exit(e.exit_code());
+        }
+    };
+    env_logger::Builder::new()
+        .parse_filters(&loaded.config.log_level)
+        .format(|buf, record| {
+            let message = redact::text(&record.args().to_string());
+            writeln!(buf, "[{} {} {}] {}", buf.timestamp(), record.level(), record.target(), message)
+        })
+        .init();
+
+    if let Err(e) = run(&cli, &loaded).await {
+        eprintln!("Error: {}", redact::text(&e.to_string()));
+        std::process::exit(e.exit_code());
+    }
+}
+
+/// Runs the command given on the command line.
+///
+/// # Arguments
+///
+/// * `cli` - The parsed command line.
+/// * `loaded` - The effective application configuration.
+///
+/// # Returns
+///
+/// A Result indicating whether the command succeeded.
+async fn run(cli: &Cli, loaded: &LoadedConfig) -> Result<()> {
+    let config = &loaded.config;
     // Match the command from CLI and execute corresponding logic
     match &cli.command {
-        Commands::Install { url } => {
-            // Determine module type and name based on the URL
-            if url.contains("://") || url.contains('/') {
-                module_name = url.split("/").last().unwrap().to_string();
-                module_type = if url.contains("github.com") {
-                    "subnets".to_string()
+        Commands::Install { url, keep_failed, answers } => {
+            // Resolve catalog names first, then fall back to guessing from the URL
+            let catalogs = Catalogs::load_dir(Path::new(CATALOG_DIR))?;
+            let (module_name, module_type, url) = if let Some((catalog, entry)) = catalogs.find(url) {
+                println!("Found {} in the {} catalog: {}", entry.name, catalog.name, entry.path);
+                (entry.name.clone(), ModuleType::Subnet, entry.path.clone())
+            } else if let Some((_, entry)) = catalogs.find_by_url(url) {
+                (entry.name.clone(), ModuleType::Subnet, url.to_string())
+            } else if url.contains("://") || url.contains('/') {
+                let module_name = url.split('/').next_back().unwrap().to_string();
+                if url.contains("github.com") {
+                    (module_name, ModuleType::Subnet, url.to_string())
                 } else {
-                    "inference".to_string()
-                };
+                    (module_name, ModuleType::Inference, url.to_string())
+                }
             } else {
-                module_name = url.to_string();
-                module_type = "inference".to_string();
-            }
-            activate_env(&PathBuf::from(format!(".{}", module_name)))?;
+                (url.to_string(), ModuleType::Inference, url.to_string())
+            };
+            let registry = ModuleRegistry::from_config(config).await?;
+            let options = InstallOptions {
+                keep_failed: *keep_failed,
+                answers: answers.load()?,
+                sandbox: config.sandbox.clone(),
+                env_providers: config.env_providers.clone(),
+            };
 
-            if module_type == "subnets" {
+            if module_type == ModuleType::Subnet {
                 // Install and register subnet module
-                let mut subnet_module = SubnetModule::new(url, &module_name)?;
-                subnet_module.install().await?;
+                let mut subnet_module = SubnetModule::new(&url, &module_name)?;
+                subnet_module.install(&registry, &options).await?;
                 println!(
                     "{} module installed and registered successfully",
                     module_name
                 );
 
                 // Parse and configure module
-                let module_dir = Path::new(&module_type).join(&module_name);
+                let module_dir = Path::new(module_type.directory()).join(&module_name);
                 let mut config = ConfigParser::parse_commands(&module_dir)?;
-                ConfigParser::prompt_for_env_vars(&mut config)?;
+                ConfigParser::prompt_for_env_vars(&mut config, &options.answers)?;
                 ConfigParser::save_config(&config, &module_dir)?;
                 print_config(&config);
             } else {
                 // Install and register inference module
-                let inference_module = InferenceModule::new(url)?;
-                inference_module.install().await?;
+                let inference_module = InferenceModule::new(&url)?;
+                inference_module.install(&registry, &options).await?;
                 println!(
                     "{} module installed and registered successfully",
                     module_name
                 );
             }
         }
-        Commands::RunInference { name, input } => {
+        Commands::Catalog { command } => run_catalog_command(command)?,
+        Commands::Config { command } => match command {
+            ConfigCommands::Show => {
+                println!("{:<24} {:<40} SOURCE", "KEY", "VALUE");
+                for (key, value, source) in loaded.entries() {
+                    println!("{:<24} {:<40} {}", key, redact::text(&value), source);
+                }
+            }
+        },
+        Commands::List => {
+            let registry = ModuleRegistry::from_config(config).await?;
+            let modules = registry.list().await?;
+            if modules.is_empty() {
+                println!("No modules installed");
+            } else {
+                print_modules(&modules);
+            }
+        }
+        Commands::Uninstall { name, yes } => {
+            let registry = ModuleRegistry::from_config(config).await?;
+            let record = registry.get(name).await?;
+            let plan = UninstallPlan::new(Path::new("."), name, record.as_ref())?;
+
+            if plan.is_empty() && record.is_none() {
+                println!("Module {} is not installed", name);
+                return Ok(());
+            }
+            plan.describe();
+
+            let confirmed = *yes
+                || Confirm::new()
+                    .with_prompt(format!("Uninstall {}?", name))
+                    .default(false)
+                    .interact()?;
+            if !confirmed {
+                println!("Uninstall cancelled");
+                return Ok(());
+            }
+
+            plan.execute()?;
+            registry.unregister(name).await?;
+            println!("{} uninstalled successfully", name);
+        }
+        Commands::Update { name, git_ref } => {
+            let registry = ModuleRegistry::from_config(config).await?;
+            let report = match installed_module_type(&registry, name).await? {
+                ModuleType::Subnet => {
+                    SubnetModule::installed(name)?
+                        .update(
+                            &registry,
+                            git_ref.as_deref(),
+                            config.sandbox.for_module(name),
+                            config.env_providers.for_module(name),
+                        )
+                        .await?
+                }
+                ModuleType::Inference => {
+                    if git_ref.is_some() {
+                        return Err(Error::InvalidInput("--ref only applies to subnet modules".to_string()));
+                    }
+                    InferenceModule::new(name)?
+                        .update(&registry, config.sandbox.for_module(name), config.env_providers.for_module(name))
+                        .await?
+                }
+            };
+            println!("{}", report);
+        }
+        Commands::Env { command } => match command {
+            EnvCommands::Rebuild { name } => {
+                let registry = ModuleRegistry::from_config(config).await?;
+                let sandbox = config.sandbox.for_module(name);
+                let env_provider = config.env_providers.for_module(name);
+                match installed_module_type(&registry, name).await? {
+                    ModuleType::Subnet => {
+                        SubnetModule::installed(name)?
+                            .rebuild_env(&registry, sandbox, env_provider)
+                            .await?
+                    }
+                    ModuleType::Inference => {
+                        InferenceModule::new(name)?.rebuild_env(&registry, sandbox, env_provider).await?
+                    }
+                }
+                println!("Rebuilt the virtual environment of {}", name);
+            }
+            EnvCommands::Show { name, all } => {
+                let registry = ModuleRegistry::from_config(config).await?;
+                let module_dir = Path::new(installed_module_type(&registry, name).await?.directory()).join(name);
+                let env_vars = dotenv::resolve(Path::new("."), &module_dir, config.profile.as_deref(), &Default::default())?;
+                println!("{:<32} {:<40} SOURCE", "KEY", "VALUE");
+                for (key, value, source) in env_vars.iter() {
+                    if *all || *source != EnvSource::Process {
+                        println!("{:<32} {:<40} {}", key, redact::value(key, value), source);
+                    }
+                }
+            }
+        },
+        Commands::RunInference { name, input, embedded, timeout } => {
             println!("Running inference for module: {}", name);
-            let module_name = name.clone();
-            let module_type = "inference".to_string();
-            let target_script_path = format!("{}/{}/{}.py", "modules", &module_name, &module_name);
+            mark_module_used(config, name).await;
+            if *embedded {
+                let executor = EmbeddedExecutor::start(name)?;
+                let result = executor.process(serde_json::Value::String(input.clone()))?;
+                println!("Inference result: {}", redact::text(&result.to_string()));
+            } else {
+                let module_name = name.clone();
+                let module_type = "inference".to_string();
+                let target_script_path = format!("{}/{}/{}.py", "modules", &module_name, &module_name);
 
-            println!("Target script path: {}", target_script_path);
-            let python_executor =
-                PythonExecutor::new(module_name.clone(), module_type, target_script_path)?; // Use the ? operator to propagate the error
+                println!("Target script path: {}", target_script_path);
+                let python_executor =
+                    PythonExecutor::new(module_name.clone(), module_type, target_script_path, config.sandbox.for_module(name))?; // Use the ? operator to propagate the error
 
-            // Split the input string into a vector of arguments
-            let args = input.to_string();
+                // The input is passed as a single argument
+                let args = [input.to_string()];
 
-            match python_executor.run_command(args) {
-                Ok(result) => println!("Inference result: {}", result),
-                Err(e) => println!("Error running inference: {}", e),
+                let options = run_options(config, name, *timeout, false)?;
+                let result = python_executor.run_command_with(&args, &options).await?;
+                println!("{}", redact::text(&result.to_string()));
+                println!("Inference result: {}", redact::text(&result.check()?.stdout));
             }
         }
-        Commands::ParseConfig { name } => {
+        Commands::ParseConfig { name, answers } => {
+            let answers = answers.load()?;
             let module_dir = PathBuf::from("subnets").join(name);
             println!("Attempting to parse config from: {:?}", module_dir);
             if module_dir.exists() {
@@ -112,48 +276,111 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         print_config(&config);
 
                         // Prompt for environment variables
-                        if let Err(e) = ConfigParser::prompt_for_env_vars(&mut config) {
-                            println!("Error prompting for environment variables: {}", e);
-                        }
+                        ConfigParser::prompt_for_env_vars(&mut config, &answers)?;
 
                         // Save the configuration
                         match ConfigParser::save_config(&config, &module_dir) {
                             Ok(_) => println!("Configuration saved successfully."),
-                            Err(e) => println!("Error saving configuration: {}", e),
+                            Err(e) => println!("Error saving configuration: {}", redact::text(&e.to_string())),
                         }
                     }
                     Err(e) => {
-                        println!("Error parsing configuration: {}", e);
+                        println!("Error parsing configuration: {}", redact::text(&e.to_string()));
                     }
                 }
             } else {
                 println!("Module directory not found: {:?}", module_dir);
             }
         }
-        Commands::LaunchValidator { name, args } => {
-            let mut validator = Validator::new(&name).unwrap();
+        Commands::LaunchValidator { name, args, timeout, answers, detach } => {
+            if detach.detach {
+                return launch_detached(cli, config, ProcessRole::Validator, name, args, *timeout, answers, detach);
+            }
+            let answers = answers.load()?;
+            mark_module_used(config, name).await;
+            let mut validator = Validator::new(name, &answers)?;
 
-            validator.identify_and_prepare_inference(&args)?;
-            let output = validator.launch(if args.is_empty() { None } else { Some(&args) })?;
-            println!("Validator output: {:?}", output);
+            validator.identify_and_prepare_inference(args)?;
+            let options = run_options(config, name, *timeout, detach.supervised)?;
+            validator.launch(args, &answers, &options).await?;
         }
-        Commands::LaunchMiner { name, args } => {
-            let mut miner = Miner::new(&name).unwrap();
+        Commands::LaunchMiner { name, args, timeout, answers, detach } => {
+            if detach.detach {
+                return launch_detached(cli, config, ProcessRole::Miner, name, args, *timeout, answers, detach);
+            }
+            let answers = answers.load()?;
+            mark_module_used(config, name).await;
+            let mut miner = Miner::new(name, &answers)?;
 
-            miner.identify_and_prepare_inference(&args)?;
-            let output = miner.launch(if args.is_empty() { None } else { Some(&args) })?;
-            println!("Miner output: {:?}", output);
+            miner.identify_and_prepare_inference(args)?;
+            let options = run_options(config, name, *timeout, detach.supervised)?;
+            miner.launch(args, &answers, &options).await?;
+        }
+        Commands::StartTranslationAPI { restart } => {
+            let supervisor = Supervisor::default().with_logs(config.logs.clone());
+            let pid = TranslationAPI::new(config.sandbox.for_module("translation"))?.start(&supervisor, *restart, config.profile.clone())?;
+            print_started(&supervisor, translation::PROCESS_NAME, pid)?;
+        }
+        Commands::Ps => print_processes(&Supervisor::default().with_logs(config.logs.clone()).list()?),
+        Commands::Stop { name } => {
+            let supervisor = Supervisor::default().with_logs(config.logs.clone());
+            let process = supervisor.get(name)?;
+            if !process.state.as_ref().is_some_and(ProcessState::is_active) {
+                println!("{} is not running ({})", name, process.status());
+            } else {
+                supervisor.stop(name)?;
+                println!("Stopped {}", name);
+            }
+        }
+        Commands::Restart { name } => {
+            let supervisor = Supervisor::default().with_logs(config.logs.clone());
+            let process = supervisor.get(name)?;
+            if process.state.as_ref().is_some_and(ProcessState::is_active) {
+                supervisor.stop(name)?;
+                println!("Stopped {}", name);
+            }
+            // Answers of a detached launch are in its answers file, read again by the process itself
+            let answers_file = supervisor.process_dir(name).join("answers.yaml");
+            let answers = Answers::load(answers_file.is_file().then_some(answers_file.as_path()), &[], false)?;
+            let env = process_env(cli, config, &process.spec, answers.values())?;
+            let pid = supervisor.start(&process.spec, env)?;
+            print_started(&supervisor, name, pid)?;
         }
-        Commands::StartTranslationAPI => {
-            let mut translation_api = TranslationAPI::new();
-            translation_api.start_with_pm2()?;
+        Commands::Logs { name, follow, since, lines } => {
+            let supervisor = Supervisor::default().with_logs(config.logs.clone());
+            let spec = supervisor.load_spec(name)?;
+            let since = since.as_deref().map(|since| parse_since(since, Utc::now())).transpose()?;
+            let limit = lines.or(if since.is_none() { Some(DEFAULT_LOG_LINES) } else { None });
+
+            // Older lines are in rotated files, newer ones are followed in the current file
+            let path = supervisor.output_log(&spec)?;
+            let mut tail = LogTail::new(&path);
+            let mut initial = logs::read_rotated(&path)?;
+            initial.extend(tail.read_lines()?);
+            for line in filter_lines(initial, since, limit) {
+                println!("{}", line);
+            }
+            // Until interrupted: Ctrl-C ends the command
+            if *follow {
+                loop {
+                    tokio::time::sleep(LOG_FOLLOW_INTERVAL).await;
+                    for line in tail.read_lines()? {
+                        println!("{}", line);
+                    }
+                }
+            }
         }
         Commands::LaunchProxy { ip, port, target_url } => {
             let proxy = proxy::Proxy::new(ip.to_string(), *port, target_url.to_string());
             proxy.run().await?;
         }
         Commands::StartAPI { port } => {
-            API::start("127.0.0.1".to_string(), *port).await?;
+            API::start("127.0.0.1".to_string(), *port, config).await?;
+        }
+        Commands::Supervise { name, dir } => {
+            let cancel = cancel_on_signal("Stopping the supervised process...")?;
+            let state = Supervisor::new(dir).with_logs(config.logs.clone()).supervise(name, &cancel).await?;
+            println!("{} {}", name, state.status);
         }
     }
     Ok(())
@@ -167,7 +394,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 fn print_config(config: &config_parser::ModuleConfig) {
     println!("Environment variables:");
     for (key, value) in &config.env_vars {
-        println!("  {}: {}", key, value);
+        println!("  {}: {}", key, redact::value(key, value));
     }
     println!("Commands:");
     for (command_name, command_config) in &config.commands {
@@ -179,3 +406,377 @@ fn print_config(config: &config_parser::ModuleConfig) {
         }
     }
 }
+
+/// Runs a `catalog` subcommand.
+///
+/// # Arguments
+///
+/// * `command` - The catalog subcommand to run.
+fn run_catalog_command(command: &CatalogCommands) -> Result<()> {
+    let mut catalogs = Catalogs::load_dir(Path::new(CATALOG_DIR))?;
+    match command {
+        CatalogCommands::List { catalog } => {
+            if let Some(name) = catalog {
+                if !catalogs.catalogs.iter().any(|c| &c.name == name) {
+                    return Err(Error::InvalidInput(format!("No catalog named {} in {}", name, CATALOG_DIR)));
+                }
+            }
+            for entry_catalog in &catalogs.catalogs {
+                if catalog.as_ref().is_some_and(|name| name != &entry_catalog.name) {
+                    continue;
+                }
+                println!("{} ({} subnets):", entry_catalog.name, entry_catalog.entries.len());
+                for entry in &entry_catalog.entries {
+                    println!("  {:<24} {}", entry.name, entry.path);
+                }
+            }
+        }
+        CatalogCommands::Search { term } => {
+            let matches = catalogs.search(term);
+            if matches.is_empty() {
+                println!("No subnets match {}", term);
+            }
+            for (catalog, entry) in matches {
+                println!("{:<24} {:<12} {}", entry.name, catalog.name, entry.path);
+            }
+        }
+        CatalogCommands::Add { name, url, catalog } => {
+            url::Url::parse(url)?;
+            let target = catalogs.get_or_create(catalog)?;
+            target.add(CatalogEntry {
+                name: name.clone(),
+                path: url.clone(),
+            })?;
+            target.save()?;
+            println!("Added {} to the {} catalog", name, catalog);
+        }
+        CatalogCommands::Remove { name, catalog } => {
+            let mut removed = false;
+            for entry_catalog in catalogs.catalogs.iter_mut() {
+                if catalog.as_ref().is_some_and(|target| target != &entry_catalog.name) {
+                    continue;
+                }
+                if entry_catalog.remove(name) {
+                    entry_catalog.save()?;
+                    println!("Removed {} from the {} catalog", name, entry_catalog.name);
+                    removed = true;
+                }
+            }
+            if !removed {
+                return Err(Error::ModuleNotFound(name.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Records that a module was used.
+///
+/// Running a module must not depend on the registry, so failures are only reported.
+///
+/// # Arguments
+///
+/// * `config` - The application configuration naming the registry database.
+/// * `name` - The name of the module.
+async fn mark_module_used(config: &Config, name: &str) {
+    let result = match ModuleRegistry::from_config(config).await {
+        Ok(registry) => registry.mark_used(name).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        eprintln!("Warning: could not record usage of {}: {}", name, e);
+    }
+}
+
+/// Determines whether an installed module is a subnet or an inference module.
+///
+/// Modules installed before the registry recorded types are recognized by their directory.
+///
+/// # Arguments
+///
+/// * `registry` - The module registry.
+/// * `name` - The name of the module.
+///
+/// # Returns
+///
+/// A Result containing the module type, or a ModuleNotFound error if the module is not installed.
+async fn installed_module_type(registry: &ModuleRegistry, name: &str) -> Result<ModuleType> {
+    match registry.get(name).await?.and_then(|record| record.module_type) {
+        Some(module_type) => Ok(module_type),
+        None if Path::new(ModuleType::Subnet.directory()).join(name).exists() => Ok(ModuleType::Subnet),
+        None if Path::new(ModuleType::Inference.directory()).join(name).exists() => Ok(ModuleType::Inference),
+        None => Err(Error::ModuleNotFound(name.to_string())),
+    }
+}
+
+/// Builds the run options for a module launched from the command line.
+///
+/// Module processes run in their own process group, so Ctrl-C in the terminal does not
+/// reach them; instead SIGINT and SIGTERM cancel the run, which stops the whole group.
+///
+/// # Arguments
+///
+/// * `config` - The application configuration, giving the sandbox policy and profile.
+/// * `name` - The name of the module.
+/// * `timeout` - The `--timeout` flag in seconds, if given.
+/// * `supervised` - Whether a supervisor runs this command and logs its output, so it is
+///   not logged a second time.
+///
+/// # Returns
+///
+/// A Result containing the RunOptions, or an error if the signal handlers cannot be installed.
+fn run_options(config: &Config, name: &str, timeout: Option<u64>, supervised: bool) -> Result<RunOptions> {
+    Ok(RunOptions {
+        timeout: timeout.map(std::time::Duration::from_secs),
+        cancel: Some(cancel_on_signal("Stopping the module...")?),
+        sandbox: config.sandbox.for_module(name).cloned(),
+        profile: config.profile.clone(),
+        env: Default::default(),
+        log: (!supervised).then(|| config.logs.clone()),
+        supervised,
+    })
+}
+
+/// Returns a handle that is cancelled when this process receives SIGINT or SIGTERM.
+///
+/// # Arguments
+///
+/// * `message` - What is printed when the handle is cancelled.
+///
+/// # Returns
+///
+/// A Result containing the CancelHandle, or an error if the signal handlers cannot be installed.
+fn cancel_on_signal(message: &'static str) -> Result<CancelHandle> {
+    let cancel = CancelHandle::new();
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        let handle = cancel.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = interrupt.recv() => {}
+                _ = terminate.recv() => {}
+            }
+            eprintln!("{}", message);
+            handle.cancel();
+        });
+    }
+    Ok(cancel)
+}
+
+/// Launches a validator or miner in the background under the supervisor.
+///
+/// The supervised process is this program running the same launch command in the
+/// foreground, without prompting and leaving the logging of its output to the supervisor. Answers are handed over in a file only the current
+/// user can read, and the configuration flags through the environment, so neither shows
+/// up in the process list.
+///
+/// # Arguments
+///
+/// * `cli` - The parsed command line, whose configuration flags are passed on.
+/// * `config` - The application configuration, giving the profile.
+/// * `role` - Whether a validator or a miner is launched.
+/// * `name` - The name of the subnet module.
+/// * `args` - The arguments for the validator or miner script.
+/// * `timeout` - The `--timeout` flag in seconds, if given.
+/// * `answer_args` - The `--set`, `--answers` flags.
+/// * `detach` - The name and restart policy of the background process.
+///
+/// # Returns
+///
+/// A Result indicating whether the supervisor was started.
+#[allow(clippy::too_many_arguments)]
+fn launch_detached(
+    cli: &Cli,
+    config: &Config,
+    role: ProcessRole,
+    name: &str,
+    args: &[String],
+    timeout: Option<u64>,
+    answer_args: &AnswerArgs,
+    detach: &DetachArgs,
+) -> Result<()> {
+    let supervisor = Supervisor::default().with_logs(config.logs.clone());
+    let answers = answer_args.load()?;
+    let process_name = detach.process_name.clone().unwrap_or_else(|| format!("{}-{}", name, role));
+
+    let mut command_args = vec![
+        format!("launch-{}", role),
+        name.to_string(),
+        "--non-interactive".to_string(),
+        "--supervised".to_string(),
+    ];
+    if let Some(timeout) = timeout {
+        command_args.extend(["--timeout".to_string(), timeout.to_string()]);
+    }
+    let spec = ProcessSpec {
+        name: process_name.clone(),
+        role,
+        module: name.to_string(),
+        program: std::env::current_exe()?,
+        args: Vec::new(),
+        cwd: std::path::absolute(".")?,
+        restart: detach.restart,
+        port: port_arg(args),
+        // The launch command applies the module's sandbox policy to the script itself
+        sandbox: None,
+    };
+    supervisor.prepare(&spec)?;
+    if !answers.values().is_empty() {
+        let answers_file = supervisor.process_dir(&process_name).join("answers.yaml");
+        answers.write_file(&answers_file)?;
+        command_args.extend(["--answers".to_string(), answers_file.display().to_string()]);
+    }
+    if !args.is_empty() {
+        command_args.push("--".to_string());
+        command_args.extend(args.iter().cloned());
+    }
+    let spec = ProcessSpec { args: command_args, ..spec };
+
+    let env = process_env(cli, config, &spec, answers.values())?;
+    let pid = supervisor.start(&spec, env)?;
+    print_started(&supervisor, &process_name, pid)
+}
+
+/// Composes the environment a supervisor is started with: the module's environment, from
+/// which it learns the secrets to mask in the process's log, and the configuration flags.
+///
+/// # Arguments
+///
+/// * `cli` - The parsed command line, whose configuration flags are passed on.
+/// * `config` - The application configuration, giving the profile.
+/// * `spec` - The process to supervise.
+/// * `overrides` - Variables set over the module's `.env` files.
+///
+/// # Returns
+///
+/// A Result containing the variables, or an error if a `.env` file cannot be parsed.
+fn process_env(
+    cli: &Cli,
+    config: &Config,
+    spec: &ProcessSpec,
+    overrides: &BTreeMap<String, String>,
+) -> Result<Vec<(String, String)>> {
+    let module_type = match spec.role {
+        ProcessRole::Inference => ModuleType::Inference,
+        ProcessRole::Miner | ProcessRole::Validator => ModuleType::Subnet,
+    };
+    let module_dir = Path::new(module_type.directory()).join(&spec.module);
+    let module_env = dotenv::resolve(Path::new("."), &module_dir, config.profile.as_deref(), overrides)?;
+    let mut env: Vec<(String, String)> = module_env.layered().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    env.extend(cli.config_overrides().env_vars().into_iter().map(|(k, v)| (k.to_string(), v)));
+    Ok(env)
+}
+
+/// Returns the port given to a script as `--port <port>` or `--port=<port>`, if any.
+///
+/// # Arguments
+///
+/// * `args` - The arguments of the script.
+fn port_arg(args: &[String]) -> Option<u16> {
+    args.iter().enumerate().find_map(|(i, arg)| match arg.strip_prefix("--port") {
+        Some("") => args.get(i + 1)?.parse().ok(),
+        Some(value) => value.strip_prefix('=')?.parse().ok(),
+        None => None,
+    })
+}
+
+/// Prints the background processes as a table.
+///
+/// # Arguments
+///
+/// * `processes` - The processes to print.
+fn print_processes(processes: &[ManagedProcess]) {
+    if processes.is_empty() {
+        println!("No background processes");
+        return;
+    }
+    println!(
+        "{:<24} {:<8} {:<10} {:<20} {:<8} {:<10} {:<9} PORT",
+        "NAME", "PID", "ROLE", "MODULE", "STATUS", "UPTIME", "RESTARTS"
+    );
+    for process in processes {
+        println!(
+            "{:<24} {:<8} {:<10} {:<20} {:<8} {:<10} {:<9} {}",
+            process.spec.name,
+            process.pid().map_or("-".to_string(), |pid| pid.to_string()),
+            process.spec.role,
+            process.spec.module,
+            process.status(),
+            process.uptime().map_or("-".to_string(), format_uptime),
+            process.state.as_ref().map_or(0, |state| state.restarts),
+            process.spec.port.map_or("-".to_string(), |port| port.to_string()),
+        );
+    }
+}
+
+/// Formats how long a process has been running, with its two largest units, e.g. `2h 5m`.
+fn format_uptime(uptime: std::time::Duration) -> String {
+    let seconds = uptime.as_secs();
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Prints where to find a process started in the background.
+///
+/// # Arguments
+///
+/// * `supervisor` - The supervisor that started the process.
+/// * `name` - The name of the process.
+/// * `pid` - The pid of its supervisor.
+///
+/// # Returns
+///
+/// A Result indicating success, or an error if the process cannot be found.
+fn print_started(supervisor: &Supervisor, name: &str, pid: u32) -> Result<()> {
+    let spec = supervisor.load_spec(name)?;
+    println!("Started {} in the background (supervisor pid {})", name, pid);
+    println!("Output is written to {}", supervisor.output_log(&spec)?.display());
+    Ok(())
+}
+
+/// Prints the installed modules as a table.
+///
+/// # Arguments
+///
+/// * `modules` - The registry records to print.
+fn print_modules(modules: &[ModuleRecord]) {
+    fn format_time(time: Option<DateTime<Utc>>) -> String {
+        time.map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "never".to_string())
+    }
+
+    println!(
+        "{:<24} {:<10} {:<12} {:<32} {:<8} {:<8} LAST USED",
+        "NAME", "TYPE", "VERSION", "PATH", "VENV", "PYTHON"
+    );
+    for module in modules {
+        let venv_dir = PathBuf::from(format!(".{}", module.name));
+        // The marker is written once provisioning succeeded, and names the provider that knows where the interpreter is
+        let venv_status = match environment::marker(&venv_dir) {
+            Some(marker) if marker.provider.provider().interpreter(&venv_dir).exists() => "ok",
+            _ if venv_dir.exists() => "broken",
+            _ => "missing",
+        };
+        println!(
+            "{:<24} {:<10} {:<12} {:<32} {:<8} {:<8} {}",
+            module.name,
+            module.module_type.map(|t| t.as_str()).unwrap_or("unknown"),
+            module.version.as_deref().unwrap_or("-"),
+            module.install_path.as_deref().unwrap_or("-"),
+            venv_status,
+            module.python_version.as_deref().unwrap_or("-"),
+            format_time(module.last_used_at),
+        );
+    }
+}
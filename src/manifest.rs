@@ -0,0 +1,202 @@
+//! Manifest module for the Module Validator application.
+//!
+//! A module or subnet can ship an optional `module.toml` (or `module.yaml`) in its
+//! root directory describing how it is installed, launched and configured:
+//!
+//! ```toml
+//! name = "synthia"
+//! version = "0.3.0"
+//! python = ">=3.10"
+//! environment = "uv"
+//! inference_modules = ["translation"]
+//! setup = ["bash setup.sh", "python -m pip install -e ."]
+//! timeout = 600
+//!
+//! [entry_points]
+//! validator = "synthia/validator/text_validator.py"
+//! miner = "synthia/miner/anthropic.py"
+//!
+//! [env.ANTHROPIC_API_KEY]
+//! description = "API key used by the miner"
+//! required = true
+//! ```
+//!
+//! Everything in the manifest is optional. When a manifest or one of its fields is
+//! absent, callers fall back to their existing heuristics.
+
+use crate::environment::provider::EnvProviderKind;
+use crate::error::{Error, Result};
+use crate::sandbox::SandboxPolicy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The file names a manifest is looked up under, in order of preference.
+pub const MANIFEST_FILES: [&str; 3] = ["module.toml", "module.yaml", "module.yml"];
+
+/// An environment variable declared by a manifest.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnvVarSpec {
+    /// What the variable is used for, shown when prompting.
+    pub description: Option<String>,
+    /// The value used when none is given.
+    pub default: Option<String>,
+    /// Whether installation needs a non-empty value.
+    #[serde(default)]
+    pub required: bool,
+    /// Whether the value is masked in output, for secrets whose names do not look secret.
+    #[serde(default)]
+    pub secret: bool,
+}
+
+/// The declarative description of a module or subnet.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModuleManifest {
+    /// The module name.
+    pub name: Option<String>,
+    /// The module version, recorded in the registry.
+    pub version: Option<String>,
+    /// The supported Python versions, e.g. `>=3.10`.
+    pub python: Option<String>,
+    /// The provider of the module's Python environment (`venv`, `uv`, `conda`, `mamba` or `system`).
+    pub environment: Option<EnvProviderKind>,
+    /// Script paths per role (`validator`, `miner`, `inference`, ...), relative to the module root.
+    #[serde(default)]
+    pub entry_points: HashMap<String, String>,
+    /// Inference modules that must be installed alongside this module.
+    #[serde(default)]
+    pub inference_modules: Vec<String>,
+    /// Environment variables the module reads.
+    #[serde(default)]
+    pub env: BTreeMap<String, EnvVarSpec>,
+    /// Shell commands run from the module root, inside its virtual environment, to set it up.
+    #[serde(default)]
+    pub setup: Vec<String>,
+    /// How many seconds a script of the module may run before it is stopped; unlimited when absent.
+    pub timeout: Option<u64>,
+}
+
+impl ModuleManifest {
+    /// Loads the manifest from a module directory, if it has one.
+    ///
+    /// # Arguments
+    ///
+    /// * `module_dir` - The root directory of the module or subnet.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the manifest, None if the directory has no manifest, or an error if it is invalid.
+    pub fn load(module_dir: &Path) -> Result<Option<Self>> {
+        for file_name in MANIFEST_FILES {
+            let path = module_dir.join(file_name);
+            if path.is_file() {
+                return Self::from_file(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Parses a manifest file, choosing TOML or YAML by its extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the manifest file.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the parsed manifest, or an error if it cannot be read or parsed.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let manifest = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str(&content).map_err(|e| Error::ConfigInvalid(format!("Invalid manifest {}: {}", path.display(), e)))?
+        } else {
+            serde_yaml::from_str(&content).map_err(|e| Error::ConfigInvalid(format!("Invalid manifest {}: {}", path.display(), e)))?
+        };
+        Ok(manifest)
+    }
+
+    /// Returns the script declared for a role, resolved against the module root.
+    ///
+    /// # Arguments
+    ///
+    /// * `module_dir` - The root directory of the module or subnet.
+    /// * `role` - The role to look up, e.g. `validator` or `miner`.
+    pub fn entry_point(&self, module_dir: &Path, role: &str) -> Option<PathBuf> {
+        self.entry_points.get(role).map(|path| module_dir.join(path))
+    }
+
+    /// Checks the variables a single call sets, e.g. the `env` of an API request.
+    ///
+    /// Only variables declared in the `env` table may be set. Variables that decide which
+    /// interpreter, libraries or code run (`PATH`, `VIRTUAL_ENV`, `LD_*`, `DYLD_*` and
+    /// `PYTHON*`) are rejected even when declared.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - The variables set for the call.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success, or an InvalidInput error naming the first variable that cannot be set.
+    pub fn check_call_env(&self, env: &BTreeMap<String, String>) -> Result<()> {
+        for key in env.keys() {
+            if is_interpreter_variable(key) {
+                return Err(Error::InvalidInput(format!("{} cannot be set for a call", key)));
+            }
+            if !self.env.contains_key(key) {
+                return Err(Error::InvalidInput(format!(
+                    "{} is not declared in the env table of the module manifest",
+                    key
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the declared setup steps from the module root with the virtual environment activated.
+    ///
+    /// # Arguments
+    ///
+    /// * `module_dir` - The root directory of the module or subnet.
+    /// * `venv_dir` - The module's virtual environment.
+    /// * `sandbox` - The module's sandbox policy, if it is sandboxed.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating whether every step succeeded.
+    pub fn run_setup(&self, module_dir: &Path, venv_dir: &Path, sandbox: Option<&SandboxPolicy>) -> Result<()> {
+        let venv_dir = fs::canonicalize(venv_dir)?;
+        let path = format!(
+            "{}:{}",
+            venv_dir.join("bin").display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        for step in &self.setup {
+            println!("Running setup step: {}", step);
+            let mut command = Command::new("bash");
+            command.args(["-c", step]).current_dir(module_dir);
+            if let Some(policy) = sandbox {
+                // Scrubs the environment, so it comes before the venv variables
+                policy.apply(&mut command, module_dir)?;
+            }
+            let status = command
+                .env("VIRTUAL_ENV", &venv_dir)
+                .env("PATH", &path)
+                .status()?;
+            if !status.success() {
+                return Err(Error::process_failed(format!("Setup step `{}`", step), status, ""));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether a variable changes which interpreter, libraries or code a process runs.
+fn is_interpreter_variable(key: &str) -> bool {
+    matches!(key, "PATH" | "VIRTUAL_ENV")
+        || key.starts_with("LD_")
+        || key.starts_with("DYLD_")
+        || key.starts_with("PYTHON")
+}
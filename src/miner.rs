@@ -2,15 +2,21 @@
 //!
 //! This module provides functionality for validating and launching subnet modules.
 
-use std::error::Error;
+use crate::answers::Answers;
+use crate::error::{Error, Result};
 use std::fs;
-use std::path::PathBuf;
-use crate::inference::python_executor::PythonExecutor;
+use std::path::{Path, PathBuf};
+use crate::inference::process::ExecutionResult;
+use crate::inference::python_executor::{PythonExecutor, RunOptions};
+use crate::manifest::ModuleManifest;
+use crate::redact;
 use regex::Regex;
 
 /// Represents a miner for subnet modules.
 pub struct Miner {
     pub subnet_name: String,
+    /// The directory holding `subnets/`, `modules/` and the virtual environments; empty for the working directory.
+    pub root_dir: PathBuf,
     pub env_dir: PathBuf,
     pub module_dir: PathBuf,
     pub miner_path: Option<PathBuf>,
@@ -21,49 +27,90 @@ impl Miner {
     ///
     /// # Arguments
     ///
-    /// * `subnet_name` - The name of the subnet to validate.
+    /// * `subnet_name` - The name of the subnet to mine.
+    /// * `answers` - Values given ahead of time, used for the script path if it cannot be found.
     ///
     /// # Returns
     ///
     /// A Result containing the miner if successful, or an error if creation fails.
-    pub fn new(subnet_name: &str) -> Result<Self, Box<dyn Error>> {
+    pub fn new(subnet_name: &str, answers: &Answers) -> Result<Self> {
+        Self::new_in(Path::new(""), subnet_name, answers)
+    }
+
+    /// Creates a new miner instance for a subnet installed under the given root directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_dir` - The directory holding `subnets/`, `modules/` and the virtual environments.
+    /// * `subnet_name` - The name of the subnet to mine.
+    /// * `answers` - Values given ahead of time, used for the script path if it cannot be found.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the miner if successful, or an error if creation fails.
+    pub fn new_in(root_dir: &Path, subnet_name: &str, answers: &Answers) -> Result<Self> {
         println!("Creating new miner for subnet: {}", subnet_name);
-        let env_dir = PathBuf::from(format!(".{}", subnet_name));
-        let module_dir = PathBuf::from("subnets").join(subnet_name);
+        let env_dir = root_dir.join(format!(".{}", subnet_name));
+        let module_dir = root_dir.join("subnets").join(subnet_name);
         
         let mut miner = Self {
             subnet_name: subnet_name.to_string(),
+            root_dir: root_dir.to_path_buf(),
             env_dir,
             module_dir,
             miner_path: None,
         };
 
         
-        miner.find_miner_script()?;
+        miner.find_miner_script(answers)?;
         Ok(miner)
     }
 
-    /// Prompts the user for the path to the miner script.
+    /// Asks for the path to the miner script, using the `MINER_PATH` answer when given.
+    ///
+    /// # Arguments
+    ///
+    /// * `answers` - Values given ahead of time with `--set` or `--answers`.
     ///
     /// # Returns
     ///
-    /// A Result containing the PathBuf of the miner script if successful, or an error if the operation
-    #[allow(dead_code)]
-    pub fn prompt_user_for_path(&self) -> Result<PathBuf, Box<dyn Error>> {
-        let mut miner_path = String::new();
-        println!("Enter the path to the miner script: ");
-        std::io::stdin().read_line(&mut miner_path).unwrap();
-        let miner_path = PathBuf::from(miner_path.trim());
-        Ok(miner_path)
+    /// A Result containing the PathBuf of the miner script if successful, or a MissingValues error when prompting is disabled.
+    pub fn prompt_user_for_path(&self, answers: &Answers) -> Result<PathBuf> {
+        match answers.ask("MINER_PATH", "Enter the path to the miner script", None)? {
+            Some(path) if !path.trim().is_empty() => Ok(PathBuf::from(path.trim())),
+            _ => Err(Error::MissingValues(vec!["MINER_PATH".to_string()])),
+        }
     }
 
     /// Finds the miner script in the module directory.
     ///
+    /// The `miner` entry point of the module manifest is preferred. Without one,
+    /// the module directory is searched for a file named `miner.py`, and as a last
+    /// resort the path is asked for.
+    ///
+    /// # Arguments
+    ///
+    /// * `answers` - Values given ahead of time with `--set` or `--answers`.
+    ///
     /// # Returns
     ///
     /// A Result indicating success or failure of finding the miner script.
-    pub fn find_miner_script(&mut self) -> Result<(), Box<dyn Error>> {
+    pub fn find_miner_script(&mut self, answers: &Answers) -> Result<()> {
         println!("Finding miner script in: {:?}", self.module_dir);
+        if let Some(manifest) = ModuleManifest::load(&self.module_dir)? {
+            if let Some(script_path) = manifest.entry_point(&self.module_dir, "miner") {
+                println!("Using miner entry point from the module manifest: {:?}", script_path);
+                if !script_path.is_file() {
+                    return Err(Error::ScriptNotFound {
+                        role: "miner".to_string(),
+                        path: script_path,
+                    });
+                }
+                self.miner_path = Some(script_path);
+                return Ok(());
+            }
+        }
+
         fn find_script(module_dir: &PathBuf) -> Option<PathBuf> {
             if let Ok(entries) = fs::read_dir(module_dir) {
                 println!("Entries: {:?}", entries);
@@ -90,7 +137,9 @@ impl Miner {
             self.miner_path = Some(script_path);
             Ok(())
         } else {
-            Err("Could not find the miner script".into())
+            let somepath = self.prompt_user_for_path(answers)?;
+            self.miner_path = Some(somepath);
+            Ok(())
         }
     }
 
@@ -103,14 +152,20 @@ impl Miner {
     /// # Returns
     ///
     /// A Result indicating success or failure of the preparation.
-    pub fn identify_and_prepare_inference(&mut self, _args: &String) -> Result<(), Box<dyn Error>> {
+    pub fn identify_and_prepare_inference(&mut self, _args: &[String]) -> Result<()> {
         println!("Preparing inference for subnet: {}", self.subnet_name);
         
-        let script_path = self.miner_path.as_ref().ok_or("miner script not found")?;
+        let script_path = self.miner_path.as_ref().ok_or_else(|| self.script_not_found())?;
         println!("Original script path: {:?}", script_path);
 
         // Adjust the path to be relative to the subnet folder
-        let relative_script_path = script_path.strip_prefix(&self.module_dir)?;
+        let relative_script_path = script_path.strip_prefix(&self.module_dir).map_err(|_| {
+            Error::InvalidInput(format!(
+                "Miner script {} is not inside {}",
+                script_path.display(),
+                self.module_dir.display()
+            ))
+        })?;
         self.miner_path = Some(relative_script_path.to_path_buf());
         
         println!("Adjusted miner path: {:?}", self.miner_path);
@@ -119,19 +174,30 @@ impl Miner {
 
     /// Identifies the inference type in the miner script.
     ///
+    /// The first inference module declared in the module manifest is preferred. Without
+    /// one, the miner script is searched for the name of an installed inference module.
+    ///
     /// # Returns
     ///
     /// A Result containing the inference type if successful, or an error if the operation fails.
-    pub fn identify_inference_type(&self) -> Result<String, Box<dyn Error>> {
-        let miner_path = self.miner_path.as_ref().ok_or("miner path not set")?;
+    pub fn identify_inference_type(&self) -> Result<String> {
+        if let Some(manifest) = ModuleManifest::load(&self.module_dir)? {
+            if let Some(inference_type) = manifest.inference_modules.first() {
+                return Ok(inference_type.clone());
+            }
+        }
+        let miner_path = self.miner_path.as_ref().ok_or_else(|| self.script_not_found())?;
         let content = fs::read_to_string(miner_path)?;
-        for module in fs::read_dir("modules")? {
+        for module in fs::read_dir(self.root_dir.join("modules"))? {
             let module = module.unwrap();
             if content.contains(module.file_name().to_str().unwrap()) {
                 return Ok(module.file_name().to_str().unwrap().to_string());
             }
         }
-        Err("Inference type not found".into())
+        Err(Error::ConfigInvalid(format!(
+            "Could not identify the inference module used by {}; declare it under inference_modules in module.toml",
+            miner_path.display()
+        )))
     }
     
 
@@ -139,37 +205,40 @@ impl Miner {
     ///
     /// # Arguments
     ///
-    /// * `args` - Optional arguments to pass to the miner.
+    /// * `args` - The arguments to pass to the miner, one element per argument.
+    /// * `answers` - Values given with `--set` or `--answers`, exported to the miner's environment.
+    /// * `options` - The timeout and cancel handle for the miner process.
     ///
     /// # Returns
     ///
-    /// A Result indicating success or failure of the miner launch.
-    pub fn launch(&self, args: Option<&String>) -> Result<(), Box<dyn Error>> {
+    /// A Result containing the ExecutionResult of the miner process, or an error if it could not be
+    /// started or did not exit successfully.
+    pub async fn launch(&self, args: &[String], answers: &Answers, options: &RunOptions) -> Result<ExecutionResult> {
         println!("Launching miner for subnet: {}", self.subnet_name);
-        let miner_path = self.miner_path.as_ref().ok_or("miner path not set")?;
+        let miner_path = self.miner_path.as_ref().ok_or_else(|| self.script_not_found())?;
         
         println!("miner path: {:?}", miner_path);
         
-        let executor = PythonExecutor::new(
+        let mut executor = PythonExecutor::new_in(
+            &self.root_dir,
             self.subnet_name.clone(),
             "subnet".to_string(),
             miner_path.to_str().unwrap().to_string(),
+            options.sandbox.as_ref(),
         )?;
+        for (key, value) in answers.values() {
+            executor.set_env(key, value);
+        }
 
         println!("Executing Python command...");
-        let output = match args {
-            Some(arg_str) => executor.run_command(arg_str.to_string())?,
-            None => executor.run_command(String::new())?,
-        };
-        println!("Raw output from Python execution: {:?}", output);
+        let result = executor.run_command_with(args, options).await?;
+        println!("{}", redact::text(&result.to_string()));
 
-        if output.trim().is_empty() {
+        if result.success() && result.stdout.trim().is_empty() {
             println!("Warning: The miner produced no output.");
-        } else {
-            println!("miner output: {}", output);
         }
 
-        Ok(())
+        result.check()
     }
 
     /// Replaces the forward function in the miner script.
@@ -177,15 +246,15 @@ impl Miner {
     /// # Returns
     ///
     /// A Result indicating success or failure of the replacement.
-    pub fn replace_forward(&self) -> Result<(), Box<dyn Error>> {
-        let miner_path = self.miner_path.as_ref().ok_or("Miner script not found")?;
+    pub fn replace_forward(&self) -> Result<()> {
+        let miner_path = self.miner_path.as_ref().ok_or_else(|| self.script_not_found())?;
         println!("Attempting to replace forward function in: {:?}", miner_path);
         
         let content = fs::read_to_string(miner_path)?;
         println!("File content read successfully. Length: {} characters", content.len());
 
         let inference_type = self.identify_inference_type()?;
-        let re = Regex::new(r"(?s)def forward\(.*?\):.*?(\n\S|\z)")?;
+        let re = Regex::new(r"(?s)def forward\(.*?\):.*?(\n\S|\z)").unwrap();
 
         let new_forward = format!(r#"def forward(self, x: dict) -> dict:
     return subprocess.run(["python", "modules/{}/{}.py"], capture_output=True, text=True)
@@ -202,4 +271,12 @@ impl Miner {
 
         Ok(())
     }
+
+    /// Builds the error returned when the miner script cannot be located.
+    fn script_not_found(&self) -> Error {
+        Error::ScriptNotFound {
+            role: "miner".to_string(),
+            path: self.module_dir.clone(),
+        }
+    }
 }
@@ -2,17 +2,27 @@
 //!
 //! This module provides functionality for installing and managing inference modules.
 
-use crate::inference::python_executor::activate_env;
+use crate::answers::Answers;
+use crate::error::{Error, Result};
+use crate::environment::provider::EnvProviderKind;
+use crate::environment::{self, EnvMarker, ModuleEnv};
+use crate::manifest::ModuleManifest;
+use crate::modules::transaction::InstallTransaction;
+use crate::modules::{InstallOptions, UpdateReport};
+use crate::sandbox::SandboxPolicy;
+use crate::utils::{run_command_with_output, run_with_output, set_env_entries, sha256_hex};
+use crate::registry::{ModuleRegistry, ModuleType, NewModule};
 use base64;
 use reqwest;
-use std::error::Error;
 use std::fs;
-use std::io::{BufRead, BufReader, Read};
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::process::Stdio;
 use url::Url;
 
+/// Where inference modules given by name are downloaded from.
+pub const REGISTRAR_URL: &str = "https://registrar-agentartificial.ngrok.dev/modules";
+
 /// Represents an inference module that can be installed and managed.
 pub struct InferenceModule {
     /// The name of the inference module.
@@ -32,27 +42,21 @@ impl InferenceModule {
     ///
     /// # Returns
     ///
-    /// * `Result<Self, Box<dyn Error>>` - Returns an InferenceModule instance if successful, or an error if the input is invalid.
-    pub fn new(input: impl AsRef<str>) -> Result<Self, Box<dyn Error>> {
+    /// * `Result<Self>` - Returns an InferenceModule instance if successful, or an error if the input is invalid.
+    pub fn new(input: impl AsRef<str>) -> Result<Self> {
         let input = input.as_ref();
         let (name, url) = if input.contains("://") {
             // If input is a full URL
             let parsed_url = Url::parse(input)?;
             let name = parsed_url
                 .path_segments()
-                .and_then(|segments| segments.last())
-                .ok_or("Invalid URL: cannot extract inference name")?
+                .and_then(|mut segments| segments.next_back())
+                .ok_or_else(|| Error::InvalidInput(format!("Invalid URL: cannot extract inference name from {}", input)))?
                 .to_string();
             (name, input.to_string())
         } else {
             // If input is just a module name
-            (
-                input.to_string(),
-                format!(
-                    "https://registrar-agentartificial.ngrok.dev/modules/{}",
-                    input
-                ),
-            )
+            (input.to_string(), format!("{}/{}", REGISTRAR_URL, input))
         };
 
         let root_dir = PathBuf::from(".");
@@ -63,66 +67,238 @@ impl InferenceModule {
         })
     }
 
+    /// Installs the module under the given root directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_dir` - The directory holding `modules/`, the virtual environments and `.env`.
+    pub fn with_root_dir(mut self, root_dir: impl Into<PathBuf>) -> Self {
+        self.root_dir = root_dir.into();
+        self
+    }
+
     /// Installs the inference module.
     ///
     /// This function performs the following steps:
     /// 1. Downloads the module script from the server.
     /// 2. Decodes the script content if it's base64 encoded.
-    /// 3. Saves the script to the appropriate directory.
-    /// 4. Creates a Python virtual environment if it doesn't exist.
-    /// 5. Runs the setup script in the virtual environment.
-    /// 6. Executes any additional installation scripts.
+    /// 3. Saves the script to a staging directory and moves it to `modules/<name>`.
+    /// 4. Provisions the Python virtual environment, unless it is already up to date.
+    /// 5. Runs the manifest's setup steps, or the setup script in the virtual environment.
+    /// 6. Executes any additional installation scripts when there is no manifest.
+    /// 7. Writes the API port and host to `.env`, taken from the answers or prompted for.
+    /// 8. Records the module in the registry.
+    ///
+    /// If any step fails, the downloaded module directory, a newly created virtual
+    /// environment and the `.env` edits are rolled back (the first two are kept aside
+    /// with `options.keep_failed`).
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry the installed module is recorded in.
+    /// * `options` - Options controlling the install.
     ///
     /// # Returns
     ///
-    /// * `Result<(), Box<dyn Error>>` - Returns Ok(()) if the installation is successful, or an error if any step fails.
-    pub async fn install(&self) -> Result<(), Box<dyn Error>> {
+    /// * `Result<()>` - Returns Ok(()) if the installation is successful, or an error if any step fails.
+    pub async fn install(&self, registry: &ModuleRegistry, options: &InstallOptions) -> Result<()> {
         println!("Installing inference module: {}", self.name);
 
-        let module_dir = self.root_dir.join("modules".to_string()).join(&self.name);
+        let module_dir = self.root_dir.join("modules").join(&self.name);
+        let mut transaction = InstallTransaction::begin(&self.name, options.keep_failed);
 
         if module_dir.exists() {
             println!("Module directory already exists. Updating requirements...");
         } else {
-            fs::create_dir_all(&module_dir)?;
+            let staging_dir = transaction.stage(&self.root_dir.join("modules"))?;
+            self.download(&staging_dir).await?;
+            transaction.promote(&module_dir)?;
+        }
+        let env_path = self.env_dir();
+        transaction.track_venv(&env_path);
+
+        let sandbox = options.sandbox.for_module(&self.name);
+        let module_env =
+            ModuleEnv::new(&env_path, &module_dir)?.with_provider(options.env_providers.for_module(&self.name));
+        println!("Virtual environment: {}", module_env.provision(sandbox)?);
+        let python_executable = module_env.python();
+
+        let manifest = ModuleManifest::load(&module_dir)?;
+        match manifest.as_ref().filter(|manifest| !manifest.setup.is_empty()) {
+            Some(manifest) => {
+                println!("Running setup steps from the module manifest");
+                manifest.run_setup(&module_dir, &env_path, sandbox)?;
+            }
+            None => self.run_default_setup(&module_dir, &python_executable, sandbox)?,
+        }
+
+        transaction.track_file(&self.env_file())?;
+        self.configure_api(&options.answers)?;
+
+        self.register(registry, &module_dir).await?;
+        transaction.commit();
+
+        println!("Inference module installed/updated successfully");
+        Ok(())
+    }
+
+    /// Updates an installed module by re-fetching its bundle from the registrar and
+    /// running its setup again.
+    ///
+    /// The bundle is downloaded into a staged copy of the module directory, which replaces
+    /// the installed one. If any step fails, the previous module directory is restored.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry the updated module is recorded in.
+    /// * `sandbox` - The module's sandbox policy for its requirements and setup scripts, if it is sandboxed.
+    /// * `env_provider` - The environment provider configured for the module, if any.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the old and new versions, or an error if the module is not installed or any step fails.
+    pub async fn update(
+        &self,
+        registry: &ModuleRegistry,
+        sandbox: Option<&SandboxPolicy>,
+        env_provider: Option<EnvProviderKind>,
+    ) -> Result<UpdateReport> {
+        let module_dir = self.root_dir.join("modules").join(&self.name);
+        if !module_dir.exists() {
+            return Err(Error::ModuleNotFound(self.name.clone()));
+        }
+        let old_version = self.version(&module_dir)?;
+
+        println!("Updating inference module: {}", self.name);
+        let mut transaction = InstallTransaction::begin(&self.name, false);
+        let staging_dir = transaction.stage_copy(&module_dir)?;
+        self.download(&staging_dir).await?;
+        transaction.replace(&module_dir)?;
+
+        let env_path = self.env_dir();
+        let module_env = ModuleEnv::new(&env_path, &module_dir)?.with_provider(env_provider);
+        println!("Virtual environment: {}", module_env.provision(sandbox)?);
+        let python_executable = module_env.python();
+        match ModuleManifest::load(&module_dir)?.filter(|manifest| !manifest.setup.is_empty()) {
+            Some(manifest) => manifest.run_setup(&module_dir, &env_path, sandbox)?,
+            None => self.run_default_setup(&module_dir, &python_executable, sandbox)?,
+        }
+        self.register(registry, &module_dir).await?;
+        transaction.commit();
+
+        Ok(UpdateReport {
+            name: self.name.clone(),
+            old_version,
+            new_version: self.version(&module_dir)?,
+        })
+    }
+
+    /// Deletes the module's virtual environment, provisions it from scratch and runs the
+    /// module's setup again.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry the module's new interpreter is recorded in.
+    /// * `sandbox` - The module's sandbox policy for its requirements and setup scripts, if it is sandboxed.
+    /// * `env_provider` - The environment provider configured for the module, if any.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success, or an error if the module is not installed or any step fails.
+    pub async fn rebuild_env(
+        &self,
+        registry: &ModuleRegistry,
+        sandbox: Option<&SandboxPolicy>,
+        env_provider: Option<EnvProviderKind>,
+    ) -> Result<()> {
+        let module_dir = self.root_dir.join("modules").join(&self.name);
+        if !module_dir.exists() {
+            return Err(Error::ModuleNotFound(self.name.clone()));
+        }
+        let env_path = self.env_dir();
+        let module_env = ModuleEnv::new(&env_path, &module_dir)?.with_provider(env_provider);
+        module_env.rebuild(sandbox)?;
+        match ModuleManifest::load(&module_dir)?.filter(|manifest| !manifest.setup.is_empty()) {
+            Some(manifest) => manifest.run_setup(&module_dir, &env_path, sandbox)?,
+            None => self.run_default_setup(&module_dir, &module_env.python(), sandbox)?,
+        }
+        self.register(registry, &module_dir).await
+    }
 
-            let url = format!(
-                "https://registrar-cellium.ngrok.dev/modules/{}",
-                self.name
-            );
-            let response = reqwest::get(&url).await?.text().await?;
+    /// Downloads the module's setup script from its URL into `module_dir`.
+    async fn download(&self, module_dir: &Path) -> Result<()> {
+        let response = reqwest::get(&self.url).await?.text().await?;
 
-            println!("Received response: {:?}", response);
+        let cleaned_response = response
+            .trim_matches('"')
+            .replace("\\", "")
+            .replace("\"", "");
 
-            let cleaned_response = response
-                .trim_matches('"')
-                .replace("\\", "")
-                .replace("\"", "");
+        let decoded_content = match base64::decode(cleaned_response.clone()) {
+            Ok(content) => String::from_utf8(content)
+                .map_err(|e| Error::InvalidInput(format!("Module {} is not valid UTF-8: {}", self.name, e)))?,
+            Err(_) => cleaned_response.to_string(),
+        };
 
-            let decoded_content = match base64::decode(cleaned_response.clone()) {
-                Ok(content) => String::from_utf8(content)
-                    .map_err(|e| format!("Failed to convert decoded bytes to UTF-8: {}", e))?,
-                Err(_) => cleaned_response.to_string(),
-            };
+        // The script may embed credentials, so only its size is logged
+        println!("Downloaded setup script ({} bytes)", decoded_content.len());
 
-            println!("Decoded/cleaned script content:\n{}", decoded_content);
+        let script_name = format!("setup_{}.py", self.name);
+        let script_path = module_dir.join(&script_name);
+        fs::write(&script_path, &decoded_content).map_err(|e| {
+            io::Error::new(e.kind(), format!("Failed to write script to {}: {}", script_path.display(), e))
+        })?;
 
-            let script_name = format!("setup_{}.py", self.name);
-            let script_path = module_dir.join(&script_name);
-            fs::write(&script_path, &decoded_content).map_err(|e| {
-                format!("Failed to write script to {}: {}", script_path.display(), e)
-            })?;
+        println!("Script saved to: {}", script_path.display());
+        Ok(())
+    }
 
-            println!("Script saved to: {}", script_path.display());
+    /// Returns the installed version: the manifest version, or a digest of the downloaded setup script.
+    fn version(&self, module_dir: &Path) -> Result<Option<String>> {
+        if let Some(version) = ModuleManifest::load(module_dir)?.and_then(|manifest| manifest.version) {
+            return Ok(Some(version));
         }
-        let env_path = PathBuf::from(format!(".{}", self.name));
+        let setup_script = module_dir.join(format!("setup_{}.py", self.name));
+        if !setup_script.exists() {
+            return Ok(None);
+        }
+        let digest = sha256_hex(fs::read(setup_script)?);
+        Ok(Some(format!("sha256:{}", &digest[..12])))
+    }
 
-        let python_executable = activate_env(&env_path)?;
+    /// Records the module in the registry.
+    async fn register(&self, registry: &ModuleRegistry, module_dir: &Path) -> Result<()> {
+        let entry_point = ModuleManifest::load(module_dir)?
+            .and_then(|manifest| manifest.entry_point(module_dir, "inference"))
+            .unwrap_or_else(|| module_dir.join(format!("{}.py", self.name)));
+        let python = environment::marker(&self.env_dir());
+        registry
+            .register(&NewModule {
+                name: self.name.clone(),
+                module_type: ModuleType::Inference,
+                version: self.version(module_dir)?,
+                entry_point: Some(entry_point.to_string_lossy().into_owned()),
+                install_path: module_dir.to_string_lossy().into_owned(),
+                python_version: python.as_ref().map(EnvMarker::python_version),
+                python_path: python.and_then(|marker| marker.python).map(|path| path.to_string_lossy().into_owned()),
+            })
+            .await
+    }
 
+    /// Sets up a module without a manifest by running `setup_<name>.py` and `install_<name>.sh` when present.
+    ///
+    /// Both scripts come from the registrar, so they run under the module's sandbox policy when it has one.
+    fn run_default_setup(&self, module_dir: &Path, python_executable: &Path, sandbox: Option<&SandboxPolicy>) -> Result<()> {
         // Run setup_MODULE_NAME.py
         let setup_script = module_dir.join(format!("setup_{}.py", self.name.clone()));
         if setup_script.exists() {
-            self.run_command_with_output(&python_executable, &[setup_script.to_str().unwrap()])?;
+            // Absolute paths, since a sandboxed script runs from the module directory
+            let mut command = Command::new(std::path::absolute(python_executable)?);
+            command.arg(std::path::absolute(&setup_script)?);
+            if let Some(policy) = sandbox {
+                policy.apply(&mut command, module_dir)?;
+            }
+            run_with_output(&mut command)?;
             println!("Setup_{}.py executed successfully", self.name.clone());
         } else {
             println!("Setup_{}.py not found", self.name.clone());
@@ -131,83 +307,69 @@ impl InferenceModule {
         // make install_MODULE_NAME.sh executable and run it
         let install_script = module_dir.join(format!("install_{}.sh", self.name.clone()));
         if install_script.exists() {
-            self.run_command_with_output("chmod", &["+x", install_script.to_str().unwrap()])?;
-            self.run_command_with_output("bash", &[install_script.to_str().unwrap()])?;
+            run_command_with_output("chmod", &["+x", install_script.to_str().unwrap()])?;
+            let mut command = Command::new("bash");
+            command.arg(std::path::absolute(&install_script)?);
+            if let Some(policy) = sandbox {
+                policy.apply(&mut command, module_dir)?;
+            }
+            run_with_output(&mut command)?;
             println!("install_{}.sh executed successfully", self.name.clone());
         } else {
             println!("install_{}.sh not found", self.name.clone());
         }
-
-        // Prompt the user for API_PORT and API_HOST
-        self.prompt_user(&format!("Enter the API_PORT for {}", self.name));
-        self.prompt_user(&format!("Enter the API_HOST for {}", self.name));
-
-        println!("Inference module installed/updated successfully");
         Ok(())
     }
 
-    fn prompt_user(&self, prompt: &str) -> () {
-        use std::fs::OpenOptions;
-        use std::io::{self, Write};
-
-        print!("{}: ", prompt);
-        io::stdout().flush().unwrap();
+    /// Returns the module's virtual environment, `.<name>`.
+    fn env_dir(&self) -> PathBuf {
+        self.root_dir.join(format!(".{}", self.name))
+    }
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        let mut result_string = input.trim().to_string();
+    /// Returns the `.env` file the module's host and port are written to.
+    fn env_file(&self) -> PathBuf {
+        self.root_dir.join(".env")
+    }
 
-        if prompt.contains("PORT") {
-            result_string = format!("{}_API_PORT", self.name.to_uppercase());
-        }
-        if prompt.contains("HOST") {
-            result_string = format!("{}_API_HOST", self.name.to_uppercase());
+    /// Asks for the module's API port and host and writes them to `.env` as
+    /// `<NAME>_API_PORT` and `<NAME>_API_HOST`.
+    ///
+    /// # Arguments
+    ///
+    /// * `answers` - Values given ahead of time with `--set` or `--answers`.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success, or a MissingValues error when prompting is disabled and a value was not given.
+    fn configure_api(&self, answers: &Answers) -> Result<()> {
+        let mut entries = Vec::new();
+        let mut missing = Vec::new();
+        for setting in API_SETTINGS {
+            let key = api_env_key(&self.name, setting);
+            let prompt = format!("Enter the {} for {}", setting, self.name);
+            match answers.ask(&key, &prompt, None)? {
+                Some(value) if !value.trim().is_empty() => entries.push((key, value.trim().to_string())),
+                _ if answers.is_interactive() => println!("No {} given, leaving it unset", key),
+                _ => missing.push(key),
+            }
         }
-        let env_file = PathBuf::from(".env".to_string());
-        if env_file.exists() {
-            let mut file = OpenOptions::new().append(true).open(env_file).unwrap();
-            writeln!(file, "{}", result_string).unwrap();
-        } else {
-            let mut file = fs::File::create(env_file).unwrap();
-            writeln!(file, "{}", result_string).unwrap();
+        if !missing.is_empty() {
+            return Err(Error::MissingValues(missing));
         }
+        set_env_entries(&self.env_file(), &entries)?;
+        Ok(())
     }
+}
 
-    fn run_command_with_output(&self, command: &str, args: &[&str]) -> Result<(), Box<dyn Error>> {
-        let mut child = Command::new(command)
-            .args(args)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-
-        let stdout = child.stdout.take().expect("Failed to open stdout");
-        let stderr = child.stderr.take().expect("Failed to open stderr");
-
-        let stdout_reader = BufReader::new(stdout);
-        let stderr_reader = BufReader::new(stderr);
-
-        std::thread::spawn(move || {
-            stdout_reader.lines().for_each(|line| {
-                if let Ok(line) = line {
-                    println!("{}", line);
-                }
-            });
-        });
-
-        std::thread::spawn(move || {
-            stderr_reader.lines().for_each(|line| {
-                if let Ok(line) = line {
-                    eprintln!("{}", line);
-                }
-            });
-        });
-
-        let status = child.wait()?;
-
-        if status.success() {
-            Ok(())
-        } else {
-            Err(format!("Command failed with exit code: {}", status).into())
-        }
-    }
+/// The settings written to `.env` for each inference module, as `<NAME>_<SETTING>`.
+pub const API_SETTINGS: [&str; 2] = ["API_PORT", "API_HOST"];
+
+/// Returns the `.env` key of a module's setting, e.g. `TRANSLATION_API_PORT`.
+///
+/// # Arguments
+///
+/// * `name` - The name of the module.
+/// * `setting` - One of [`API_SETTINGS`].
+pub fn api_env_key(name: &str, setting: &str) -> String {
+    format!("{}_{}", name.to_uppercase(), setting)
 }
@@ -1,3 +1,56 @@
+//! Module management for the Module Validator application.
+//!
+//! This module groups the installable module kinds and the operations on them.
+
 pub mod inference_module;
 pub mod subnet_module;
+pub mod transaction;
+pub mod uninstall;
+
+use crate::answers::Answers;
+use crate::environment::provider::EnvProviders;
+use crate::sandbox::SandboxPolicies;
+use std::fmt;
+
+/// Options controlling how modules are installed.
+#[derive(Debug, Clone, Default)]
+pub struct InstallOptions {
+    /// Keep the module tree and virtual environment of a failed install for debugging.
+    pub keep_failed: bool,
+    /// Values for the prompts shown during the install.
+    pub answers: Answers,
+    /// Sandbox policies for the setup scripts of the installed modules.
+    pub sandbox: SandboxPolicies,
+    /// Python environment providers for the installed modules, overriding their manifests.
+    pub env_providers: EnvProviders,
+}
+
+/// The outcome of updating an installed module.
+#[derive(Debug)]
+pub struct UpdateReport {
+    /// The name of the module.
+    pub name: String,
+    /// The version before the update, if known.
+    pub old_version: Option<String>,
+    /// The version after the update, if known.
+    pub new_version: Option<String>,
+}
+
+impl UpdateReport {
+    /// Returns true when the update changed the installed version.
+    pub fn changed(&self) -> bool {
+        self.old_version != self.new_version
+    }
+}
 
+impl fmt::Display for UpdateReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let old_version = self.old_version.as_deref().unwrap_or("unknown");
+        let new_version = self.new_version.as_deref().unwrap_or("unknown");
+        if self.changed() {
+            write!(f, "{} updated from {} to {}", self.name, old_version, new_version)
+        } else {
+            write!(f, "{} is already up to date ({})", self.name, new_version)
+        }
+    }
+}
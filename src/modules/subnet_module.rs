@@ -1,12 +1,18 @@
-use std::error::Error;
 use std::process::Command;
+use crate::error::{Error, Result};
 use url::Url;
 use std::path::{Path, PathBuf};
 use std::collections::HashSet;
 use dialoguer::{MultiSelect, Confirm};
+use crate::environment::provider::EnvProviderKind;
+use crate::environment::{self, EnvMarker, ModuleEnv};
+use crate::manifest::ModuleManifest;
 use crate::modules::inference_module::InferenceModule;
-use std::io::{BufRead, BufReader};
-use std::process::Stdio;
+use crate::modules::transaction::InstallTransaction;
+use crate::modules::{InstallOptions, UpdateReport};
+use crate::registry::{ModuleRegistry, ModuleType, NewModule};
+use crate::sandbox::SandboxPolicy;
+use crate::utils::{run_command_with_output, run_with_output};
 
 /// Represents a subnet module that can be installed and managed.
 pub struct SubnetModule {
@@ -16,6 +22,8 @@ pub struct SubnetModule {
     pub url: String,
     /// The set of required inference modules for this subnet module.
     pub required_inference_modules: HashSet<String>,
+    /// The directory holding `subnets/` and the virtual environments; empty for the working directory.
+    pub root_dir: PathBuf,
 }
 
 impl SubnetModule {
@@ -27,8 +35,8 @@ impl SubnetModule {
     /// * `name` - The name of the subnet module.
     /// # Returns
     ///
-    /// * `Result<Self, Box<dyn Error>>` - Returns a SubnetModule instance if successful, or an error if the URL is invalid.
-    pub fn new(url: impl AsRef<str>, name: &str) -> Result<Self, Box<dyn Error>> {
+    /// * `Result<Self>` - Returns a SubnetModule instance if successful, or an error if the URL is invalid.
+    pub fn new(url: impl AsRef<str>, name: &str) -> Result<Self> {
         let url = url.as_ref();
         let parsed_url = Url::parse(url)?;
 
@@ -36,82 +44,274 @@ impl SubnetModule {
             name: name.to_string(), 
             url: parsed_url.to_string(),
             required_inference_modules: HashSet::new(),
+            root_dir: PathBuf::new(),
+        })
+    }
+
+    /// Installs the subnet, and the inference modules it requires, under the given root directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_dir` - The directory holding `subnets/`, `modules/`, the virtual environments and `.env`.
+    pub fn with_root_dir(mut self, root_dir: impl Into<PathBuf>) -> Self {
+        self.root_dir = root_dir.into();
+        self
+    }
+
+    /// Creates a SubnetModule for an installed subnet, with the URL its checkout was cloned from.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the subnet module.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the SubnetModule, a ModuleNotFound error if the subnet is not
+    /// installed, or an InvalidInput error if its checkout records no origin.
+    pub fn installed(name: &str) -> Result<Self> {
+        let module_dir = PathBuf::from("subnets").join(name);
+        if !module_dir.exists() {
+            return Err(Error::ModuleNotFound(name.to_string()));
+        }
+        let url = git_output(&module_dir, &["remote", "get-url", "origin"])
+            .map_err(|_| Error::InvalidInput(format!("Subnet module {} has no origin remote; reinstall it from its URL", name)))?;
+        Ok(SubnetModule {
+            name: name.to_string(),
+            url: url.trim().to_string(),
+            required_inference_modules: HashSet::new(),
+            root_dir: PathBuf::new(),
         })
     }
 
     /// Installs the subnet module.
     ///
     /// This function performs the following steps:
-    /// 1. Clones the repository from the provided URL.
-    /// 2. Runs the setup script if it exists.
-    /// 3. Installs Python requirements if a requirements.txt file is present.
-    /// 4. Prompts the user to select required inference modules.
-    /// 5. Installs the selected inference modules.
+    /// 1. Clones the repository into a staging directory and moves it to `subnets/<name>`.
+    /// 2. Provisions the `.<name>` virtual environment with the package and its requirements.txt.
+    /// 3. Runs the manifest's setup steps, or the setup script if there is no manifest.
+    /// 4. Collects the required inference modules declared in the module manifest.
+    /// 5. Installs the required inference modules.
+    /// 6. Records the module in the registry.
+    ///
+    /// If any step fails, the module directory and a newly created virtual environment
+    /// are removed again (or kept aside with `options.keep_failed`).
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry the installed module is recorded in.
+    /// * `options` - Options controlling the install.
     ///
     /// # Returns
     ///
-    /// * `Result<(), Box<dyn Error>>` - Returns Ok(()) if the installation is successful, or an error if any step fails.
-    pub async fn install(&mut self) -> Result<(), Box<dyn Error>> {
+    /// * `Result<()>` - Returns Ok(()) if the installation is successful, or an error if any step fails.
+    pub async fn install(&mut self, registry: &ModuleRegistry, options: &InstallOptions) -> Result<()> {
         println!("Installing subnet module: {}", self.name);
 
-        let module_dir = PathBuf::from("subnets").join(&self.name);
+        let module_dir = self.module_dir();
 
         if module_dir.exists() {
             println!("Subnet module {} is already installed.", self.name);
+            self.register(registry, &module_dir).await?;
             return Ok(());
-        } else{
-            self.run_command_with_output("git", &["clone", &self.url, &module_dir.to_string_lossy()])?;
         }
 
-        let env_dir = PathBuf::from(format!(".{}", self.name));
-        self.run_command_with_output("python", &["-m", "venv", env_dir.to_str().unwrap()])?;
+        let mut transaction = InstallTransaction::begin(&self.name, options.keep_failed);
+        let staging_dir = transaction.stage(&self.root_dir.join("subnets"))?;
+        run_command_with_output("git", &["clone", &self.url, &staging_dir.to_string_lossy()])?;
+        transaction.promote(&module_dir)?;
+        println!("Repository cloned successfully");
 
-        let python_executable = env_dir.join("bin").join("python");
+        let env_dir = self.env_dir();
+        transaction.track_venv(&env_dir);
+        let sandbox = options.sandbox.for_module(&self.name);
+        // Creates the environment and installs the package and its requirements.txt
+        let module_env =
+            ModuleEnv::new(&env_dir, &module_dir)?.with_provider(options.env_providers.for_module(&self.name));
+        println!("Virtual environment: {}", module_env.provision(sandbox)?);
 
-        
+        let manifest = ModuleManifest::load(&module_dir)?;
+        match manifest.as_ref().filter(|manifest| !manifest.setup.is_empty()) {
+            Some(manifest) => {
+                println!("Running setup steps from the module manifest");
+                manifest.run_setup(&module_dir, &env_dir, sandbox)?;
+            }
+            None => self.run_default_setup(&module_dir, sandbox)?,
+        }
 
-        println!("Repository cloned successfully");
+        if let Some(manifest) = &manifest {
+            self.required_inference_modules.extend(manifest.inference_modules.iter().cloned());
+        }
+        for inference_name in &self.required_inference_modules {
+            println!("Installing required inference module: {}", inference_name);
+            InferenceModule::new(inference_name)?
+                .with_root_dir(&self.root_dir)
+                .install(registry, options)
+                .await?;
+        }
 
+        // self.prompt_for_inference_modules(registry).await?;
+
+        self.register(registry, &module_dir).await?;
+        transaction.commit();
+
+        println!("Subnet module installed successfully");
+        Ok(())
+    }
+
+    /// Sets up a subnet without a manifest by running `setup.sh` if present, under the
+    /// subnet's sandbox policy when it has one.
+    fn run_default_setup(&self, module_dir: &Path, sandbox: Option<&SandboxPolicy>) -> Result<()> {
         let setup_script = module_dir.join("setup.sh");
         match setup_script.exists() {
             true => {
                 println!("Running setup script");
-                self.run_command_with_output("bash", &[setup_script.to_str().unwrap()])?;
+                let mut command = Command::new("bash");
+                command.arg(std::path::absolute(&setup_script)?);
+                if let Some(policy) = sandbox {
+                    policy.apply(&mut command, module_dir)?;
+                }
+                run_with_output(&mut command)?;
                 println!("Setup script executed successfully");
             }
             false => {
                 println!("No setup script found");
             }
         }
-        
-        println!("Installing package in editable mode");
-        match self.run_command_with_output(&python_executable.to_path_buf().to_str().unwrap(), &["-m", "pip", "install", "-e", "."]) {
-            Ok(_) => println!("Package installed in editable mode successfully"),
-            Err(e) => {
-                eprintln!("Warning: Failed to install package in editable mode: {}", e);
-                println!("Continuing with installation process...");
-            }
+        Ok(())
+    }
+
+    /// Updates an installed subnet to the latest upstream commit, or to `git_ref` when given,
+    /// and reinstalls its requirements into the `.<name>` virtual environment if they changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry the updated module is recorded in.
+    /// * `git_ref` - An optional branch, tag or commit to check out.
+    /// * `sandbox` - The subnet's sandbox policy for its requirements and manifest setup steps, if it is sandboxed.
+    /// * `env_provider` - The environment provider configured for the subnet, if any.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the old and new versions, or an error if the checkout has local changes or any step fails.
+    pub async fn update(
+        &self,
+        registry: &ModuleRegistry,
+        git_ref: Option<&str>,
+        sandbox: Option<&SandboxPolicy>,
+        env_provider: Option<EnvProviderKind>,
+    ) -> Result<UpdateReport> {
+        let module_dir = self.module_dir();
+        if !module_dir.exists() {
+            return Err(Error::ModuleNotFound(self.name.clone()));
         }
 
-        let requirements_file = module_dir.join("requirements.txt");
-        match self.run_command_with_output(&python_executable.to_path_buf().to_str().unwrap(), &["-m", "pip", "install", "-r", requirements_file.to_str().unwrap()]) {
-            Ok(_) => println!("Python requirements installed successfully"),
-            Err(e) => {
-                eprintln!("Warning: Failed to install Python requirements: {}", e);
-                println!("Continuing with installation process...");
-            }
+        let changes = git_output(&module_dir, &["status", "--porcelain"])?;
+        if !changes.trim().is_empty() {
+            return Err(Error::LocalChanges {
+                name: self.name.clone(),
+                changes: changes.trim_end().to_string(),
+            });
         }
 
+        let old_version = Self::version(&module_dir)?;
+        println!("Updating subnet module: {}", self.name);
 
+        let dir = module_dir.to_string_lossy();
+        run_command_with_output("git", &["-C", &dir, "fetch", "--tags", "origin"])?;
+        if let Some(git_ref) = git_ref {
+            run_command_with_output("git", &["-C", &dir, "checkout", git_ref])?;
+        }
+        if git_output(&module_dir, &["rev-parse", "--abbrev-ref", "@{u}"]).is_ok() {
+            run_command_with_output("git", &["-C", &dir, "merge", "--ff-only", "@{u}"])?;
+        } else if git_ref.is_none() {
+            return Err(Error::InvalidInput(format!(
+                "Subnet module {} is not on a branch that tracks a remote; pass --ref to choose what to check out",
+                self.name
+            )));
+        }
 
-        // self.prompt_for_inference_modules().await?;
+        // Reinstalls the requirements only if the update changed them
+        let env_dir = self.env_dir();
+        let module_env = ModuleEnv::new(&env_dir, &module_dir)?.with_provider(env_provider);
+        println!("Virtual environment: {}", module_env.provision(sandbox)?);
+        if let Some(manifest) = ModuleManifest::load(&module_dir)?.filter(|manifest| !manifest.setup.is_empty()) {
+            manifest.run_setup(&module_dir, &env_dir, sandbox)?;
+        }
+        self.register(registry, &module_dir).await?;
 
-        println!("Subnet module installed successfully");
-        Ok(())
+        Ok(UpdateReport {
+            name: self.name.clone(),
+            old_version,
+            new_version: Self::version(&module_dir)?,
+        })
+    }
+
+    /// Deletes the subnet's virtual environment, provisions it from scratch and runs the
+    /// subnet's setup again.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry the subnet's new interpreter is recorded in.
+    /// * `sandbox` - The subnet's sandbox policy for its requirements and setup scripts, if it is sandboxed.
+    /// * `env_provider` - The environment provider configured for the subnet, if any.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success, or an error if the subnet is not installed or any step fails.
+    pub async fn rebuild_env(
+        &self,
+        registry: &ModuleRegistry,
+        sandbox: Option<&SandboxPolicy>,
+        env_provider: Option<EnvProviderKind>,
+    ) -> Result<()> {
+        let module_dir = self.module_dir();
+        if !module_dir.exists() {
+            return Err(Error::ModuleNotFound(self.name.clone()));
+        }
+        let env_dir = self.env_dir();
+        ModuleEnv::new(&env_dir, &module_dir)?.with_provider(env_provider).rebuild(sandbox)?;
+        match ModuleManifest::load(&module_dir)?.filter(|manifest| !manifest.setup.is_empty()) {
+            Some(manifest) => manifest.run_setup(&module_dir, &env_dir, sandbox)?,
+            None => self.run_default_setup(&module_dir, sandbox)?,
+        }
+        self.register(registry, &module_dir).await
+    }
+
+    /// Returns the directory the subnet is cloned into, `subnets/<name>`.
+    fn module_dir(&self) -> PathBuf {
+        self.root_dir.join("subnets").join(&self.name)
+    }
+
+    /// Returns the subnet's virtual environment, `.<name>`.
+    fn env_dir(&self) -> PathBuf {
+        self.root_dir.join(format!(".{}", self.name))
+    }
+
+    /// Returns the installed version: the manifest version, or the checked out commit.
+    fn version(module_dir: &Path) -> Result<Option<String>> {
+        let manifest_version = ModuleManifest::load(module_dir)?.and_then(|manifest| manifest.version);
+        Ok(manifest_version.or_else(|| git_revision(module_dir)))
+    }
+
+    /// Records the subnet in the registry. The manifest version is preferred over the checked out commit.
+    async fn register(&self, registry: &ModuleRegistry, module_dir: &Path) -> Result<()> {
+        let python = environment::marker(&self.env_dir());
+        registry
+            .register(&NewModule {
+                name: self.name.clone(),
+                module_type: ModuleType::Subnet,
+                version: Self::version(module_dir)?,
+                entry_point: None,
+                install_path: module_dir.to_string_lossy().into_owned(),
+                python_version: python.as_ref().map(EnvMarker::python_version),
+                python_path: python.and_then(|marker| marker.python).map(|path| path.to_string_lossy().into_owned()),
+            })
+            .await
     }
 
     // New method to get the Python executable path from the virtual environment
-    fn get_venv_python(&self, env_dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    #[allow(dead_code)]
+    fn get_venv_python(&self, env_dir: &Path) -> Result<PathBuf> {
         #[cfg(target_os = "windows")]
         let python_path = env_dir.join("Scripts").join("python.exe");
 
@@ -121,7 +321,10 @@ impl SubnetModule {
         if python_path.exists() {
             Ok(python_path)
         } else {
-            Err(format!("Python executable not found in virtual environment: {:?}", python_path).into())
+            Err(Error::ScriptNotFound {
+                role: "Python executable".to_string(),
+                path: python_path,
+            })
         }
     }
     /// Prompts the user to select required inference modules.
@@ -131,8 +334,9 @@ impl SubnetModule {
     ///
     /// # Returns
     ///
-    /// * `Result<(), Box<dyn Error>>` - Returns Ok(()) if the user selects at least one module, or an error if an issue occurs.
-    async fn prompt_for_inference_modules(&mut self) -> Result<(), Box<dyn Error>> {
+    /// * `Result<()>` - Returns Ok(()) if the user selects at least one module, or an error if an issue occurs.
+    #[allow(dead_code)]
+    async fn prompt_for_inference_modules(&mut self, registry: &ModuleRegistry) -> Result<()> {
         let available_modules = vec!["translation", "embedding", "none"];
     
         let mut selections = Vec::new();
@@ -175,7 +379,7 @@ impl SubnetModule {
             for selected_module in &self.required_inference_modules {
                 println!("Installing inference module: {}", selected_module);
                 let inference_module = InferenceModule::new(selected_module)?;
-                inference_module.install().await?;
+                inference_module.install(registry, &InstallOptions::default()).await?;
                 println!("Inference module {} installed successfully", selected_module);
             }
         } else {
@@ -184,42 +388,25 @@ impl SubnetModule {
     
         Ok(())
     }
+}
 
-    fn run_command_with_output(&self, command: &str, args: &[&str]) -> Result<(), Box<dyn Error>> {
-        let mut child = Command::new(command)
-            .args(args)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-
-        let stdout = child.stdout.take().expect("Failed to open stdout");
-        let stderr = child.stderr.take().expect("Failed to open stderr");
-
-        let stdout_reader = BufReader::new(stdout);
-        let stderr_reader = BufReader::new(stderr);
-
-        std::thread::spawn(move || {
-            stdout_reader.lines().for_each(|line| {
-                if let Ok(line) = line {
-                    println!("{}", line);
-                }
-            });
-        });
-
-        std::thread::spawn(move || {
-            stderr_reader.lines().for_each(|line| {
-                if let Ok(line) = line {
-                    eprintln!("{}", line);
-                }
-            });
-        });
-
-        let status = child.wait()?;
+/// Returns the short hash of the commit checked out in `module_dir`, if it is a git repository.
+fn git_revision(module_dir: &Path) -> Option<String> {
+    git_output(module_dir, &["rev-parse", "--short", "HEAD"])
+        .ok()
+        .map(|output| output.trim().to_string())
+}
 
-        if status.success() {
-            Ok(())
-        } else {
-            Err(format!("Command failed with exit code: {}", status).into())
-        }
+/// Runs a git command in `module_dir` and returns its stdout.
+fn git_output(module_dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git").args(args).current_dir(module_dir).output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(Error::process_failed(
+            format!("git {}", args.join(" ")),
+            output.status,
+            &String::from_utf8_lossy(&output.stderr),
+        ))
     }
-}
\ No newline at end of file
+}
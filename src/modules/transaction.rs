@@ -0,0 +1,256 @@
+//! Install transactions for the Module Validator application.
+//!
+//! An install downloads into a staging directory and records every change it makes
+//! to the working tree. If it fails before being committed, the changes are undone
+//! in reverse order, so a failed install never looks like a finished one.
+
+use crate::error::{Error, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// A change made by an install.
+#[derive(Debug)]
+enum InstallStep {
+    /// A staging directory the download is written to.
+    Staged(TempDir),
+    /// A module directory moved into place from staging.
+    CreatedDir(PathBuf),
+    /// A module directory moved aside to make room for its update, kept until the update is committed.
+    ReplacedDir { target: PathBuf, backup: TempDir },
+    /// A virtual environment that did not exist before the install.
+    CreatedVenv(PathBuf),
+    /// A file edited by the install, with its previous content (None if it did not exist).
+    EditedFile { path: PathBuf, original: Option<Vec<u8>> },
+}
+
+/// Records the steps of an install and rolls them back unless the install is committed.
+#[derive(Debug)]
+pub struct InstallTransaction {
+    name: String,
+    keep_failed: bool,
+    steps: Vec<InstallStep>,
+    committed: bool,
+}
+
+impl InstallTransaction {
+    /// Starts recording an install.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the module being installed.
+    /// * `keep_failed` - Keep the module tree and virtual environment of a failed install for debugging.
+    pub fn begin(name: &str, keep_failed: bool) -> Self {
+        InstallTransaction {
+            name: name.to_string(),
+            keep_failed,
+            steps: Vec::new(),
+            committed: false,
+        }
+    }
+
+    /// Creates an empty staging directory next to where the module will be installed.
+    ///
+    /// # Arguments
+    ///
+    /// * `parent` - The directory the module is installed in, e.g. `subnets`.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the path of the staging directory.
+    pub fn stage(&mut self, parent: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(parent)?;
+        let dir = tempfile::Builder::new()
+            .prefix(&format!(".staging-{}-", self.name))
+            .tempdir_in(parent)?;
+        let path = dir.path().to_path_buf();
+        self.steps.push(InstallStep::Staged(dir));
+        Ok(path)
+    }
+
+    /// Creates a staging directory holding a copy of an installed module, for an update to change.
+    ///
+    /// # Arguments
+    ///
+    /// * `module_dir` - The installed module directory.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the path of the staging directory.
+    pub fn stage_copy(&mut self, module_dir: &Path) -> Result<PathBuf> {
+        let parent = module_dir.parent().unwrap_or(Path::new("."));
+        let staging_dir = self.stage(parent)?;
+        copy_dir(module_dir, &staging_dir)?;
+        Ok(staging_dir)
+    }
+
+    /// Moves an installed module directory aside and the most recently staged directory into
+    /// its place. The previous directory is restored on rollback and deleted on commit.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The installed module directory.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating whether the directories were swapped.
+    pub fn replace(&mut self, target: &Path) -> Result<()> {
+        let parent = target.parent().unwrap_or(Path::new("."));
+        let backup = tempfile::Builder::new()
+            .prefix(&format!(".previous-{}-", self.name))
+            .tempdir_in(parent)?;
+        fs::rename(target, backup.path().join("module"))?;
+        self.steps.push(InstallStep::ReplacedDir {
+            target: target.to_path_buf(),
+            backup,
+        });
+        self.promote(target)
+    }
+
+    /// Moves the most recently staged directory to its final location.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The module directory, which must not exist yet.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating whether the directory was moved.
+    pub fn promote(&mut self, target: &Path) -> Result<()> {
+        let index = self
+            .steps
+            .iter()
+            .rposition(|step| matches!(step, InstallStep::Staged(_)))
+            .ok_or_else(|| Error::InvalidInput(format!("Nothing was staged for {}", self.name)))?;
+        let InstallStep::Staged(dir) = &self.steps[index] else {
+            unreachable!()
+        };
+        fs::rename(dir.path(), target)?;
+        self.steps.remove(index);
+        self.steps.push(InstallStep::CreatedDir(target.to_path_buf()));
+        Ok(())
+    }
+
+    /// Records a virtual environment that is about to be created, unless it already exists.
+    pub fn track_venv(&mut self, venv_dir: &Path) {
+        if !venv_dir.exists() {
+            self.steps.push(InstallStep::CreatedVenv(venv_dir.to_path_buf()));
+        }
+    }
+
+    /// Saves the current content of a file that is about to be edited, so it can be restored.
+    pub fn track_file(&mut self, path: &Path) -> Result<()> {
+        let tracked = self
+            .steps
+            .iter()
+            .any(|step| matches!(step, InstallStep::EditedFile { path: tracked, .. } if tracked == path));
+        if !tracked {
+            let original = if path.exists() { Some(fs::read(path)?) } else { None };
+            self.steps.push(InstallStep::EditedFile {
+                path: path.to_path_buf(),
+                original,
+            });
+        }
+        Ok(())
+    }
+
+    /// Marks the install as finished, keeping every change.
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+
+    /// Undoes the recorded steps in reverse order. Failures are reported and the rollback continues.
+    fn rollback(&mut self) {
+        eprintln!("Install of {} failed, rolling back", self.name);
+        while let Some(step) = self.steps.pop() {
+            let result = match step {
+                InstallStep::Staged(dir) if self.keep_failed => {
+                    eprintln!("  Kept staged download at {}", dir.into_path().display());
+                    Ok(())
+                }
+                InstallStep::Staged(dir) => dir.close(),
+                InstallStep::CreatedDir(path) if self.keep_failed => {
+                    let kept = failed_path(&path);
+                    let result = remove_dir_if_exists(&kept).and_then(|_| fs::rename(&path, &kept));
+                    if result.is_ok() {
+                        eprintln!("  Kept failed install at {}", kept.display());
+                    }
+                    result
+                }
+                InstallStep::CreatedDir(path) => {
+                    eprintln!("  Removing {}", path.display());
+                    fs::remove_dir_all(&path)
+                }
+                InstallStep::CreatedVenv(path) if self.keep_failed => {
+                    eprintln!("  Kept virtual environment at {}", path.display());
+                    Ok(())
+                }
+                InstallStep::CreatedVenv(path) => {
+                    eprintln!("  Removing virtual environment {}", path.display());
+                    remove_dir_if_exists(&path)
+                }
+                InstallStep::ReplacedDir { target, backup } => {
+                    eprintln!("  Restoring the previous {}", target.display());
+                    // Left on disk if it cannot be moved back
+                    let previous = backup.into_path();
+                    fs::rename(previous.join("module"), &target).and_then(|_| fs::remove_dir(&previous))
+                }
+                InstallStep::EditedFile { path, original } => {
+                    eprintln!("  Restoring {}", path.display());
+                    match original {
+                        Some(content) => fs::write(&path, content),
+                        None if path.exists() => fs::remove_file(&path),
+                        None => Ok(()),
+                    }
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("  Warning: rollback step failed: {}", e);
+            }
+        }
+    }
+}
+
+impl Drop for InstallTransaction {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.rollback();
+        }
+    }
+}
+
+/// Returns where a failed install is kept with `--keep-failed`, e.g. `subnets/synthia.failed`.
+pub fn failed_path(module_dir: &Path) -> PathBuf {
+    let mut name = module_dir.file_name().unwrap_or_default().to_os_string();
+    name.push(".failed");
+    module_dir.with_file_name(name)
+}
+
+/// Copies a directory tree, recreating symlinks rather than following them.
+fn copy_dir(source: &Path, target: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(target)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let destination = target.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &destination)?;
+        } else if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &destination)?;
+            #[cfg(not(unix))]
+            fs::copy(entry.path(), &destination)?;
+        } else {
+            fs::copy(entry.path(), &destination)?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_dir_if_exists(path: &Path) -> std::io::Result<()> {
+    if path.exists() {
+        fs::remove_dir_all(path)
+    } else {
+        Ok(())
+    }
+}
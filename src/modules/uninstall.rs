@@ -0,0 +1,119 @@
+//! Uninstall module for the Module Validator application.
+//!
+//! This module works out what an installed module left on disk and removes it.
+
+use crate::environment;
+use crate::error::Result;
+use crate::modules::inference_module::{api_env_key, API_SETTINGS};
+use crate::registry::{ModuleRecord, ModuleType};
+use crate::utils::{check_name, env_line_key, remove_env_entries};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Everything that will be removed when a module is uninstalled.
+#[derive(Debug)]
+pub struct UninstallPlan {
+    /// The name of the module.
+    pub name: String,
+    /// The module or subnet directory, if it exists.
+    pub module_dir: Option<PathBuf>,
+    /// The `.<name>` virtual environment, if it exists.
+    pub venv_dir: Option<PathBuf>,
+    /// The `.env` file holding entries added for this module.
+    pub env_file: PathBuf,
+    /// The keys in `env_file` written when this module was installed.
+    pub env_keys: Vec<String>,
+}
+
+impl UninstallPlan {
+    /// Builds the plan for uninstalling a module.
+    ///
+    /// The registry record is preferred for locating the module directory. Without
+    /// one, `modules/<name>` and `subnets/<name>` are checked like `scripts/cleanup.sh` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_dir` - The directory modules are installed relative to.
+    /// * `name` - The name of the module.
+    /// * `record` - The registry record of the module, if there is one.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the UninstallPlan, an InvalidInput error if the name could
+    /// escape the module directories (empty, `.`, `..` or containing a path separator),
+    /// or an error if the `.env` file cannot be read.
+    pub fn new(root_dir: &Path, name: &str, record: Option<&ModuleRecord>) -> Result<Self> {
+        check_name("module", name)?;
+        let recorded_dir = record
+            .and_then(|r| r.install_path.as_ref())
+            .map(|path| root_dir.join(path));
+        let module_dir = recorded_dir
+            .into_iter()
+            .chain([ModuleType::Inference, ModuleType::Subnet].iter().map(|t| root_dir.join(t.directory()).join(name)))
+            .find(|dir| dir.exists());
+
+        let venv_dir = Some(root_dir.join(format!(".{}", name))).filter(|dir| dir.exists());
+
+        let env_file = root_dir.join(".env");
+        let module_keys = env_keys(name);
+        let env_keys = if env_file.exists() {
+            fs::read_to_string(&env_file)?
+                .lines()
+                .filter_map(env_line_key)
+                .filter(|key| module_keys.iter().any(|module_key| module_key == key))
+                .map(String::from)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Ok(UninstallPlan {
+            name: name.to_string(),
+            module_dir,
+            venv_dir,
+            env_file,
+            env_keys,
+        })
+    }
+
+    /// Returns true when there is nothing on disk to remove.
+    pub fn is_empty(&self) -> bool {
+        self.module_dir.is_none() && self.venv_dir.is_none() && self.env_keys.is_empty()
+    }
+
+    /// Prints what the plan will remove.
+    pub fn describe(&self) {
+        println!("Uninstalling {} will remove:", self.name);
+        if let Some(dir) = &self.module_dir {
+            println!("  module directory: {}", dir.display());
+        }
+        if let Some(dir) = &self.venv_dir {
+            println!("  virtual environment: {}", dir.display());
+        }
+        for key in &self.env_keys {
+            println!("  {} entry: {}", self.env_file.display(), key);
+        }
+    }
+
+    /// Removes the module directory, its virtual environment and its `.env` entries.
+    pub fn execute(&self) -> Result<()> {
+        if let Some(dir) = &self.module_dir {
+            fs::remove_dir_all(dir)?;
+            println!("Removed {}", dir.display());
+        }
+        if let Some(dir) = &self.venv_dir {
+            environment::destroy(dir)?;
+            println!("Removed {}", dir.display());
+        }
+        if !self.env_keys.is_empty() {
+            let removed = remove_env_entries(&self.env_file, &self.env_keys)?;
+            println!("Removed {} entries from {}", removed.len(), self.env_file.display());
+        }
+        Ok(())
+    }
+}
+
+/// Returns the `.env` keys written when a module is installed, e.g. `TRANSLATION_API_PORT`.
+pub fn env_keys(name: &str) -> Vec<String> {
+    API_SETTINGS.iter().map(|setting| api_env_key(name, setting)).collect()
+}
@@ -3,6 +3,7 @@ use std::convert::Infallible;
 use hyper::{Body, Client, Request, Response, Server, Uri};
 use hyper::service::{make_service_fn, service_fn};
 use hyper::client::HttpConnector;
+use crate::error::{Error, Result};
 
 pub struct Proxy {
     ip: String,
@@ -15,8 +16,10 @@ impl Proxy {
         Self { ip, port, target_base_url }
     }
 
-    pub async fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let addr: SocketAddr = format!("{}:{}", self.ip, self.port).parse()?;
+    pub async fn run(&self) -> Result<()> {
+        let addr: SocketAddr = format!("{}:{}", self.ip, self.port)
+            .parse()
+            .map_err(|e| Error::InvalidInput(format!("Invalid proxy address {}:{}: {}", self.ip, self.port, e)))?;
 
         println!("Proxy listening on {}", addr);
         println!("Forwarding requests to {}", self.target_base_url);
@@ -48,7 +51,7 @@ async fn proxy_service(
     client: Client<HttpConnector>,
     target_base_url: String,
     req: Request<Body>,
-) -> Result<Response<Body>, hyper::Error> {
+) -> std::result::Result<Response<Body>, hyper::Error> {
     let path_and_query = req.uri().path_and_query()
         .map(|x| x.to_string())
         .unwrap_or_default();
@@ -0,0 +1,78 @@
+//! Registry module for the Module Validator application.
+//!
+//! The ModuleRegistry is the single place the rest of the application asks
+//! "what is installed". It is backed by the `modules` table of whichever
+//! database `Config.database_url` points at.
+
+use crate::error::Result;
+use crate::config::Config;
+use crate::database::{self, ModuleStore};
+pub use crate::database::{ModuleRecord, ModuleType, NewModule};
+use std::env;
+use std::sync::Arc;
+
+/// Keeps track of installed inference and subnet modules.
+#[derive(Clone)]
+pub struct ModuleRegistry {
+    db: Arc<dyn ModuleStore>,
+}
+
+impl ModuleRegistry {
+    /// Connects to the registry database and applies any pending migrations.
+    ///
+    /// # Arguments
+    ///
+    /// * `database_url` - A `postgres://` or `sqlite://` connection string.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the ModuleRegistry if successful, or an error if the database is unreachable.
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let db = database::connect(database_url).await?;
+        Ok(ModuleRegistry { db: Arc::from(db) })
+    }
+
+    /// Connects to the registry database configured in `config.database_url`.
+    pub async fn from_config(config: &Config) -> Result<Self> {
+        Self::connect(&config.database_url).await
+    }
+
+    /// Connects to the registry database named by the `DATABASE_URL` environment variable,
+    /// falling back to the default SQLite file when it is unset.
+    pub async fn from_env() -> Result<Self> {
+        let database_url = env::var("DATABASE_URL").unwrap_or_else(|_| Config::default().database_url);
+        Self::connect(&database_url).await
+    }
+
+    /// Records an installed module, replacing any previous record of the same name.
+    pub async fn register(&self, module: &NewModule) -> Result<()> {
+        self.db.upsert_module(module).await?;
+        println!("Registered {} module {}", module.module_type, module.name);
+        Ok(())
+    }
+
+    /// Looks up an installed module by name.
+    pub async fn get(&self, name: &str) -> Result<Option<ModuleRecord>> {
+        self.db.get_module(name).await
+    }
+
+    /// Lists every installed module, ordered by name.
+    pub async fn list(&self) -> Result<Vec<ModuleRecord>> {
+        self.db.list_modules(None).await
+    }
+
+    /// Lists the installed modules of a single type, ordered by name.
+    pub async fn list_by_type(&self, module_type: ModuleType) -> Result<Vec<ModuleRecord>> {
+        self.db.list_modules(Some(module_type)).await
+    }
+
+    /// Records that a module has just been used.
+    pub async fn mark_used(&self, name: &str) -> Result<()> {
+        self.db.touch_module(name).await
+    }
+
+    /// Forgets an uninstalled module, returning whether it was registered.
+    pub async fn unregister(&self, name: &str) -> Result<bool> {
+        self.db.delete_module(name).await
+    }
+}
@@ -0,0 +1,310 @@
+//! Sandboxed execution for the Module Validator application.
+//!
+//! Installing a module runs code fetched from elsewhere: the registrar's
+//! `setup_<name>.py`, a subnet's `setup.sh` or manifest setup steps, and later its
+//! validator and miner scripts. A [`SandboxPolicy`] declared for a module in
+//! `config.yaml` confines those processes on Linux:
+//!
+//! * resource limits on CPU time, address space, open files and processes
+//! * an environment scrubbed down to [`DEFAULT_ENV_ALLOWLIST`] and the policy's own allowlist
+//! * a working directory inside the module directory
+//! * optionally, no network access, by running the process in a new network namespace
+//!
+//! The sandbox is opt-in: modules without a policy run with the operator's full
+//! environment, as before.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// The key of the policy applied to modules that have none of their own.
+pub const ALL_MODULES: &str = "*";
+
+/// Environment variables passed to every sandboxed process, besides the policy's allowlist.
+pub const DEFAULT_ENV_ALLOWLIST: [&str; 9] = ["PATH", "HOME", "USER", "LANG", "LC_ALL", "LC_CTYPE", "TERM", "TZ", "TMPDIR"];
+
+/// How a module's processes are confined.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SandboxPolicy {
+    /// CPU time a process may use, in seconds (`RLIMIT_CPU`).
+    #[serde(default)]
+    pub cpu_seconds: Option<u64>,
+    /// Address space a process may use, in megabytes (`RLIMIT_AS`).
+    #[serde(default)]
+    pub memory_mb: Option<u64>,
+    /// Number of files a process may have open (`RLIMIT_NOFILE`).
+    #[serde(default)]
+    pub open_files: Option<u64>,
+    /// Number of processes the user may run (`RLIMIT_NPROC`); not enforced for root.
+    #[serde(default)]
+    pub processes: Option<u64>,
+    /// Environment variables passed through besides [`DEFAULT_ENV_ALLOWLIST`].
+    #[serde(default)]
+    pub env_allowlist: Vec<String>,
+    /// The directory processes run in, relative to the module directory.
+    #[serde(default)]
+    pub workdir: Option<PathBuf>,
+    /// Whether processes may use the network; false runs them in a new network namespace.
+    #[serde(default = "default_network")]
+    pub network: bool,
+}
+
+impl Default for SandboxPolicy {
+    fn default() -> Self {
+        SandboxPolicy {
+            cpu_seconds: None,
+            memory_mb: None,
+            open_files: None,
+            processes: None,
+            env_allowlist: Vec::new(),
+            workdir: None,
+            network: default_network(),
+        }
+    }
+}
+
+fn default_network() -> bool {
+    true
+}
+
+impl SandboxPolicy {
+    /// Confines a command to the policy.
+    ///
+    /// The command's environment is cleared, so this must be called before the
+    /// command's own variables (e.g. the module's `.env`) are set.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to confine.
+    /// * `module_dir` - The directory of the module the command belongs to.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success, or an error if the working directory is outside the
+    /// module directory or the platform cannot sandbox processes.
+    pub fn apply(&self, command: &mut Command, module_dir: &Path) -> Result<()> {
+        command.current_dir(self.working_dir(module_dir)?);
+
+        command.env_clear();
+        let allowed = DEFAULT_ENV_ALLOWLIST.iter().copied().chain(self.env_allowlist.iter().map(String::as_str));
+        for name in allowed {
+            if let Some(value) = env::var_os(name) {
+                command.env(name, value);
+            }
+        }
+
+        self.confine(command)
+    }
+
+    /// Returns the directory processes run in, checking that it is inside the module directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `module_dir` - The directory of the module.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the absolute working directory, or a ConfigInvalid error if it
+    /// does not exist or resolves outside the module directory.
+    pub fn working_dir(&self, module_dir: &Path) -> Result<PathBuf> {
+        let module_dir = fs::canonicalize(module_dir)?;
+        let Some(workdir) = &self.workdir else {
+            return Ok(module_dir);
+        };
+        let resolved = fs::canonicalize(module_dir.join(workdir)).map_err(|e| {
+            Error::ConfigInvalid(format!("sandbox workdir {}: {}", workdir.display(), e))
+        })?;
+        // Resolving symlinks first means a link pointing out of the module is caught too
+        if !resolved.starts_with(&module_dir) {
+            return Err(Error::ConfigInvalid(format!(
+                "sandbox workdir {} is outside the module directory {}",
+                workdir.display(),
+                module_dir.display()
+            )));
+        }
+        Ok(resolved)
+    }
+
+    /// Lists what is wrong with the policy's values, for configuration validation.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let limits = [
+            ("cpu_seconds", self.cpu_seconds),
+            ("memory_mb", self.memory_mb),
+            ("open_files", self.open_files),
+            ("processes", self.processes),
+        ];
+        for (key, value) in limits {
+            if value == Some(0) {
+                problems.push(format!("{} must be at least 1", key));
+            }
+        }
+        if let Some(workdir) = &self.workdir {
+            if workdir.components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir)) {
+                problems.push(format!("workdir `{}` must be a relative path inside the module directory", workdir.display()));
+            }
+        }
+        problems
+    }
+
+    /// Sets the resource limits and network isolation, applied in the child before it executes.
+    #[cfg(target_os = "linux")]
+    fn confine(&self, command: &mut Command) -> Result<()> {
+        use std::io;
+        use std::os::unix::process::CommandExt;
+
+        let limits = [
+            (libc::RLIMIT_CPU, self.cpu_seconds),
+            (libc::RLIMIT_AS, self.memory_mb.map(|mb| mb.saturating_mul(1024 * 1024))),
+            (libc::RLIMIT_NOFILE, self.open_files),
+            (libc::RLIMIT_NPROC, self.processes),
+        ];
+        let isolation = (!self.network).then(NetworkIsolation::prepare);
+        let cpu = libc::RLIMIT_CPU;
+        // SAFETY: the closure runs between fork and exec, so it only makes async-signal-safe
+        // system calls on data prepared beforehand, and does not allocate.
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(isolation) = &isolation {
+                    isolation.enter()?;
+                }
+                for (resource, limit) in limits {
+                    let Some(limit) = limit else { continue };
+                    // A CPU hard limit one second above the soft one sends SIGXCPU before SIGKILL
+                    let hard = if resource == cpu { limit.saturating_add(1) } else { limit };
+                    let rlimit = libc::rlimit { rlim_cur: limit, rlim_max: hard };
+                    if libc::setrlimit(resource, &rlimit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn confine(&self, _command: &mut Command) -> Result<()> {
+        Err(Error::ConfigInvalid("sandbox policies are only supported on Linux".to_string()))
+    }
+}
+
+impl fmt::Display for SandboxPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(cpu_seconds) = self.cpu_seconds {
+            parts.push(format!("cpu {}s", cpu_seconds));
+        }
+        if let Some(memory_mb) = self.memory_mb {
+            parts.push(format!("memory {} MB", memory_mb));
+        }
+        if let Some(open_files) = self.open_files {
+            parts.push(format!("{} open files", open_files));
+        }
+        if let Some(processes) = self.processes {
+            parts.push(format!("{} processes", processes));
+        }
+        if !self.env_allowlist.is_empty() {
+            parts.push(format!("env {}", self.env_allowlist.join(",")));
+        }
+        if let Some(workdir) = &self.workdir {
+            parts.push(format!("workdir {}", workdir.display()));
+        }
+        parts.push(if self.network { "network" } else { "no network" }.to_string());
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// The sandbox policies declared in `config.yaml`, keyed by module name.
+///
+/// The policy under [`ALL_MODULES`] applies to modules without one of their own.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SandboxPolicies(pub BTreeMap<String, SandboxPolicy>);
+
+impl SandboxPolicies {
+    /// Returns the policy for a module, if it is sandboxed.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the module.
+    pub fn for_module(&self, name: &str) -> Option<&SandboxPolicy> {
+        self.0.get(name).or_else(|| self.0.get(ALL_MODULES))
+    }
+}
+
+impl fmt::Display for SandboxPolicies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "none");
+        }
+        let policies: Vec<String> = self.0.iter().map(|(name, policy)| format!("{}: {}", name, policy)).collect();
+        write!(f, "{}", policies.join("; "))
+    }
+}
+
+/// What the child needs to move into its own network namespace.
+///
+/// Without root, the network namespace is created inside a new user namespace, which
+/// maps the current user to itself so file permissions are unchanged.
+#[cfg(target_os = "linux")]
+struct NetworkIsolation {
+    /// The uid and gid map lines, when a user namespace is needed.
+    user_maps: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+#[cfg(target_os = "linux")]
+impl NetworkIsolation {
+    fn prepare() -> Self {
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        NetworkIsolation {
+            user_maps: (uid != 0).then(|| {
+                (
+                    format!("{} {} 1\n", uid, uid).into_bytes(),
+                    format!("{} {} 1\n", gid, gid).into_bytes(),
+                )
+            }),
+        }
+    }
+
+    /// Unshares the network namespace; called in the child between fork and exec.
+    fn enter(&self) -> std::io::Result<()> {
+        let flags = match self.user_maps {
+            Some(_) => libc::CLONE_NEWUSER | libc::CLONE_NEWNET,
+            None => libc::CLONE_NEWNET,
+        };
+        if unsafe { libc::unshare(flags) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if let Some((uid_map, gid_map)) = &self.user_maps {
+            write_proc_file(c"/proc/self/setgroups", b"deny")?;
+            write_proc_file(c"/proc/self/uid_map", uid_map)?;
+            write_proc_file(c"/proc/self/gid_map", gid_map)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes to a file under /proc without allocating.
+#[cfg(target_os = "linux")]
+fn write_proc_file(path: &std::ffi::CStr, content: &[u8]) -> std::io::Result<()> {
+    unsafe {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let written = libc::write(fd, content.as_ptr().cast(), content.len());
+        let error = std::io::Error::last_os_error();
+        libc::close(fd);
+        if written < 0 {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
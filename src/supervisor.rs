@@ -0,0 +1,855 @@
+//! Process supervisor module for the Module Validator application.
+//!
+//! Miners, validators and inference servers run for days, longer than the terminal
+//! that launched them. A supervised process is started by a detached copy of this
+//! program (`module_validator supervise <name>`), which runs the process in its own
+//! process group, writes its output to a log file and restarts it according to its
+//! [`RestartPolicy`], waiting longer after each failure in a row. The supervisor is
+//! the leader of a new session, so it survives the CLI exiting and the terminal closing.
+//!
+//! Everything the supervisor knows is kept under `data/processes/<name>/`:
+//!
+//! * `spec.json` - the [`ProcessSpec`]: what to run and how to restart it.
+//! * `state.json` - the [`ProcessState`]: pids and their start times, status, restarts and the last exit.
+//! * `supervisor.log` - what the supervisor itself printed.
+//!
+//! The process's stdout and stderr go to the rotating module log
+//! `data/logs/<module>/<role>.log` (see [`crate::logs`]), timestamped and with secrets masked.
+//!
+//! Since all of it is on disk, any later invocation of the CLI can list the processes,
+//! stop or restart them and read their logs.
+//!
+//! The environment of the process is inherited from the supervisor, scrubbed by the spec's
+//! sandbox policy if it has one, and never written to disk, so secrets from the modules' `.env` files stay out of `data/`.
+
+use crate::error::{Error, Result};
+use crate::inference::process::{exit_signal, CancelHandle, OutputStream, ProcessGroup, WaitOutcome};
+use crate::logs::{LogSettings, RotatingLog};
+use crate::sandbox::SandboxPolicy;
+use crate::utils::check_name;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::mpsc;
+
+/// The directory holding the state of supervised processes.
+pub const PROCESS_DIR: &str = "data/processes";
+
+/// How long the supervisor waits before the first restart after a failure.
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest the supervisor waits between two restarts.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A process that ran at least this long before exiting is restarted after [`INITIAL_BACKOFF`]
+/// again, however often it was restarted before.
+pub const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+/// How long a stopped process gets to exit after SIGTERM before it is sent SIGKILL.
+///
+/// Longer than [`crate::inference::process::TERMINATE_GRACE_PERIOD`], so a supervised
+/// launch command has the time to stop the module it started itself.
+pub const STOP_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How long the supervisor waits for the last output lines before it exits.
+const LOG_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long [`Supervisor::stop`] waits for a supervisor to stop its process and exit
+/// before killing both.
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How often [`Supervisor::stop`] checks whether a supervisor has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// When a supervised process is restarted after it exits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Whenever it exits, successfully or not.
+    Always,
+    /// When it exits unsuccessfully or is killed by a signal.
+    #[default]
+    OnFailure,
+    /// Never: the supervisor exits with the process.
+    Never,
+}
+
+impl RestartPolicy {
+    /// Returns whether a process that exited should be restarted.
+    ///
+    /// # Arguments
+    ///
+    /// * `success` - Whether the process exited successfully.
+    pub fn should_restart(&self, success: bool) -> bool {
+        match self {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => !success,
+            RestartPolicy::Never => false,
+        }
+    }
+}
+
+impl FromStr for RestartPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "always" => Ok(RestartPolicy::Always),
+            "on-failure" => Ok(RestartPolicy::OnFailure),
+            "never" => Ok(RestartPolicy::Never),
+            other => Err(Error::InvalidInput(format!(
+                "Unknown restart policy `{}` (expected always, on-failure or never)",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            RestartPolicy::Always => "always",
+            RestartPolicy::OnFailure => "on-failure",
+            RestartPolicy::Never => "never",
+        })
+    }
+}
+
+/// Returns how long to wait before restarting a process that keeps exiting soon after it starts.
+///
+/// The delay starts at [`INITIAL_BACKOFF`] and doubles with each restart, up to [`MAX_BACKOFF`].
+///
+/// # Arguments
+///
+/// * `restarts` - The number of restarts in a row, each after a run shorter than [`STABLE_UPTIME`].
+pub fn backoff(restarts: u32) -> Duration {
+    INITIAL_BACKOFF
+        .checked_mul(2u32.saturating_pow(restarts))
+        .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF))
+}
+
+/// What a supervised process does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessRole {
+    Miner,
+    Validator,
+    Inference,
+}
+
+impl fmt::Display for ProcessRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            ProcessRole::Miner => "miner",
+            ProcessRole::Validator => "validator",
+            ProcessRole::Inference => "inference",
+        })
+    }
+}
+
+/// What to run under supervision and how to restart it, saved as `spec.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessSpec {
+    /// The name the process is managed under.
+    pub name: String,
+    /// What the process does.
+    pub role: ProcessRole,
+    /// The module the process runs.
+    pub module: String,
+    /// The program to run.
+    pub program: PathBuf,
+    /// Its arguments.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// The directory it runs in.
+    pub cwd: PathBuf,
+    /// When it is restarted.
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// The port it listens on, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Confines the process, with `cwd` as its module directory. The supervisor applies the
+    /// policy to every run, so the process only sees the allowlisted variables of the
+    /// supervisor's environment. None runs it with the supervisor's environment and privileges.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxPolicy>,
+}
+
+/// The lifecycle status of a supervised process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessStatus {
+    /// The process is being started.
+    Starting,
+    /// The process is running.
+    Running,
+    /// The process exited and will be restarted after a delay.
+    Backoff,
+    /// The process was stopped on request.
+    Stopped,
+    /// The process exited successfully and was not restarted.
+    Exited,
+    /// The process failed and was not restarted.
+    Failed,
+    /// The supervisor died without recording how its process ended.
+    Dead,
+}
+
+impl fmt::Display for ProcessStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            ProcessStatus::Starting => "starting",
+            ProcessStatus::Running => "running",
+            ProcessStatus::Backoff => "backoff",
+            ProcessStatus::Stopped => "stopped",
+            ProcessStatus::Exited => "exited",
+            ProcessStatus::Failed => "failed",
+            ProcessStatus::Dead => "dead",
+        })
+    }
+}
+
+/// How a supervised process last exited.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExitInfo {
+    /// The exit code, if the process exited on its own.
+    pub code: Option<i32>,
+    /// The signal that killed the process, if any.
+    pub signal: Option<i32>,
+    /// When the exit was seen.
+    pub at: DateTime<Utc>,
+}
+
+impl ExitInfo {
+    fn from_status(status: &ExitStatus) -> Self {
+        ExitInfo {
+            code: status.code(),
+            signal: exit_signal(status),
+            at: Utc::now(),
+        }
+    }
+}
+
+/// What the supervisor knows about its process, saved as `state.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessState {
+    /// The pid of the supervisor.
+    pub supervisor_pid: u32,
+    /// The start time of the supervisor, which tells it apart from a later process reusing its pid.
+    #[serde(default)]
+    pub supervisor_start_time: Option<u64>,
+    /// The pid of the running process, which is also its process group id.
+    pub pid: Option<u32>,
+    /// The start time of the running process.
+    #[serde(default)]
+    pub start_time: Option<u64>,
+    /// The lifecycle status.
+    pub status: ProcessStatus,
+    /// How many times the process was restarted.
+    pub restarts: u32,
+    /// When the current run of the process started.
+    pub started_at: Option<DateTime<Utc>>,
+    /// How the process last exited.
+    pub last_exit: Option<ExitInfo>,
+    /// When the state was last written.
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ProcessState {
+    /// Creates the state of a supervisor that is starting its process.
+    pub fn new(supervisor_pid: u32) -> Self {
+        ProcessState {
+            supervisor_pid,
+            supervisor_start_time: process_start_time(supervisor_pid),
+            pid: None,
+            start_time: None,
+            status: ProcessStatus::Starting,
+            restarts: 0,
+            started_at: None,
+            last_exit: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Returns whether the supervisor is still alive, i.e. the process is managed.
+    ///
+    /// Like [`Supervisor::stop`], it only counts the recorded supervisor, not a later
+    /// process that reuses its pid.
+    pub fn is_active(&self) -> bool {
+        !self.is_finished() && is_recorded_process(self.supervisor_pid, self.supervisor_start_time)
+    }
+
+    /// Returns whether the supervisor recorded that it is done with the process.
+    fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            ProcessStatus::Stopped | ProcessStatus::Exited | ProcessStatus::Failed | ProcessStatus::Dead
+        )
+    }
+}
+
+/// A process started under the supervisor, with the last state its supervisor wrote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagedProcess {
+    pub spec: ProcessSpec,
+    /// None until the supervisor has started.
+    pub state: Option<ProcessState>,
+}
+
+impl ManagedProcess {
+    /// Returns the status of the process, [`ProcessStatus::Dead`] if its supervisor is gone
+    /// although it did not record the process as finished.
+    pub fn status(&self) -> ProcessStatus {
+        match &self.state {
+            None => ProcessStatus::Starting,
+            Some(state) if state.is_finished() || state.is_active() => state.status,
+            Some(_) => ProcessStatus::Dead,
+        }
+    }
+
+    /// Returns the pid of the process while it runs.
+    pub fn pid(&self) -> Option<u32> {
+        match self.status() {
+            ProcessStatus::Running => self.state.as_ref().and_then(|state| state.pid),
+            _ => None,
+        }
+    }
+
+    /// Returns how long the process has been running, while it runs.
+    pub fn uptime(&self) -> Option<Duration> {
+        self.pid()?;
+        let started_at = self.state.as_ref()?.started_at?;
+        (Utc::now() - started_at).to_std().ok()
+    }
+}
+
+/// Returns the start time of a process in clock ticks since boot, if it exists.
+///
+/// Together with the pid, it identifies a process: a later process that reuses the pid
+/// starts at another time.
+#[cfg(target_os = "linux")]
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces, so fields are counted from its closing parenthesis
+    let fields = stat.rsplit_once(") ")?.1;
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// Returns the start time of a process, which is not known on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn process_start_time(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// Returns whether a pid still belongs to the process that was recorded with it.
+///
+/// A process whose start time was not recorded cannot be told apart from one reusing its
+/// pid, so it does not count.
+fn is_recorded_process(pid: u32, start_time: Option<u64>) -> bool {
+    start_time.is_some() && process_start_time(pid) == start_time
+}
+
+/// Sends a signal to a process, or to a process group if `group` is set.
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: libc::c_int, group: bool) {
+    unsafe {
+        if group {
+            libc::killpg(pid as libc::pid_t, signal);
+        } else {
+            libc::kill(pid as libc::pid_t, signal);
+        }
+    }
+}
+
+/// Starts, supervises and inspects the processes whose state is kept in a directory.
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    dir: PathBuf,
+    logs: LogSettings,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Supervisor::new(PROCESS_DIR)
+    }
+}
+
+impl Supervisor {
+    /// Creates a supervisor keeping its state in a directory, by default [`PROCESS_DIR`].
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding one subdirectory per supervised process.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Supervisor {
+            dir: dir.into(),
+            logs: LogSettings::default(),
+        }
+    }
+
+    /// Sets where the output of the processes is logged and how the logs are rotated.
+    pub fn with_logs(mut self, logs: LogSettings) -> Self {
+        self.logs = logs;
+        self
+    }
+
+    /// Returns the directory holding the state of supervised processes.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the directory holding the state of one process.
+    pub fn process_dir(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Returns the log file the output of a process is written to: the log of its module and role.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the path, or an InvalidInput error if the module name is invalid.
+    pub fn output_log(&self, spec: &ProcessSpec) -> Result<PathBuf> {
+        self.logs.path(&spec.module, &spec.role.to_string())
+    }
+
+    /// Loads what a process runs.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the ProcessSpec, or an InvalidInput error if the name is invalid or
+    /// no process of that name was started.
+    pub fn load_spec(&self, name: &str) -> Result<ProcessSpec> {
+        check_name("process", name)?;
+        let path = self.process_dir(name).join("spec.json");
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::InvalidInput(format!("No managed process named {}", name)))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_str(&content)
+            .map_err(|e| Error::ConfigInvalid(format!("Invalid process spec {}: {}", path.display(), e)))
+    }
+
+    /// Loads a process started under the supervisor and its last state.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the ManagedProcess, or an InvalidInput error if no process of that name was started.
+    pub fn get(&self, name: &str) -> Result<ManagedProcess> {
+        Ok(ManagedProcess {
+            spec: self.load_spec(name)?,
+            state: self.load_state(name)?,
+        })
+    }
+
+    /// Lists the processes started under the supervisor, running or not, ordered by name.
+    pub fn list(&self) -> Result<Vec<ManagedProcess>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut processes = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if !entry.path().join("spec.json").is_file() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                processes.push(self.get(name)?);
+            }
+        }
+        processes.sort_by(|a, b| a.spec.name.cmp(&b.spec.name));
+        Ok(processes)
+    }
+
+    /// Stops a process and its supervisor.
+    ///
+    /// The supervisor is sent SIGTERM, which makes it stop the process's group and record
+    /// the process as stopped. A supervisor still alive after [`STOP_TIMEOUT`] is killed
+    /// with the process group, and the process is recorded as stopped here instead. Only
+    /// pids whose start time matches the recorded one are signalled, so a process that
+    /// reuses the pid of a dead supervisor or process is left alone.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the process.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the process as it is once stopped, or an InvalidInput error if
+    /// no process of that name was started.
+    #[cfg(unix)]
+    pub fn stop(&self, name: &str) -> Result<ManagedProcess> {
+        let process = self.get(name)?;
+        let Some(mut state) = process.state else {
+            return Ok(process);
+        };
+        if state.is_finished() {
+            return Ok(ManagedProcess {
+                spec: process.spec,
+                state: Some(state),
+            });
+        }
+
+        // The pids may have been reused since the state was written
+        let supervisor_running = || is_recorded_process(state.supervisor_pid, state.supervisor_start_time);
+        if supervisor_running() {
+            send_signal(state.supervisor_pid, libc::SIGTERM, false);
+            let deadline = Instant::now() + STOP_TIMEOUT;
+            while supervisor_running() && Instant::now() < deadline {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        }
+        if let Some(stopped) = self.load_state(name)?.filter(ProcessState::is_finished) {
+            return Ok(ManagedProcess {
+                spec: process.spec,
+                state: Some(stopped),
+            });
+        }
+
+        // The supervisor is stuck or already gone: nothing else will stop the process
+        if supervisor_running() {
+            send_signal(state.supervisor_pid, libc::SIGKILL, false);
+        }
+        if let Some(pid) = state.pid.take() {
+            if is_recorded_process(pid, state.start_time.take()) {
+                send_signal(pid, libc::SIGKILL, true);
+            }
+        }
+        state.status = ProcessStatus::Stopped;
+        self.save_state(name, &mut state)?;
+        Ok(ManagedProcess {
+            spec: process.spec,
+            state: Some(state),
+        })
+    }
+
+    /// Stops a process and its supervisor.
+    #[cfg(not(unix))]
+    pub fn stop(&self, _name: &str) -> Result<ManagedProcess> {
+        Err(Error::InvalidInput("Stopping supervised processes is only supported on Unix".to_string()))
+    }
+
+    /// Loads the last state written by the supervisor of a process.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the ProcessState, or None if the supervisor has not written one yet,
+    /// or an InvalidInput error if the name is invalid.
+    pub fn load_state(&self, name: &str) -> Result<Option<ProcessState>> {
+        check_name("process", name)?;
+        let path = self.process_dir(name).join("state.json");
+        match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map(Some)
+                .map_err(|e| Error::ConfigInvalid(format!("Invalid process state {}: {}", path.display(), e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Saves the state of a process, replacing the file at once so readers never see half of it.
+    fn save_state(&self, name: &str, state: &mut ProcessState) -> Result<()> {
+        state.updated_at = Utc::now();
+        let path = self.process_dir(name).join("state.json");
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string_pretty(state).map_err(io::Error::other)?)?;
+        fs::rename(&temp, &path)?;
+        Ok(())
+    }
+
+    /// Starts a detached supervisor for a process, which keeps running after this program exits.
+    ///
+    /// The supervisor is a copy of the running executable, started with the hidden
+    /// `supervise` command in a new session.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - What to run and how to restart it.
+    /// * `env` - Variables set for the supervisor and the process, over this program's environment.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the pid of the supervisor, or an error if the name is invalid, a
+    /// process of that name is already running or the supervisor cannot be started.
+    pub fn start<K, V>(&self, spec: &ProcessSpec, env: impl IntoIterator<Item = (K, V)>) -> Result<u32>
+    where
+        K: AsRef<std::ffi::OsStr>,
+        V: AsRef<std::ffi::OsStr>,
+    {
+        self.prepare(spec)?;
+        let process_dir = self.process_dir(&spec.name);
+        let supervisor_log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(process_dir.join("supervisor.log"))?;
+
+        let mut command = Command::new(std::env::current_exe()?);
+        command
+            .arg("supervise")
+            .arg(&spec.name)
+            .arg("--dir")
+            .arg(std::path::absolute(&self.dir)?)
+            .envs(env)
+            .stdin(Stdio::null())
+            .stdout(supervisor_log.try_clone()?)
+            .stderr(supervisor_log);
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            // A new session has no controlling terminal, so closing the terminal sends it no SIGHUP
+            unsafe {
+                command.pre_exec(|| {
+                    if libc::setsid() == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+        let child = command.spawn()?;
+        Ok(child.id())
+    }
+
+    /// Checks that a process can be started under a name and saves its spec.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - What to run and how to restart it.
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating whether the spec was saved, or an InvalidInput error if the name is
+    /// invalid or a process of that name is still running.
+    pub fn prepare(&self, spec: &ProcessSpec) -> Result<()> {
+        check_name("process", &spec.name)?;
+        self.output_log(spec)?;
+        if let Some(state) = self.load_state(&spec.name)? {
+            if state.is_active() {
+                return Err(Error::InvalidInput(format!(
+                    "{} is already running (supervisor pid {})",
+                    spec.name, state.supervisor_pid
+                )));
+            }
+        }
+        let process_dir = self.process_dir(&spec.name);
+        fs::create_dir_all(&process_dir)?;
+        let spec_json = serde_json::to_string_pretty(spec).map_err(io::Error::other)?;
+        fs::write(process_dir.join("spec.json"), spec_json)?;
+        match fs::remove_file(process_dir.join("state.json")) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs a process until it is stopped or its restart policy gives up, restarting it in between.
+    ///
+    /// This is the body of the `supervise` command. The output of the process is appended to
+    /// its log and the state file is updated at each change.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the process was started under.
+    /// * `cancel` - A handle that stops the process and the supervisor when cancelled.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the final state, or an error if the spec or the state files cannot be used.
+    pub async fn supervise(&self, name: &str, cancel: &CancelHandle) -> Result<ProcessState> {
+        let spec = self.load_spec(name)?;
+        let mut state = ProcessState::new(std::process::id());
+        self.save_state(name, &mut state)?;
+
+        let log = RotatingLog::open(&self.logs, &spec.module, &spec.role.to_string())?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(write_log(log, receiver));
+        let log_event = |message: String| {
+            let _ = sender.send(LogLine::Supervisor(message));
+        };
+
+        // Restarts in a row of runs shorter than STABLE_UPTIME
+        let mut rapid_restarts = 0;
+        loop {
+            state.status = ProcessStatus::Starting;
+            self.save_state(name, &mut state)?;
+
+            let started = Instant::now();
+            let success = match self.run_once(&spec, &mut state, &sender, cancel).await? {
+                RunEnd::Exited(status) => {
+                    log_event(format!("{} {}", spec.name, describe_status(&status)));
+                    state.last_exit = Some(ExitInfo::from_status(&status));
+                    status.success()
+                }
+                RunEnd::Stopped(status) => {
+                    state.pid = None;
+                    state.last_exit = Some(ExitInfo::from_status(&status));
+                    state.status = ProcessStatus::Stopped;
+                    break;
+                }
+                RunEnd::SpawnFailed(e) => {
+                    log_event(format!("failed to start {}: {}", spec.program.display(), e));
+                    false
+                }
+            };
+            state.pid = None;
+            if !spec.restart.should_restart(success) {
+                state.status = if success { ProcessStatus::Exited } else { ProcessStatus::Failed };
+                break;
+            }
+
+            if started.elapsed() >= STABLE_UPTIME {
+                rapid_restarts = 0;
+            }
+            let delay = backoff(rapid_restarts);
+            rapid_restarts = rapid_restarts.saturating_add(1);
+            state.status = ProcessStatus::Backoff;
+            self.save_state(name, &mut state)?;
+            log_event(format!("restarting {} in {}s", spec.name, delay.as_secs_f64()));
+            if sleep_unless_cancelled(delay, cancel).await {
+                state.status = ProcessStatus::Stopped;
+                break;
+            }
+            state.restarts += 1;
+        }
+
+        log_event(format!("{} {}", spec.name, state.status));
+        drop(sender);
+        // Processes the stopped one started may still hold its stdout open
+        let _ = tokio::time::timeout(LOG_FLUSH_TIMEOUT, writer).await;
+        self.save_state(name, &mut state)?;
+        Ok(state)
+    }
+
+    /// Runs a process once, until it exits or the handle is cancelled.
+    async fn run_once(
+        &self,
+        spec: &ProcessSpec,
+        state: &mut ProcessState,
+        lines: &mpsc::UnboundedSender<LogLine>,
+        cancel: &CancelHandle,
+    ) -> Result<RunEnd> {
+        let mut process = match self.spawn(spec, lines) {
+            Ok(process) => process,
+            Err(e) => return Ok(RunEnd::SpawnFailed(e)),
+        };
+        let pid = process.id();
+        state.pid = Some(pid);
+        state.start_time = process_start_time(pid);
+        state.status = ProcessStatus::Running;
+        state.started_at = Some(Utc::now());
+        self.save_state(&spec.name, state)?;
+        let _ = lines.send(LogLine::Supervisor(format!("started {} (pid {})", spec.name, pid)));
+
+        let exited = tokio::select! {
+            outcome = process.wait(None, None) => match outcome? {
+                WaitOutcome::Exited(status) => Some(status),
+                _ => unreachable!("no timeout or cancel handle was given"),
+            },
+            _ = cancel.cancelled() => None,
+        };
+        match exited {
+            Some(status) => Ok(RunEnd::Exited(status)),
+            None => {
+                let _ = lines.send(LogLine::Supervisor(format!("stopping {}", spec.name)));
+                Ok(RunEnd::Stopped(process.terminate(STOP_GRACE_PERIOD).await?))
+            }
+        }
+    }
+
+    /// Spawns one run of a process, forwarding its output lines to the log writer.
+    fn spawn(&self, spec: &ProcessSpec, lines: &mpsc::UnboundedSender<LogLine>) -> io::Result<ProcessGroup> {
+        let mut command = tokio::process::Command::new(&spec.program);
+        command
+            .args(&spec.args)
+            .current_dir(&spec.cwd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(policy) = &spec.sandbox {
+            policy.apply(command.as_std_mut(), &spec.cwd).map_err(io::Error::other)?;
+        }
+        let mut process = ProcessGroup::spawn(&mut command)?;
+        let stdout = process.child().stdout.take().expect("Failed to capture stdout");
+        let stderr = process.child().stderr.take().expect("Failed to capture stderr");
+        forward_lines(stdout, OutputStream::Stdout, lines.clone());
+        forward_lines(stderr, OutputStream::Stderr, lines.clone());
+        Ok(process)
+    }
+}
+
+/// How one run of a supervised process ended.
+enum RunEnd {
+    /// The process exited on its own.
+    Exited(ExitStatus),
+    /// The process was stopped because the supervisor was cancelled.
+    Stopped(ExitStatus),
+    /// The process could not be started.
+    SpawnFailed(io::Error),
+}
+
+/// A line for the output log of a supervised process.
+enum LogLine {
+    /// A line the process wrote.
+    Output(OutputStream, String),
+    /// Something the supervisor did.
+    Supervisor(String),
+}
+
+/// Reads the lines of a stream of the process and sends them to the log writer.
+fn forward_lines(
+    stream: impl AsyncRead + Unpin + Send + 'static,
+    tag: OutputStream,
+    lines: mpsc::UnboundedSender<LogLine>,
+) {
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(stream);
+        let mut buffer = Vec::new();
+        while matches!(reader.read_until(b'\n', &mut buffer).await, Ok(read) if read > 0) {
+            let text = String::from_utf8_lossy(&buffer);
+            let line = text.trim_end_matches(['\n', '\r']).to_string();
+            buffer.clear();
+            if lines.send(LogLine::Output(tag, line)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Appends lines to the process's log until every sender is gone.
+async fn write_log(mut log: RotatingLog, mut lines: mpsc::UnboundedReceiver<LogLine>) {
+    while let Some(line) = lines.recv().await {
+        let (tag, text) = match &line {
+            LogLine::Output(stream, text) => (stream.as_str(), text),
+            LogLine::Supervisor(text) => ("supervisor", text),
+        };
+        // A full disk must not stop the supervisor
+        let _ = log.write_line(tag, text);
+    }
+}
+
+/// Sleeps for a while, returning early with true if the handle is cancelled.
+async fn sleep_unless_cancelled(delay: Duration, cancel: &CancelHandle) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(delay) => cancel.is_cancelled(),
+        _ = cancel.cancelled() => true,
+    }
+}
+
+fn describe_status(status: &ExitStatus) -> String {
+    match (status.code(), exit_signal(status)) {
+        (Some(code), _) => format!("exited with code {}", code),
+        (None, Some(signal)) => format!("was killed by signal {}", signal),
+        (None, None) => "exited".to_string(),
+    }
+}
@@ -0,0 +1,54 @@
+use crate::answers::Answers;
+use crate::config_parser::{ConfigParser, ModuleConfig};
+use crate::error::Error;
+use std::collections::{BTreeSet, HashMap};
+
+#[test]
+fn test_answers_file_and_set_precedence() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("answers.yaml");
+    std::fs::write(&path, "TRANSLATION_API_PORT: 8090\nTRANSLATION_API_HOST: 0.0.0.0\nDEBUG: true\n").unwrap();
+
+    let sets = vec!["TRANSLATION_API_PORT=9000".to_string(), "EMPTY=".to_string()];
+    let answers = Answers::load(Some(&path), &sets, false).unwrap();
+
+    assert_eq!(answers.get("TRANSLATION_API_PORT"), Some("9000"));
+    assert_eq!(answers.get("TRANSLATION_API_HOST"), Some("0.0.0.0"));
+    assert_eq!(answers.get("DEBUG"), Some("true"));
+    assert_eq!(answers.get("EMPTY"), Some(""));
+    assert!(!answers.is_interactive());
+    assert!(Answers::load(None, &["NO_EQUALS".to_string()], false).is_err());
+}
+
+#[test]
+fn test_non_interactive_ask_uses_answers_then_defaults() {
+    let answers = Answers::load(None, &["KEY=value".to_string()], false).unwrap();
+    assert_eq!(answers.ask("KEY", "Enter KEY", None).unwrap().as_deref(), Some("value"));
+    assert_eq!(answers.ask("OTHER", "Enter OTHER", Some("default")).unwrap().as_deref(), Some("default"));
+    assert_eq!(answers.ask("OTHER", "Enter OTHER", None).unwrap(), None);
+}
+
+#[test]
+fn test_env_vars_report_every_missing_required_value() {
+    let mut config = ModuleConfig {
+        env_vars: HashMap::from([
+            ("API_KEY".to_string(), String::new()),
+            ("SECRET".to_string(), String::new()),
+            ("REGION".to_string(), "eu".to_string()),
+        ]),
+        commands: HashMap::new(),
+        env_descriptions: HashMap::new(),
+        required_env: BTreeSet::from(["API_KEY".to_string(), "SECRET".to_string()]),
+    };
+
+    let answers = Answers::load(None, &[], false).unwrap();
+    match ConfigParser::prompt_for_env_vars(&mut config, &answers) {
+        Err(Error::MissingValues(missing)) => assert_eq!(missing, vec!["API_KEY", "SECRET"]),
+        other => panic!("expected missing values, got {:?}", other),
+    }
+
+    let answers = Answers::load(None, &["API_KEY=k".to_string(), "SECRET=s".to_string()], false).unwrap();
+    ConfigParser::prompt_for_env_vars(&mut config, &answers).unwrap();
+    assert_eq!(config.env_vars["API_KEY"], "k");
+    assert_eq!(config.env_vars["REGION"], "eu");
+}
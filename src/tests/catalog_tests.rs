@@ -0,0 +1,64 @@
+use crate::catalog::{Catalog, CatalogEntry, Catalogs};
+use std::fs;
+use std::path::Path;
+
+fn entry(name: &str, path: &str) -> CatalogEntry {
+    CatalogEntry {
+        name: name.to_string(),
+        path: path.to_string(),
+    }
+}
+
+#[test]
+fn test_shipped_catalog_round_trips() {
+    let path = Path::new("subnets").join("commune.json");
+    let original = fs::read_to_string(&path).unwrap();
+    let catalog = Catalog::load(&path).unwrap();
+    assert_eq!(catalog.name, "commune");
+    assert_eq!(catalog.get("synthia").unwrap().path, "https://github.com/renlabs/synthia");
+
+    let dir = tempfile::tempdir().unwrap();
+    let copy = Catalog {
+        name: catalog.name.clone(),
+        path: dir.path().join("commune.json"),
+        entries: catalog.entries.clone(),
+    };
+    copy.save().unwrap();
+    assert_eq!(fs::read_to_string(&copy.path).unwrap().trim_end(), original.trim_end());
+}
+
+#[test]
+fn test_catalogs_find_and_search_across_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut commune = Catalog::load(&dir.path().join("commune.json")).unwrap();
+    commune.add(entry("synthia", "https://github.com/renlabs/synthia")).unwrap();
+    commune.save().unwrap();
+    let mut team = Catalog::load(&dir.path().join("team.json")).unwrap();
+    team.add(entry("eden-subnet", "https://github.com/agent-artificial/eden-subnet")).unwrap();
+    team.save().unwrap();
+    fs::create_dir(dir.path().join("synthia")).unwrap();
+
+    let catalogs = Catalogs::load_dir(dir.path()).unwrap();
+    assert_eq!(catalogs.catalogs.len(), 2);
+    let (catalog, found) = catalogs.find("eden-subnet").unwrap();
+    assert_eq!(catalog.name, "team");
+    assert_eq!(found.path, "https://github.com/agent-artificial/eden-subnet");
+    assert!(catalogs.find_by_url("https://github.com/renlabs/synthia.git").is_some());
+    assert_eq!(catalogs.search("RENLABS").len(), 1);
+    assert!(catalogs.search("missing").is_empty());
+}
+
+#[test]
+fn test_catalog_add_and_remove() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut catalogs = Catalogs::load_dir(dir.path()).unwrap();
+    let catalog = catalogs.get_or_create("testnet").unwrap();
+    catalog.add(entry("zangief", "https://github.com/nakamoto-ai/zangief")).unwrap();
+    assert!(catalog.add(entry("zangief", "https://example.com/other")).is_err());
+    catalog.save().unwrap();
+
+    let mut reloaded = Catalog::load(&dir.path().join("testnet.json")).unwrap();
+    assert!(reloaded.remove("zangief"));
+    assert!(!reloaded.remove("zangief"));
+    assert!(reloaded.entries.is_empty());
+}
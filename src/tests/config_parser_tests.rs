@@ -0,0 +1,58 @@
+use crate::config_parser::ConfigParser;
+use crate::error::Error;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+
+const TYPER_CLI: &str = r#"
+import typer
+
+app = typer.Typer()
+
+@app.command("transfer")
+def transfer(key: str, amount: float, dest_ss58: str = typer.Option(default="", help="Destination"), dry_run: bool = typer.Option(default="False")):
+    pass
+"#;
+
+fn values(value: Value) -> HashMap<String, Value> {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn test_command_args_to_argv() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("cli.py"), TYPER_CLI).unwrap();
+    let config = ConfigParser::parse_commands(dir.path()).unwrap();
+    let command = &config.commands["transfer"];
+    assert!(command.args["key"].required);
+    assert!(!command.args["dest_ss58"].required);
+
+    // Values are separate arguments, so shell syntax in them stays literal
+    let argv = command
+        .to_argv(&values(json!({
+            "key": "alice; rm -rf ~",
+            "amount": 1.5,
+            "dest_ss58": "$(whoami)",
+            "dry_run": false,
+        })))
+        .unwrap();
+    assert_eq!(
+        argv,
+        vec!["--amount", "1.5", "--dest-ss58", "$(whoami)", "--no-dry-run", "--key", "alice; rm -rf ~"]
+    );
+}
+
+#[test]
+fn test_command_args_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("cli.py"), TYPER_CLI).unwrap();
+    let config = ConfigParser::parse_commands(dir.path()).unwrap();
+    let command = &config.commands["transfer"];
+
+    let unknown = command.to_argv(&values(json!({"key": "alice", "amount": 1, "shell": "bash"})));
+    assert!(matches!(unknown, Err(Error::InvalidInput(message)) if message.contains("shell")));
+    let missing = command.to_argv(&values(json!({"key": "alice"})));
+    assert!(matches!(missing, Err(Error::InvalidInput(message)) if message.contains("amount")));
+    let nested = command.to_argv(&values(json!({"key": {"name": "alice"}, "amount": 1})));
+    assert!(matches!(nested, Err(Error::InvalidInput(_))));
+}
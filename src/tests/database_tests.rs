@@ -0,0 +1,124 @@
+use crate::registry::{ModuleRegistry, ModuleType, NewModule};
+use tempfile::TempDir;
+
+/// Opens a registry on a fresh SQLite file, plus one on the Postgres database named
+/// by `TEST_DATABASE_URL` when it is set.
+async fn test_registries() -> (Vec<ModuleRegistry>, TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    let sqlite_url = format!("sqlite://{}", dir.path().join("data").join("modules.db").display());
+    let mut registries = vec![ModuleRegistry::connect(&sqlite_url).await.unwrap()];
+    if let Ok(database_url) = std::env::var("TEST_DATABASE_URL") {
+        registries.push(ModuleRegistry::connect(&database_url).await.unwrap());
+    }
+    (registries, dir)
+}
+
+fn new_module(name: &str, module_type: ModuleType) -> NewModule {
+    NewModule {
+        name: name.to_string(),
+        module_type,
+        version: Some("1.0.0".to_string()),
+        entry_point: None,
+        install_path: format!("{}/{}", module_type.directory(), name),
+        python_version: Some("3.11.2".to_string()),
+        python_path: Some("/usr/bin/python3.11".to_string()),
+    }
+}
+
+#[test]
+fn test_module_type_round_trip() {
+    assert_eq!("inference".parse::<ModuleType>().unwrap(), ModuleType::Inference);
+    assert_eq!("subnets".parse::<ModuleType>().unwrap(), ModuleType::Subnet);
+    assert_eq!(ModuleType::Subnet.to_string(), "subnet");
+    assert!("plugin".parse::<ModuleType>().is_err());
+}
+
+#[tokio::test]
+async fn test_unsupported_database_url() {
+    assert!(ModuleRegistry::connect("mysql://localhost/modules").await.is_err());
+}
+
+#[tokio::test]
+async fn test_sqlite_migrations_are_idempotent() {
+    let dir = tempfile::tempdir().unwrap();
+    let sqlite_url = format!("sqlite://{}", dir.path().join("modules.db").display());
+    let registry = ModuleRegistry::connect(&sqlite_url).await.unwrap();
+    registry.register(&new_module("db_test_reopen", ModuleType::Inference)).await.unwrap();
+
+    let reopened = ModuleRegistry::connect(&sqlite_url).await.unwrap();
+    assert!(reopened.get("db_test_reopen").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_registry_register_and_get() {
+    let (registries, _dir) = test_registries().await;
+    for registry in registries {
+        registry.register(&new_module("db_test_translation", ModuleType::Inference)).await.unwrap();
+
+        let record = registry.get("db_test_translation").await.unwrap().unwrap();
+        assert_eq!(record.module_type, Some(ModuleType::Inference));
+        assert_eq!(record.version.as_deref(), Some("1.0.0"));
+        assert_eq!(record.python_version.as_deref(), Some("3.11.2"));
+        assert_eq!(record.python_path.as_deref(), Some("/usr/bin/python3.11"));
+        assert_eq!(record.install_path.as_deref(), Some("modules/db_test_translation"));
+        assert!(record.installed_at.is_some());
+    }
+}
+
+#[tokio::test]
+async fn test_registry_reinstall_updates_row() {
+    let (registries, _dir) = test_registries().await;
+    for registry in registries {
+        let mut module = new_module("db_test_reinstall", ModuleType::Subnet);
+        registry.register(&module).await.unwrap();
+        module.version = Some("abc1234".to_string());
+        registry.register(&module).await.unwrap();
+
+        let record = registry.get("db_test_reinstall").await.unwrap().unwrap();
+        assert_eq!(record.version.as_deref(), Some("abc1234"));
+    }
+}
+
+#[tokio::test]
+async fn test_registry_reinstall_keeps_installed_at() {
+    let (registries, _dir) = test_registries().await;
+    for registry in registries {
+        let module = new_module("db_test_installed_at", ModuleType::Subnet);
+        registry.register(&module).await.unwrap();
+        let installed = registry.get("db_test_installed_at").await.unwrap().unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        registry.register(&module).await.unwrap();
+
+        let updated = registry.get("db_test_installed_at").await.unwrap().unwrap();
+        assert_eq!(updated.installed_at, installed.installed_at);
+        assert!(updated.updated_at > installed.updated_at);
+    }
+}
+
+#[tokio::test]
+async fn test_registry_list_by_type_and_mark_used() {
+    let (registries, _dir) = test_registries().await;
+    for registry in registries {
+        registry.register(&new_module("db_test_subnet", ModuleType::Subnet)).await.unwrap();
+        registry.register(&new_module("db_test_embedding", ModuleType::Inference)).await.unwrap();
+        registry.mark_used("db_test_subnet").await.unwrap();
+
+        let subnets = registry.list_by_type(ModuleType::Subnet).await.unwrap();
+        let record = subnets.iter().find(|m| m.name == "db_test_subnet").unwrap();
+        assert!(record.last_used_at.is_some());
+        assert!(subnets.iter().all(|m| m.module_type == Some(ModuleType::Subnet)));
+        assert!(registry.get("db_test_missing").await.unwrap().is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_registry_unregister() {
+    let (registries, _dir) = test_registries().await;
+    for registry in registries {
+        registry.register(&new_module("db_test_uninstall", ModuleType::Inference)).await.unwrap();
+
+        assert!(registry.unregister("db_test_uninstall").await.unwrap());
+        assert!(registry.get("db_test_uninstall").await.unwrap().is_none());
+        assert!(!registry.unregister("db_test_uninstall").await.unwrap());
+    }
+}
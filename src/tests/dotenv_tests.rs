@@ -0,0 +1,92 @@
+use crate::dotenv::{self, EnvSource, ResolvedEnv};
+use crate::error::Error;
+use std::collections::BTreeMap;
+use std::fs;
+
+#[test]
+fn test_parse_dotenv_syntax() {
+    let content = r#"
+# A comment
+export HOST=0.0.0.0
+PORT = 8000 # inline comment
+COLOR=#ffffff
+SINGLE='literal ${HOST} \n'
+DOUBLE="line one\nsay \"hi\""
+MULTI="first
+second"
+URL=http://${HOST}:$PORT/api
+FALLBACK=${MISSING:-default}
+HOME_DIR=${HOME}
+ESCAPED="costs \$5"
+EMPTY=
+"#;
+    let lookup = |name: &str| (name == "HOME").then(|| "/home/validator".to_string());
+    let entries: BTreeMap<String, String> = dotenv::parse(content, lookup).unwrap().into_iter().collect();
+
+    assert_eq!(entries["HOST"], "0.0.0.0");
+    assert_eq!(entries["PORT"], "8000");
+    assert_eq!(entries["COLOR"], "#ffffff");
+    assert_eq!(entries["SINGLE"], "literal ${HOST} \\n");
+    assert_eq!(entries["DOUBLE"], "line one\nsay \"hi\"");
+    assert_eq!(entries["MULTI"], "first\nsecond");
+    assert_eq!(entries["URL"], "http://0.0.0.0:8000/api");
+    assert_eq!(entries["FALLBACK"], "default");
+    assert_eq!(entries["HOME_DIR"], "/home/validator");
+    assert_eq!(entries["ESCAPED"], "costs $5");
+    assert_eq!(entries["EMPTY"], "");
+    assert_eq!(entries.len(), 11);
+}
+
+#[test]
+fn test_parse_dotenv_reports_line() {
+    let error = dotenv::parse("A=1\n\nB=\"unterminated\n", |_| None).unwrap_err();
+    assert!(matches!(&error, Error::ConfigInvalid(reason) if reason.starts_with("line 3:")), "{}", error);
+
+    let error = dotenv::parse("A=1\nnot a variable\n", |_| None).unwrap_err();
+    assert!(matches!(&error, Error::ConfigInvalid(reason) if reason.starts_with("line 2:")), "{}", error);
+}
+
+#[test]
+fn test_resolve_layers_in_order() {
+    let root = tempfile::tempdir().unwrap();
+    let module_dir = root.path().join("subnets").join("demo");
+    fs::create_dir_all(&module_dir).unwrap();
+    fs::write(root.path().join(".env"), "SHARED=global\nGLOBAL_ONLY=1\n").unwrap();
+    fs::write(module_dir.join(".env"), "SHARED=module\nLEVEL=module\nDERIVED=${SHARED}-${GLOBAL_ONLY}\n").unwrap();
+    fs::write(module_dir.join(".env.staging"), "LEVEL=staging\n").unwrap();
+
+    let overrides = BTreeMap::from([("CALL".to_string(), "override".to_string())]);
+    let resolved = dotenv::resolve(root.path(), &module_dir, Some("staging"), &overrides).unwrap();
+
+    assert_eq!(resolved.get("GLOBAL_ONLY"), Some("1"));
+    assert_eq!(resolved.source("GLOBAL_ONLY"), Some(&EnvSource::Global(root.path().join(".env"))));
+    assert_eq!(resolved.get("SHARED"), Some("module"));
+    assert_eq!(resolved.get("DERIVED"), Some("module-1"));
+    assert_eq!(resolved.get("LEVEL"), Some("staging"));
+    assert_eq!(resolved.source("LEVEL"), Some(&EnvSource::Profile(module_dir.join(".env.staging"))));
+    assert_eq!(resolved.source("CALL"), Some(&EnvSource::Override));
+
+    // Without a profile or any .env file, only the process environment is left
+    let empty = tempfile::tempdir().unwrap();
+    let resolved = dotenv::resolve(empty.path(), empty.path(), None, &BTreeMap::new()).unwrap();
+    assert_eq!(resolved.layered().count(), 0);
+}
+
+#[test]
+fn test_later_layers_override_process_env() {
+    let dir = tempfile::tempdir().unwrap();
+    let env_file = dir.path().join(".env");
+    fs::write(&env_file, "TOKEN=from-file\nURL=${BASE}/v1\n").unwrap();
+
+    let mut resolved = ResolvedEnv::new([
+        ("TOKEN".to_string(), "from-process".to_string()),
+        ("BASE".to_string(), "http://localhost".to_string()),
+    ]);
+    resolved.apply_file(&env_file, EnvSource::Module(env_file.clone())).unwrap();
+
+    assert_eq!(resolved.get("TOKEN"), Some("from-file"));
+    assert_eq!(resolved.get("URL"), Some("http://localhost/v1"));
+    assert_eq!(resolved.source("BASE"), Some(&EnvSource::Process));
+    let layered: Vec<_> = resolved.layered().map(|(key, _)| key).collect();
+    assert_eq!(layered, ["TOKEN", "URL"]);
+}
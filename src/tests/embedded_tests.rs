@@ -0,0 +1,74 @@
+use crate::error::Error;
+use crate::inference::embedded::EmbeddedExecutor;
+use pyo3::Python;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+/// Lays out an inference module and an empty virtual environment for the embedded interpreter.
+fn write_module(root: &Path, name: &str, code: &str) {
+    let module_dir = root.join("modules").join(name);
+    fs::create_dir_all(&module_dir).unwrap();
+    fs::write(module_dir.join(format!("{}.py", name)), code).unwrap();
+    fs::write(module_dir.join(".env"), format!("{}_GREETING=\"hello\"\n", name.to_uppercase())).unwrap();
+
+    let version = Python::with_gil(|py| {
+        let version = py.version_info();
+        format!("python{}.{}", version.major, version.minor)
+    });
+    fs::create_dir_all(root.join(format!(".{}", name)).join("lib").join(version).join("site-packages")).unwrap();
+}
+
+#[test]
+fn test_embedded_module_is_loaded_once() {
+    let dir = tempfile::tempdir().unwrap();
+    write_module(
+        dir.path(),
+        "embedded_echo",
+        r#"
+import os
+
+loads = 0
+calls = 0
+
+def load():
+    global loads
+    loads += 1
+
+def process(text, suffix=""):
+    global calls
+    calls += 1
+    return {"text": os.environ["EMBEDDED_ECHO_GREETING"] + " " + text + suffix, "loads": loads, "calls": calls}
+"#,
+    );
+
+    let executor = EmbeddedExecutor::start_in(dir.path(), "embedded_echo").unwrap();
+    let first = executor.process(json!("world")).unwrap();
+    assert_eq!(first, json!({"text": "hello world", "loads": 1, "calls": 1}));
+    let second = executor.process(json!({"text": "again", "suffix": "!"})).unwrap();
+    assert_eq!(second, json!({"text": "hello again!", "loads": 1, "calls": 2}));
+}
+
+#[test]
+fn test_embedded_exception_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    write_module(
+        dir.path(),
+        "embedded_failing",
+        "def process(*args):\n    raise ValueError('bad input: %r' % (args,))\n",
+    );
+
+    let executor = EmbeddedExecutor::start_in(dir.path(), "embedded_failing").unwrap();
+    match executor.process(json!([1, 2])) {
+        Err(Error::PythonFailed { module, message }) => {
+            assert_eq!(module, "embedded_failing");
+            assert!(message.contains("ValueError: bad input: (1, 2)"), "{}", message);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert!(matches!(
+        EmbeddedExecutor::start_in(dir.path(), "missing"),
+        Err(Error::ModuleNotFound(_))
+    ));
+}
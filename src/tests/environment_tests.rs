@@ -0,0 +1,231 @@
+use crate::config::{Config, ConfigOverrides};
+use crate::environment::interpreter::{self, PythonVersion, VersionSpec};
+use crate::environment::provider::EnvProviderKind;
+use crate::error::Error;
+use crate::environment::{ModuleEnv, Provisioning};
+use crate::sandbox::SandboxPolicy;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Writes a stand-in for `python3` that logs its arguments, creates virtual environments
+/// by copying itself, and accepts any pip command.
+fn fake_python(dir: &Path, version: &str) -> PathBuf {
+    fake_interpreter(dir, &format!("python-{}", version), version)
+}
+
+/// Writes a fake interpreter under the given name, reporting the given version.
+fn fake_interpreter(dir: &Path, name: &str, version: &str) -> PathBuf {
+    let log = dir.join("python.log");
+    let path = dir.join(name);
+    let script = format!(
+        "#!/bin/sh\necho \"$@\" >> {log}\ncase \"$1\" in\n  --version) echo \"Python {version}\" ;;\n  -m) if [ \"$2\" = venv ]; then mkdir -p \"$3/bin\" && cp \"$0\" \"$3/bin/python3\"; fi ;;\nesac\n",
+        log = log.display(),
+        version = version,
+    );
+    fs::write(&path, script).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+/// Returns the commands run so far, other than version checks.
+fn logged_commands(dir: &Path) -> Vec<String> {
+    fs::read_to_string(dir.join("python.log"))
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| *line != "--version")
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn test_provision_only_when_inputs_change() {
+    let dir = tempfile::tempdir().unwrap();
+    let module_dir = dir.path().join("module");
+    fs::create_dir(&module_dir).unwrap();
+    fs::write(module_dir.join("requirements.txt"), "requests\n").unwrap();
+    let env = ModuleEnv::new(dir.path().join(".module"), &module_dir)
+        .unwrap()
+        .with_base_python(fake_python(dir.path(), "3.99.0"));
+
+    assert_eq!(env.provision(None).unwrap(), Provisioning::Created);
+    let commands = logged_commands(dir.path());
+    assert!(commands[0].starts_with("-m venv"));
+    assert!(commands.last().unwrap().contains("-m pip install -r"));
+    assert_eq!(env.marker().unwrap().interpreter, "Python 3.99.0");
+
+    assert_eq!(env.provision(None).unwrap(), Provisioning::UpToDate);
+    assert_eq!(logged_commands(dir.path()), commands);
+
+    fs::write(module_dir.join("requirements.txt"), "requests\nnumpy\n").unwrap();
+    assert_eq!(env.provision(None).unwrap(), Provisioning::Updated);
+    let reinstalled = &logged_commands(dir.path())[commands.len()..];
+    assert!(!reinstalled.iter().any(|command| command.starts_with("-m venv")));
+    assert!(reinstalled.last().unwrap().contains("-m pip install -r"));
+}
+
+#[test]
+fn test_requirements_install_under_the_sandbox_policy() {
+    let dir = tempfile::tempdir().unwrap();
+    let module_dir = dir.path().join("module");
+    fs::create_dir(&module_dir).unwrap();
+    fs::write(module_dir.join("requirements.txt"), "requests\n").unwrap();
+    let env = ModuleEnv::new(dir.path().join(".module"), &module_dir)
+        .unwrap()
+        .with_base_python(fake_python(dir.path(), "3.99.0"));
+    env.provision(None).unwrap();
+
+    // The environment's interpreter now records where and how pip runs
+    let record = dir.path().join("pip.log");
+    let python = env.python();
+    let script = format!("#!/bin/sh\necho \"$(pwd -P)|$(ulimit -n)|$1 $2 $3\" >> {}\n", record.display());
+    fs::write(&python, script).unwrap();
+    fs::set_permissions(&python, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let policy = SandboxPolicy {
+        open_files: Some(64),
+        ..SandboxPolicy::default()
+    };
+    fs::write(module_dir.join("requirements.txt"), "requests\nnumpy\n").unwrap();
+    assert_eq!(env.provision(Some(&policy)).unwrap(), Provisioning::Updated);
+    let module_dir = fs::canonicalize(&module_dir).unwrap();
+    let recorded = fs::read_to_string(&record).unwrap();
+    assert!(!recorded.is_empty());
+    for line in recorded.lines() {
+        assert!(line.starts_with(&format!("{}|64|-m pip install", module_dir.display())), "{}", line);
+    }
+}
+
+#[test]
+fn test_provision_recreates_env_for_new_interpreter() {
+    let dir = tempfile::tempdir().unwrap();
+    let module_dir = dir.path().join("module");
+    fs::create_dir(&module_dir).unwrap();
+    let venv_dir = dir.path().join(".module");
+    let env = ModuleEnv::new(&venv_dir, &module_dir)
+        .unwrap()
+        .with_base_python(fake_python(dir.path(), "3.99.0"));
+    env.provision(None).unwrap();
+    fs::write(venv_dir.join("leftover"), "").unwrap();
+
+    let upgraded = ModuleEnv::new(&venv_dir, &module_dir)
+        .unwrap()
+        .with_base_python(fake_python(dir.path(), "3.100.0"));
+    assert_eq!(upgraded.provision(None).unwrap(), Provisioning::Created);
+    assert!(!venv_dir.join("leftover").exists());
+    assert_eq!(upgraded.marker().unwrap().interpreter, "Python 3.100.0");
+
+    fs::write(venv_dir.join("leftover"), "").unwrap();
+    upgraded.rebuild(None).unwrap();
+    assert!(!venv_dir.join("leftover").exists());
+    assert!(upgraded.marker().is_some());
+}
+
+#[test]
+fn test_env_provider_from_manifest_and_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let module_dir = dir.path().join("module");
+    fs::create_dir(&module_dir).unwrap();
+    fs::write(module_dir.join("module.toml"), "environment = \"system\"\n").unwrap();
+    let venv_dir = dir.path().join(".module");
+    let python = fake_python(dir.path(), "3.99.0");
+
+    // The system provider links the base interpreter instead of creating a venv
+    let env = ModuleEnv::new(&venv_dir, &module_dir).unwrap().with_base_python(&python);
+    assert_eq!(env.provider(), EnvProviderKind::System);
+    assert_eq!(env.provision(None).unwrap(), Provisioning::Created);
+    assert_eq!(fs::read_link(env.python()).unwrap(), python);
+    assert!(!logged_commands(dir.path()).iter().any(|command| command.starts_with("-m venv")));
+
+    // A provider chosen in the configuration wins, and switching providers recreates the environment
+    let config = dir.path().join("config.yaml");
+    fs::write(&config, "env_providers:\n  \"*\": venv\n").unwrap();
+    let loaded = Config::load_layers(&[config], |_| None, &ConfigOverrides::default()).unwrap();
+    let configured = loaded.config.env_providers.for_module("module");
+    let env = ModuleEnv::new(&venv_dir, &module_dir).unwrap().with_provider(configured).with_base_python(&python);
+    assert_eq!(env.provision(None).unwrap(), Provisioning::Created);
+    assert!(fs::read_link(env.python()).is_err());
+    assert_eq!(env.marker().unwrap().provider, EnvProviderKind::Venv);
+
+    // Running the module keeps the provider it was provisioned with
+    let recorded = ModuleEnv::new(&venv_dir, &module_dir).unwrap().with_recorded_provider();
+    assert_eq!(recorded.provider(), EnvProviderKind::Venv);
+}
+
+#[test]
+fn test_version_spec_matching() {
+    let version = |text: &str| PythonVersion::parse(text).unwrap();
+    let spec: VersionSpec = ">=3.9, <3.12, !=3.10.*".parse().unwrap();
+    assert!(spec.matches(version("Python 3.9.18")));
+    assert!(!spec.matches(version("3.10.4")));
+    assert!(spec.matches(version("3.11.0rc1")));
+    assert!(!spec.matches(version("3.12.1")));
+
+    let compatible: VersionSpec = "~=3.10".parse().unwrap();
+    assert!(compatible.matches(version("3.13.0")));
+    assert!(!compatible.matches(version("4.0.0")));
+    let bare: VersionSpec = "3.9".parse().unwrap();
+    assert!(bare.matches(version("3.9.7")) && !bare.matches(version("3.10.0")));
+
+    assert!(">=three".parse::<VersionSpec>().is_err());
+    assert!(">=3.9.*".parse::<VersionSpec>().is_err());
+}
+
+#[test]
+fn test_find_interpreter_for_constraint() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path_dir, pyenv_dir) = (dir.path().join("bin"), dir.path().join("pyenv"));
+    fs::create_dir(&path_dir).unwrap();
+    fs::create_dir(&pyenv_dir).unwrap();
+    fake_interpreter(&path_dir, "python3", "3.8.10");
+    fake_interpreter(&path_dir, "python3.9", "3.9.18");
+    fake_interpreter(&pyenv_dir, "python3.11", "3.11.9");
+    fake_interpreter(&pyenv_dir, "python3.12", "3.12.4");
+    fake_interpreter(&pyenv_dir, "python3-config", "0.0.0");
+    let dirs = [path_dir.clone(), pyenv_dir.clone()];
+
+    // The default python3 is kept when there is no constraint, or it satisfies it
+    assert_eq!(interpreter::find(None, &dirs).unwrap().path, path_dir.join("python3"));
+    let spec: VersionSpec = ">=3.8,<3.10".parse().unwrap();
+    assert_eq!(interpreter::find(Some(&spec), &dirs).unwrap().path, path_dir.join("python3"));
+
+    let spec: VersionSpec = ">=3.10".parse().unwrap();
+    assert_eq!(interpreter::find(Some(&spec), &dirs).unwrap().path, pyenv_dir.join("python3.12"));
+    let spec: VersionSpec = "~=3.11.0".parse().unwrap();
+    assert_eq!(interpreter::find(Some(&spec), &dirs).unwrap().version.to_string(), "3.11.9");
+
+    let spec: VersionSpec = ">=3.13".parse().unwrap();
+    match interpreter::find(Some(&spec), &dirs) {
+        Err(Error::InterpreterNotFound { requirement, found }) => {
+            assert_eq!(requirement, ">=3.13");
+            assert_eq!(found.len(), 4);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_module_env_checks_requires_python() {
+    let dir = tempfile::tempdir().unwrap();
+    let module_dir = dir.path().join("module");
+    fs::create_dir(&module_dir).unwrap();
+    fs::write(module_dir.join("pyproject.toml"), "[project]\nname = \"module\"\nrequires-python = \">=3.10\"\n").unwrap();
+    let venv_dir = dir.path().join(".module");
+
+    let env = ModuleEnv::new(&venv_dir, &module_dir).unwrap();
+    assert_eq!(env.requires_python().unwrap().to_string(), ">=3.10");
+    let too_old = env.clone().with_base_python(fake_python(dir.path(), "3.9.18"));
+    assert!(matches!(too_old.provision(None), Err(Error::InterpreterNotFound { .. })));
+    assert!(!venv_dir.exists());
+
+    let env = env.with_base_python(fake_python(dir.path(), "3.11.9"));
+    assert_eq!(env.provision(None).unwrap(), Provisioning::Created);
+    let marker = env.marker().unwrap();
+    assert_eq!(marker.python_version(), "3.11.9");
+    assert_eq!(marker.python, Some(dir.path().join("python-3.11.9")));
+
+    // The manifest's constraint takes precedence over pyproject.toml
+    fs::write(module_dir.join("module.toml"), "python = \"<3.10\"\n").unwrap();
+    let env = ModuleEnv::new(&venv_dir, &module_dir).unwrap();
+    assert_eq!(env.requires_python().unwrap().to_string(), "<3.10");
+}
@@ -0,0 +1,45 @@
+use crate::error::Error;
+use actix_web::http::StatusCode;
+use actix_web::ResponseError;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+#[test]
+fn test_process_failed_keeps_stderr_tail() {
+    let stderr: String = (1..=30).map(|i| format!("line {}\n", i)).collect();
+    let error = Error::process_failed("python -m miner", ExitStatus::from_raw(1 << 8), &stderr);
+
+    match &error {
+        Error::ProcessFailed { exit_code, stderr_tail, .. } => {
+            assert_eq!(*exit_code, Some(1));
+            assert!(stderr_tail.starts_with("line 11\n"));
+            assert!(stderr_tail.ends_with("line 30"));
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(error.to_string().starts_with("python -m miner failed with exit code 1:\nline 11"));
+}
+
+#[test]
+fn test_exit_codes_and_statuses() {
+    let not_found = Error::ModuleNotFound("synthia".to_string());
+    assert_eq!(not_found.exit_code(), 3);
+    assert_eq!(not_found.status_code(), StatusCode::NOT_FOUND);
+
+    let failed = Error::process_failed("bash setup.sh", ExitStatus::from_raw(2 << 8), "");
+    assert_eq!(failed.exit_code(), 8);
+    assert_eq!(failed.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(failed.to_string(), "bash setup.sh failed with exit code 2");
+
+    let timeout = Error::Timeout {
+        command: "Python module miner".to_string(),
+        timeout: std::time::Duration::from_secs(30),
+    };
+    assert_eq!(timeout.exit_code(), 10);
+    assert_eq!(timeout.status_code(), StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(timeout.to_string(), "Python module miner timed out after 30s and was stopped");
+
+    let config = Error::ConfigInvalid("config.yaml: missing field".to_string());
+    assert_eq!(config.exit_code(), 5);
+    assert_eq!(config.kind(), "config_invalid");
+}
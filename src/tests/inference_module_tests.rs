@@ -1,5 +1,12 @@
+use crate::answers::Answers;
+use crate::environment::provider::{EnvProviderKind, EnvProviders, ALL_MODULES};
 use crate::modules::inference_module::InferenceModule;
-use std::path::PathBuf;
+use crate::modules::InstallOptions;
+use crate::registry::{ModuleRegistry, ModuleType};
+use std::collections::BTreeMap;
+use std::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
 
 #[tokio::test]
 async fn test_inference_module_creation() {
@@ -15,17 +22,51 @@ async fn test_inference_module_creation_with_url() {
     assert_eq!(module.url, "https://example.com/modules/custom_module");
 }
 
+/// Serves one response as the registrar would, and returns the URL of the module on it.
+async fn serve_module(name: &str, body: String) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/modules/{}", listener.local_addr().unwrap(), name);
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = [0; 4096];
+        let _ = stream.read(&mut request).await.unwrap();
+        let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+        stream.write_all(response.as_bytes()).await.unwrap();
+    });
+    url
+}
+
 #[tokio::test]
 async fn test_inference_module_install() {
-    let module = InferenceModule::new("test_module").unwrap();
-    let result = module.install().await;
-    assert!(result.is_ok());
-    
+    let root = tempfile::tempdir().unwrap();
+    let registry = ModuleRegistry::connect(&format!("sqlite://{}", root.path().join("modules.db").display()))
+        .await
+        .unwrap();
+    let url = serve_module("test_module", format!("\"{}\"", base64::encode("print('setup ran')\n"))).await;
+    let module = InferenceModule::new(&url).unwrap().with_root_dir(root.path());
+    let sets = ["TEST_MODULE_API_PORT=8000".to_string(), "TEST_MODULE_API_HOST=0.0.0.0".to_string()];
+    let options = InstallOptions {
+        answers: Answers::load(None, &sets, false).unwrap(),
+        // The system interpreter needs no environment to be created or downloaded
+        env_providers: EnvProviders(BTreeMap::from([(ALL_MODULES.to_string(), EnvProviderKind::System)])),
+        ..Default::default()
+    };
+    module.install(&registry, &options).await.unwrap();
+
     // Check if the module directory was created
-    let module_dir = PathBuf::from("modules").join("test_module");
+    let module_dir = root.path().join("modules").join("test_module");
     assert!(module_dir.exists());
-    
+
     // Check if the setup script was created
     let setup_script = module_dir.join("setup_test_module.py");
-    assert!(setup_script.exists());
-}
\ No newline at end of file
+    assert_eq!(fs::read_to_string(setup_script).unwrap(), "print('setup ran')\n");
+    assert!(root.path().join(".test_module").join("bin").join("python3").exists());
+    assert_eq!(
+        fs::read_to_string(root.path().join(".env")).unwrap(),
+        "TEST_MODULE_API_PORT=8000\nTEST_MODULE_API_HOST=0.0.0.0\n"
+    );
+
+    let record = registry.get("test_module").await.unwrap().unwrap();
+    assert_eq!(record.module_type, Some(ModuleType::Inference));
+    assert_eq!(record.install_path.as_deref(), Some(module_dir.to_str().unwrap()));
+}
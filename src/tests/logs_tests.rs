@@ -0,0 +1,108 @@
+use crate::error::Error;
+use crate::logs::{self, filter_lines, parse_since, rotated_files, LogSettings, LogTail, RotatingLog};
+use chrono::{Duration, TimeZone, Utc};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// Returns log settings keeping logs in a directory.
+fn settings_in(dir: &Path) -> LogSettings {
+    LogSettings {
+        dir: dir.to_path_buf(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_log_paths_and_roles() {
+    let dir = tempfile::tempdir().unwrap();
+    let settings = settings_in(dir.path());
+    assert_eq!(settings.path("demo", "miner").unwrap(), dir.path().join("demo").join("miner.log"));
+    for (module, role) in [("../escape", "miner"), ("demo", ".hidden"), ("demo", "a/b"), ("", "miner")] {
+        assert!(matches!(settings.path(module, role), Err(Error::InvalidInput(_))), "{}/{} was accepted", module, role);
+    }
+
+    assert!(settings.roles("demo").unwrap().is_empty());
+    let mut log = RotatingLog::open(&settings, "demo", "validator").unwrap();
+    log.write_line("stdout", "hello").unwrap();
+    log.rotate(Utc::now()).unwrap();
+    RotatingLog::open(&settings, "demo", "inference").unwrap();
+    assert_eq!(settings.roles("demo").unwrap(), ["inference", "validator"]);
+}
+
+#[test]
+fn test_rotation_keeps_compressed_files_up_to_retention() {
+    let dir = tempfile::tempdir().unwrap();
+    let settings = LogSettings {
+        retain: 2,
+        ..settings_in(dir.path())
+    };
+    let mut log = RotatingLog::open(&settings, "demo", "miner").unwrap();
+    let start = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+    for i in 0..4 {
+        log.write_line("stdout", &format!("line {}", i)).unwrap();
+        log.rotate(start + Duration::minutes(i)).unwrap();
+    }
+    log.write_line("stderr", "current").unwrap();
+
+    let rotated = rotated_files(log.path()).unwrap();
+    let names: Vec<_> = rotated.iter().map(|path| path.file_name().unwrap().to_string_lossy().into_owned()).collect();
+    assert_eq!(names, ["miner-20260101T000200.000Z.log.gz", "miner-20260101T000300.000Z.log.gz"]);
+
+    let lines = logs::read_all(log.path()).unwrap();
+    let texts: Vec<_> = lines.iter().map(|line| line.split_once(' ').unwrap().1).collect();
+    assert_eq!(texts, ["stdout line 2", "stdout line 3", "stderr current"]);
+}
+
+#[test]
+fn test_old_logs_are_rotated_on_the_next_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let settings = LogSettings {
+        compress: false,
+        ..settings_in(dir.path())
+    };
+    let path = settings.path("demo", "miner").unwrap();
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let old = (Utc::now() - Duration::hours(25)).format("%Y-%m-%dT%H:%M:%S%.3fZ");
+    fs::write(&path, format!("{} stdout yesterday\n", old)).unwrap();
+
+    let mut log = RotatingLog::open(&settings, "demo", "miner").unwrap();
+    log.write_line("stdout", "today").unwrap();
+    let rotated = rotated_files(&path).unwrap();
+    assert_eq!(rotated.len(), 1);
+    assert_eq!(rotated[0].extension().unwrap(), "log");
+    assert!(fs::read_to_string(&rotated[0]).unwrap().ends_with("stdout yesterday\n"));
+    assert!(fs::read_to_string(&path).unwrap().ends_with("stdout today\n"));
+}
+
+#[test]
+fn test_log_tail_and_filters() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("output.log");
+    let mut tail = LogTail::new(&path);
+    assert!(tail.read_lines().unwrap().is_empty());
+
+    let mut log = OpenOptions::new().create(true).append(true).open(&path).unwrap();
+    write!(log, "2026-01-01T10:00:00.000Z stdout first\nTraceback\n2026-01-01T10:05:00.000Z stderr sec").unwrap();
+    assert_eq!(tail.read_lines().unwrap(), ["2026-01-01T10:00:00.000Z stdout first", "Traceback"]);
+    writeln!(log, "ond\n2026-01-01T10:10:00.000Z stdout third").unwrap();
+    let lines = tail.read_lines().unwrap();
+    assert_eq!(lines, ["2026-01-01T10:05:00.000Z stderr second", "2026-01-01T10:10:00.000Z stdout third"]);
+
+    // A replaced file is read from its start
+    fs::write(&path, "2026-01-01T11:00:00.000Z stdout new\n").unwrap();
+    assert_eq!(tail.read_lines().unwrap(), ["2026-01-01T11:00:00.000Z stdout new"]);
+
+    let all = LogTail::new(&path).read_lines().unwrap();
+    let mut lines = vec!["2026-01-01T10:00:00.000Z stdout first".to_string(), "Traceback".to_string()];
+    lines.extend(all);
+    let noon = Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap();
+    let since = parse_since("1h30m", noon);
+    assert!(since.is_err());
+    let since = parse_since("90m", noon).unwrap();
+    assert_eq!(since, Utc.with_ymd_and_hms(2026, 1, 1, 10, 30, 0).unwrap());
+    assert_eq!(filter_lines(lines.clone(), Some(since), None), ["2026-01-01T11:00:00.000Z stdout new"]);
+    let since = parse_since("2026-01-01T09:00:00Z", noon).unwrap();
+    assert_eq!(filter_lines(lines.clone(), Some(since), None), lines);
+    assert_eq!(filter_lines(lines, None, Some(2)), ["Traceback", "2026-01-01T11:00:00.000Z stdout new"]);
+}
@@ -0,0 +1,110 @@
+use crate::config_parser::ConfigParser;
+use crate::error::Error;
+use crate::manifest::ModuleManifest;
+use std::collections::BTreeMap;
+use std::fs;
+
+const TOML_MANIFEST: &str = r#"
+name = "synthia"
+version = "0.3.0"
+python = ">=3.10"
+inference_modules = ["translation"]
+setup = ["echo ready > setup_done.txt"]
+timeout = 600
+
+[entry_points]
+validator = "synthia/validator/text_validator.py"
+miner = "synthia/miner/anthropic.py"
+
+[env.ANTHROPIC_API_KEY]
+description = "API key used by the miner"
+required = true
+
+[env.MINER_PORT]
+default = "8000"
+"#;
+
+#[test]
+fn test_manifest_absent() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(ModuleManifest::load(dir.path()).unwrap().is_none());
+}
+
+#[test]
+fn test_manifest_from_toml() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("module.toml"), TOML_MANIFEST).unwrap();
+
+    let manifest = ModuleManifest::load(dir.path()).unwrap().unwrap();
+    assert_eq!(manifest.version.as_deref(), Some("0.3.0"));
+    assert_eq!(manifest.python.as_deref(), Some(">=3.10"));
+    assert_eq!(manifest.inference_modules, vec!["translation"]);
+    assert_eq!(manifest.timeout, Some(600));
+    assert_eq!(
+        manifest.entry_point(dir.path(), "validator"),
+        Some(dir.path().join("synthia/validator/text_validator.py"))
+    );
+    assert!(manifest.entry_point(dir.path(), "inference").is_none());
+    assert!(manifest.env["ANTHROPIC_API_KEY"].required);
+    assert_eq!(manifest.env["MINER_PORT"].default.as_deref(), Some("8000"));
+}
+
+#[test]
+fn test_manifest_from_yaml() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("module.yaml"),
+        "version: \"1.2.0\"\nentry_points:\n  inference: serve.py\n",
+    )
+    .unwrap();
+
+    let manifest = ModuleManifest::load(dir.path()).unwrap().unwrap();
+    assert_eq!(manifest.version.as_deref(), Some("1.2.0"));
+    assert_eq!(manifest.entry_point(dir.path(), "inference"), Some(dir.path().join("serve.py")));
+    assert!(manifest.setup.is_empty());
+}
+
+#[test]
+fn test_call_env_accepts_only_declared_variables() {
+    let mut manifest: ModuleManifest = toml::from_str(TOML_MANIFEST).unwrap();
+    manifest.env.insert("PYTHONPATH".to_string(), Default::default());
+    let env = |key: &str| BTreeMap::from([(key.to_string(), "value".to_string())]);
+
+    assert!(manifest.check_call_env(&BTreeMap::new()).is_ok());
+    assert!(manifest.check_call_env(&env("MINER_PORT")).is_ok());
+    for key in ["UNDECLARED", "PYTHONPATH", "LD_PRELOAD", "PATH", "VIRTUAL_ENV"] {
+        assert!(matches!(manifest.check_call_env(&env(key)), Err(Error::InvalidInput(_))), "{} was accepted", key);
+    }
+    assert!(ModuleManifest::default().check_call_env(&env("MINER_PORT")).is_err());
+}
+
+#[test]
+fn test_invalid_manifest_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("module.toml"), "entry_points = 3").unwrap();
+    assert!(ModuleManifest::load(dir.path()).is_err());
+}
+
+#[test]
+fn test_manifest_setup_runs_in_module_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let venv_dir = dir.path().join(".synthia");
+    fs::create_dir_all(venv_dir.join("bin")).unwrap();
+    fs::write(dir.path().join("module.toml"), TOML_MANIFEST).unwrap();
+
+    let manifest = ModuleManifest::load(dir.path()).unwrap().unwrap();
+    manifest.run_setup(dir.path(), &venv_dir, None).unwrap();
+    assert_eq!(fs::read_to_string(dir.path().join("setup_done.txt")).unwrap().trim(), "ready");
+}
+
+#[test]
+fn test_config_parser_prefers_manifest_env() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("module.toml"), TOML_MANIFEST).unwrap();
+    fs::write(dir.path().join(".env.example"), "OLD_SETTING=1\n").unwrap();
+
+    let config = ConfigParser::parse_commands(dir.path()).unwrap();
+    assert!(!config.env_vars.contains_key("OLD_SETTING"));
+    assert_eq!(config.env_vars["MINER_PORT"], "8000");
+    assert_eq!(config.env_descriptions["ANTHROPIC_API_KEY"], "API key used by the miner");
+}
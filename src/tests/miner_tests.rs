@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::answers::Answers;
+use crate::error::Error;
+use crate::inference::python_executor::RunOptions;
+use crate::miner::Miner;
+
+/// Lays out an installed subnet with a miner script under a root directory.
+///
+/// The subnet's environment is the system interpreter, so nothing is created or downloaded.
+fn install_subnet(root: &Path, name: &str, script: &str) -> PathBuf {
+    let module_dir = root.join("subnets").join(name);
+    fs::create_dir_all(module_dir.join("neurons")).unwrap();
+    fs::write(module_dir.join("neurons").join("miner.py"), script).unwrap();
+    fs::write(module_dir.join("module.toml"), "environment = \"system\"\n").unwrap();
+    module_dir
+}
+
+#[test]
+fn test_miner_creation_and_find_script() {
+    let root = tempfile::tempdir().unwrap();
+    let module_dir = install_subnet(root.path(), "eden-subnet", "");
+    let miner = Miner::new_in(root.path(), "eden-subnet", &Answers::default()).unwrap();
+    assert_eq!(miner.subnet_name, "eden-subnet");
+    assert_eq!(miner.env_dir, root.path().join(".eden-subnet"));
+    assert_eq!(miner.module_dir, module_dir);
+    assert_eq!(miner.miner_path, Some(module_dir.join("neurons").join("miner.py")));
+}
+
+#[test]
+fn test_miner_without_script_asks_for_its_path() {
+    let root = tempfile::tempdir().unwrap();
+    fs::create_dir_all(root.path().join("subnets").join("eden-subnet")).unwrap();
+
+    let answers = Answers::load(None, &["MINER_PATH=neurons/custom.py".to_string()], false).unwrap();
+    let miner = Miner::new_in(root.path(), "eden-subnet", &answers).unwrap();
+    assert_eq!(miner.miner_path, Some(PathBuf::from("neurons/custom.py")));
+
+    let result = Miner::new_in(root.path(), "eden-subnet", &Answers::load(None, &[], false).unwrap());
+    assert!(matches!(result, Err(Error::MissingValues(keys)) if keys == ["MINER_PATH"]));
+}
+
+#[tokio::test]
+async fn test_miner_launch() {
+    let root = tempfile::tempdir().unwrap();
+    install_subnet(root.path(), "eden-subnet", "print(\"Miner launched successfully\")\n");
+    let mut miner = Miner::new_in(root.path(), "eden-subnet", &Answers::default()).unwrap();
+
+    miner.identify_and_prepare_inference(&[]).unwrap();
+    let result = miner.launch(&[], &Answers::default(), &RunOptions::default()).await.unwrap();
+    assert_eq!(result.stdout, "Miner launched successfully\n");
+    assert!(root.path().join(".eden-subnet").join("bin").join("python3").exists());
+}
@@ -2,5 +2,22 @@ mod utils_tests;
 mod config_tests;
 mod database_tests;
 mod validator_tests;
+mod miner_tests;
 mod subnet_moduels_tests;
-mod inference_module_tests;
\ No newline at end of file
+mod inference_module_tests;
+mod uninstall_tests;
+mod catalog_tests;
+mod manifest_tests;
+mod error_tests;
+mod transaction_tests;
+mod answers_tests;
+mod embedded_tests;
+mod worker_tests;
+mod process_tests;
+mod config_parser_tests;
+mod sandbox_tests;
+mod environment_tests;
+mod dotenv_tests;
+mod redact_tests;
+mod supervisor_tests;
+mod logs_tests;
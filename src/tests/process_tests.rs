@@ -0,0 +1,173 @@
+use crate::error::Error;
+use crate::inference::process::{
+    CancelHandle, ExecutionResult, OutputLine, OutputStream, ProcessGroup, RunningCommand, WaitOutcome,
+    OUTPUT_CAPTURE_BYTES,
+};
+use std::fs;
+use std::os::unix::process::ExitStatusExt;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio_stream::StreamExt;
+
+/// Returns whether a process exists and is not a zombie.
+fn is_running(pid: u32) -> bool {
+    match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => !stat.rsplit(") ").next().unwrap_or_default().starts_with('Z'),
+        Err(_) => false,
+    }
+}
+
+/// Waits briefly for a process to go away, since a killed grandchild is reaped asynchronously.
+fn wait_until_gone(pid: u32) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while Instant::now() < deadline {
+        if !is_running(pid) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_timeout_stops_the_process_group() {
+    let dir = tempfile::tempdir().unwrap();
+    let pid_file = dir.path().join("grandchild.pid");
+    let script = format!("sleep 30 & echo $! > {}; wait", pid_file.display());
+    let mut process = ProcessGroup::spawn(Command::new("sh").args(["-c", &script])).unwrap();
+
+    let started = Instant::now();
+    let outcome = process.wait(Some(Duration::from_millis(300)), None).await.unwrap();
+    assert!(matches!(outcome, WaitOutcome::TimedOut));
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let grandchild: u32 = fs::read_to_string(&pid_file).unwrap().trim().parse().unwrap();
+    assert!(wait_until_gone(grandchild), "grandchild {} survived", grandchild);
+}
+
+#[tokio::test]
+async fn test_terminate_escalates_to_sigkill() {
+    let mut process = ProcessGroup::spawn(Command::new("sh").args(["-c", "trap '' TERM; sleep 30"])).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let status = process.terminate(Duration::from_millis(200)).await.unwrap();
+    assert_eq!(status.signal(), Some(libc::SIGKILL));
+}
+
+#[tokio::test]
+async fn test_cancel_and_drop_stop_the_child() {
+    let cancel = CancelHandle::new();
+    let mut process = ProcessGroup::spawn(Command::new("sleep").arg("30")).unwrap();
+    let handle = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        handle.cancel();
+    });
+    assert!(matches!(process.wait(None, Some(&cancel)).await.unwrap(), WaitOutcome::Cancelled));
+
+    let process = ProcessGroup::spawn(Command::new("sleep").arg("30")).unwrap();
+    let pid = process.id();
+    drop(process);
+    assert!(wait_until_gone(pid));
+}
+
+/// Runs a shell script to completion and describes the run.
+async fn execute(script: &str) -> ExecutionResult {
+    let mut command = Command::new("sh");
+    command.args(["-c", script]);
+    RunningCommand::spawn("sh".to_string(), &mut command, None, None)
+        .unwrap()
+        .wait()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_execution_result_of_a_failed_run() {
+    let result = execute("echo starting; sleep 0.1; echo 'bad config' >&2; exit 3").await;
+    assert!(!result.success());
+    assert_eq!(result.exit_code, Some(3));
+    assert_eq!(result.signal, None);
+    assert_eq!(result.stdout, "starting\n");
+    assert!(result.peak_memory_kb.is_some_and(|peak| peak > 0));
+    assert_eq!(
+        result.output_tail,
+        vec![
+            OutputLine { stream: OutputStream::Stdout, line: "starting".to_string() },
+            OutputLine { stream: OutputStream::Stderr, line: "bad config".to_string() },
+        ]
+    );
+    assert!(result.to_string().contains("[stderr] bad config"));
+
+    match result.check() {
+        Err(Error::ProcessFailed { exit_code, stderr_tail, .. }) => {
+            assert_eq!(exit_code, Some(3));
+            assert_eq!(stderr_tail, "bad config");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let killed = execute("kill -9 $$").await;
+    assert_eq!(killed.signal, Some(libc::SIGKILL));
+    assert_eq!(killed.check().unwrap_err().to_string(), "sh was killed by signal 9");
+}
+
+#[tokio::test]
+async fn test_execution_result_keeps_the_end_of_long_output() {
+    let result = execute("yes 0123456789 | head -n 300000; echo last").await;
+    assert!(result.success());
+    assert!(result.output_truncated);
+    assert!(result.stdout.len() <= OUTPUT_CAPTURE_BYTES);
+    assert!(result.stdout.starts_with("0123456789\n"));
+    assert!(result.stdout.ends_with("0123456789\nlast\n"));
+    assert!(!execute("echo short").await.output_truncated);
+}
+
+#[tokio::test]
+async fn test_running_command_streams_output_and_reads_stdin() {
+    let mut command = Command::new("sh");
+    command.args(["-c", "echo ready; read name; echo \"hello $name\"; echo done >&2"]);
+    let mut running = RunningCommand::spawn("sh".to_string(), &mut command, None, None).unwrap();
+    let mut lines = running.output().unwrap();
+    assert!(running.output().is_none());
+
+    // The first line arrives while the script is still waiting for input
+    let first = lines.next().await.unwrap();
+    assert_eq!(first, OutputLine { stream: OutputStream::Stdout, line: "ready".to_string() });
+    running.write_stdin(b"world\n").await.unwrap();
+
+    let result = running.wait().await.unwrap();
+    assert!(result.success());
+    assert_eq!(result.stdout, "ready\nhello world\n");
+    let rest: Vec<OutputLine> = lines.collect().await;
+    assert!(rest.contains(&OutputLine { stream: OutputStream::Stdout, line: "hello world".to_string() }));
+    assert!(rest.contains(&OutputLine { stream: OutputStream::Stderr, line: "done".to_string() }));
+}
+
+#[tokio::test]
+async fn test_running_command_times_out() {
+    let mut command = Command::new("sleep");
+    command.arg("30");
+    let running = RunningCommand::spawn("sleep".to_string(), &mut command, Some(Duration::from_millis(200)), None).unwrap();
+    let started = Instant::now();
+    let result = running.wait().await.unwrap();
+    assert!(result.timed_out);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_wait_kills_grandchildren_holding_the_output_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let pid_file = dir.path().join("grandchild.pid");
+    let mut command = Command::new("sh");
+    command.args(["-c", &format!("echo started; sleep 30 & echo $! > {}", pid_file.display())]);
+    let running = RunningCommand::spawn("sh".to_string(), &mut command, Some(Duration::from_secs(1)), None).unwrap();
+    let started = Instant::now();
+    let result = running.wait().await.unwrap();
+    assert!(result.success());
+    assert_eq!(result.stdout, "started\n");
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let grandchild: u32 = fs::read_to_string(&pid_file).unwrap().trim().parse().unwrap();
+    assert!(wait_until_gone(grandchild), "grandchild {} survived", grandchild);
+}
@@ -0,0 +1,99 @@
+use crate::config::{Config, ConfigOverrides};
+use crate::sandbox::SandboxPolicy;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Runs a shell script in a module directory under the policy and returns its stdout.
+fn run_sandboxed(policy: &SandboxPolicy, module_dir: &std::path::Path, script: &str) -> io::Result<String> {
+    let mut command = Command::new("/bin/sh");
+    command.args(["-c", script]);
+    policy.apply(&mut command, module_dir).unwrap();
+    let output = command.output()?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[test]
+fn test_sandbox_scrubs_env_and_sets_limits() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("work")).unwrap();
+    std::env::set_var("SANDBOX_TEST_SECRET", "hunter2");
+    std::env::set_var("SANDBOX_TEST_ALLOWED", "yes");
+    let policy = SandboxPolicy {
+        memory_mb: Some(512),
+        open_files: Some(64),
+        env_allowlist: vec!["SANDBOX_TEST_ALLOWED".to_string()],
+        workdir: Some(PathBuf::from("work")),
+        ..SandboxPolicy::default()
+    };
+
+    let output = run_sandboxed(
+        &policy,
+        dir.path(),
+        "echo \"$SANDBOX_TEST_SECRET|$SANDBOX_TEST_ALLOWED|$(pwd -P)|$(ulimit -n)|$(ulimit -v)\"",
+    )
+    .unwrap();
+    let workdir = fs::canonicalize(dir.path().join("work")).unwrap();
+    assert_eq!(output, format!("|yes|{}|64|{}", workdir.display(), 512 * 1024));
+}
+
+#[test]
+fn test_sandbox_workdir_stays_in_module_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let module_dir = dir.path().join("module");
+    fs::create_dir(&module_dir).unwrap();
+    std::os::unix::fs::symlink(dir.path(), module_dir.join("escape")).unwrap();
+
+    let escaping = SandboxPolicy {
+        workdir: Some(PathBuf::from("escape")),
+        ..SandboxPolicy::default()
+    };
+    assert!(escaping.working_dir(&module_dir).is_err());
+
+    let parent = SandboxPolicy {
+        workdir: Some(PathBuf::from("../")),
+        ..SandboxPolicy::default()
+    };
+    assert_eq!(parent.problems().len(), 1);
+    assert_eq!(SandboxPolicy::default().working_dir(&module_dir).unwrap(), fs::canonicalize(&module_dir).unwrap());
+}
+
+#[test]
+fn test_sandbox_denies_network() {
+    let dir = tempfile::tempdir().unwrap();
+    let policy = SandboxPolicy {
+        network: false,
+        ..SandboxPolicy::default()
+    };
+    match run_sandboxed(&policy, dir.path(), "tail -n +3 /proc/self/net/dev | cut -d: -f1 | tr -d ' '") {
+        // A fresh network namespace only has a loopback device
+        Ok(output) => assert_eq!(output, "lo"),
+        // Kernels that forbid unprivileged namespaces cannot run the sandbox at all
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {}
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn test_sandbox_policies_from_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.yaml");
+    fs::write(
+        &path,
+        "sandbox:\n  \"*\":\n    network: false\n  synthia:\n    cpu_seconds: 600\n    memory_mb: 4096\n",
+    )
+    .unwrap();
+    let loaded = Config::load_layers(std::slice::from_ref(&path), |_| None, &ConfigOverrides::default()).unwrap();
+    let sandbox = &loaded.config.sandbox;
+    assert_eq!(sandbox.for_module("synthia").unwrap().memory_mb, Some(4096));
+    assert!(sandbox.for_module("synthia").unwrap().network);
+    assert!(!sandbox.for_module("translation").unwrap().network);
+
+    fs::write(&path, "sandbox:\n  synthia:\n    open_files: 0\n    workdir: /tmp\n").unwrap();
+    let error = Config::load_layers(&[path], |_| None, &ConfigOverrides::default()).unwrap_err();
+    let message = error.to_string();
+    assert!(message.contains("sandbox.synthia: open_files must be at least 1"), "{}", message);
+    assert!(message.contains("workdir `/tmp`"), "{}", message);
+}
@@ -1,36 +1,84 @@
 use crate::modules::subnet_module::SubnetModule;
-use std::path::PathBuf;
+use crate::modules::UpdateReport;
+use crate::modules::InstallOptions;
+use crate::registry::{ModuleRegistry, ModuleType};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 #[test]
 fn test_subnet_module_creation() {
-    let module = SubnetModule::new("https://github.com/example/subnet_module").unwrap();
+    let module = SubnetModule::new("https://github.com/example/subnet_module", "subnet_module").unwrap();
     assert_eq!(module.name, "subnet_module");
     assert_eq!(module.url, "https://github.com/example/subnet_module");
     assert!(module.required_inference_modules.is_empty());
 }
 
+/// Creates a git repository holding a subnet, to be cloned instead of one on GitHub.
+fn subnet_repository(dir: &Path) -> PathBuf {
+    let repository = dir.join("origin");
+    fs::create_dir_all(&repository).unwrap();
+    // The system interpreter needs no environment to be created or downloaded
+    fs::write(repository.join("module.toml"), "version = \"1.0.0\"\nenvironment = \"system\"\n").unwrap();
+    fs::write(repository.join("validator.py"), "print('validating')\n").unwrap();
+    for args in [&["init", "--quiet"][..], &["add", "."], &["commit", "--quiet", "--message", "Initial commit"]] {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(&repository)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+    repository
+}
+
 #[tokio::test]
 async fn test_subnet_module_install() {
-    let mut module = SubnetModule::new("https://github.com/example/test_subnet").unwrap();
-    let result = module.install().await;
-    assert!(result.is_ok());
-    
+    let root = tempfile::tempdir().unwrap();
+    let registry = ModuleRegistry::connect(&format!("sqlite://{}", root.path().join("modules.db").display()))
+        .await
+        .unwrap();
+    let url = format!("file://{}", subnet_repository(root.path()).display());
+    let mut module = SubnetModule::new(&url, "test_subnet").unwrap().with_root_dir(root.path());
+    module.install(&registry, &InstallOptions::default()).await.unwrap();
+
     // Check if the module directory was created
-    let module_dir = PathBuf::from("subnets").join("test_subnet");
-    assert!(module_dir.exists());
-    
+    let module_dir = root.path().join("subnets").join("test_subnet");
+    assert!(module_dir.join("validator.py").exists());
+
     // Check if the virtual environment was created
-    let venv_dir = PathBuf::from(".test_subnet");
-    assert!(venv_dir.exists());
+    let venv_dir = root.path().join(".test_subnet");
+    assert!(venv_dir.join("bin").join("python3").exists());
+
+    let record = registry.get("test_subnet").await.unwrap().unwrap();
+    assert_eq!(record.module_type, Some(ModuleType::Subnet));
+    assert_eq!(record.version.as_deref(), Some("1.0.0"));
+    assert_eq!(record.install_path.as_deref(), Some(module_dir.to_str().unwrap()));
 }
 
 #[test]
 fn test_subnet_module_with_inference_modules() {
-    let mut module = SubnetModule::new("https://github.com/example/test_subnet").unwrap();
+    let mut module = SubnetModule::new("https://github.com/example/test_subnet", "test_subnet").unwrap();
     module.required_inference_modules.insert("translation".to_string());
     module.required_inference_modules.insert("embedding".to_string());
     
     assert_eq!(module.required_inference_modules.len(), 2);
     assert!(module.required_inference_modules.contains("translation"));
     assert!(module.required_inference_modules.contains("embedding"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_update_report_display() {
+    let mut report = UpdateReport {
+        name: "synthia".to_string(),
+        old_version: Some("abc1234".to_string()),
+        new_version: Some("def5678".to_string()),
+    };
+    assert!(report.changed());
+    assert_eq!(report.to_string(), "synthia updated from abc1234 to def5678");
+
+    report.old_version = report.new_version.clone();
+    assert!(!report.changed());
+    assert_eq!(report.to_string(), "synthia is already up to date (def5678)");
+}
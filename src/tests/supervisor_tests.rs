@@ -0,0 +1,211 @@
+use crate::error::Error;
+use crate::inference::process::CancelHandle;
+use crate::logs::LogSettings;
+use crate::sandbox::SandboxPolicy;
+use crate::supervisor::{
+    backoff, process_start_time, ProcessRole, ProcessSpec, ProcessState, ProcessStatus, RestartPolicy, Supervisor, MAX_BACKOFF,
+};
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Spawns a child that sleeps, reaped in the background so it does not linger as a zombie.
+///
+/// # Returns
+///
+/// The pid of the child, and a receiver notified once it has exited.
+fn spawn_sleeper(command: &mut Command) -> (u32, mpsc::Receiver<()>) {
+    let mut child = command.spawn().unwrap();
+    let pid = child.id();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = child.wait();
+        let _ = sender.send(());
+    });
+    (pid, receiver)
+}
+
+/// Returns a supervisor keeping its state and logs in a directory.
+fn supervisor_in(dir: &Path) -> Supervisor {
+    let logs = LogSettings {
+        dir: dir.join("logs"),
+        ..Default::default()
+    };
+    Supervisor::new(dir.join("processes")).with_logs(logs)
+}
+
+/// Returns the spec of a shell script supervised from a directory.
+fn shell_spec(name: &str, dir: &Path, script: &str, restart: RestartPolicy) -> ProcessSpec {
+    ProcessSpec {
+        name: name.to_string(),
+        role: ProcessRole::Miner,
+        module: "test".to_string(),
+        program: "sh".into(),
+        args: vec!["-c".to_string(), script.to_string()],
+        cwd: dir.to_path_buf(),
+        restart,
+        port: None,
+        sandbox: None,
+    }
+}
+
+#[test]
+fn test_restart_policy_and_backoff() {
+    assert_eq!("on-failure".parse::<RestartPolicy>().unwrap(), RestartPolicy::OnFailure);
+    assert_eq!(RestartPolicy::Always.to_string(), "always");
+    assert!(matches!("sometimes".parse::<RestartPolicy>(), Err(Error::InvalidInput(_))));
+
+    assert!(RestartPolicy::Always.should_restart(true));
+    assert!(!RestartPolicy::OnFailure.should_restart(true));
+    assert!(RestartPolicy::OnFailure.should_restart(false));
+    assert!(!RestartPolicy::Never.should_restart(false));
+
+    assert_eq!(backoff(0), Duration::from_secs(1));
+    assert_eq!(backoff(3), Duration::from_secs(8));
+    assert_eq!(backoff(10), MAX_BACKOFF);
+    assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+}
+
+#[tokio::test]
+async fn test_supervise_restarts_a_failing_process_until_it_succeeds() {
+    let dir = tempfile::tempdir().unwrap();
+    let supervisor = supervisor_in(dir.path());
+    // Fails on the first two runs, then succeeds
+    let script = "echo run >> runs; echo started; [ $(wc -l < runs) -ge 3 ]";
+    let spec = shell_spec("flaky", dir.path(), script, RestartPolicy::OnFailure);
+    supervisor.prepare(&spec).unwrap();
+
+    let state = supervisor.supervise("flaky", &CancelHandle::new()).await.unwrap();
+    assert_eq!(state.status, ProcessStatus::Exited);
+    assert_eq!(state.restarts, 2);
+    assert_eq!(state.last_exit.as_ref().unwrap().code, Some(0));
+    assert_eq!(supervisor.load_state("flaky").unwrap().unwrap(), state);
+
+    let log_path = supervisor.output_log(&spec).unwrap();
+    assert_eq!(log_path, dir.path().join("logs").join("test").join("miner.log"));
+    let log = fs::read_to_string(log_path).unwrap();
+    assert_eq!(log.matches(" stdout started").count(), 3);
+    assert!(log.contains("supervisor flaky exited with code 1"));
+    assert!(log.contains("supervisor restarting flaky in 2s"));
+}
+
+#[tokio::test]
+async fn test_supervise_applies_the_sandbox_policy() {
+    let dir = tempfile::tempdir().unwrap();
+    let supervisor = supervisor_in(dir.path());
+    // Cargo sets both variables for the test binary; only one of them is allowlisted
+    let script = "echo \"kept=$CARGO_MANIFEST_DIR dropped=$CARGO_PKG_NAME\"";
+    let policy = SandboxPolicy {
+        env_allowlist: vec!["CARGO_MANIFEST_DIR".to_string()],
+        ..Default::default()
+    };
+    let spec = ProcessSpec {
+        sandbox: Some(policy),
+        ..shell_spec("sandboxed", dir.path(), script, RestartPolicy::Never)
+    };
+    supervisor.prepare(&spec).unwrap();
+
+    let state = supervisor.supervise("sandboxed", &CancelHandle::new()).await.unwrap();
+    assert_eq!(state.status, ProcessStatus::Exited);
+    let log = fs::read_to_string(supervisor.output_log(&spec).unwrap()).unwrap();
+    assert!(log.contains(&format!("kept={} dropped=\n", env!("CARGO_MANIFEST_DIR"))), "{}", log);
+}
+
+#[tokio::test]
+async fn test_supervise_stops_the_process_when_cancelled() {
+    let dir = tempfile::tempdir().unwrap();
+    let supervisor = supervisor_in(dir.path());
+    supervisor.prepare(&shell_spec("sleeper", dir.path(), "sleep 30", RestartPolicy::Always)).unwrap();
+
+    let cancel = CancelHandle::new();
+    let handle = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        handle.cancel();
+    });
+    let state = tokio::time::timeout(Duration::from_secs(5), supervisor.supervise("sleeper", &cancel))
+        .await
+        .expect("the supervisor did not stop")
+        .unwrap();
+    assert_eq!(state.status, ProcessStatus::Stopped);
+    assert_eq!(state.pid, None);
+    assert_eq!(state.restarts, 0);
+    assert!(!state.is_active());
+}
+
+#[test]
+fn test_prepare_rejects_invalid_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let supervisor = Supervisor::new(dir.path());
+    for name in ["", "../escape", ".hidden", "a b"] {
+        let spec = shell_spec(name, dir.path(), "true", RestartPolicy::Never);
+        assert!(matches!(supervisor.prepare(&spec), Err(Error::InvalidInput(_))), "{:?} was accepted", name);
+    }
+    assert!(matches!(supervisor.load_spec("missing"), Err(Error::InvalidInput(_))));
+    assert!(matches!(supervisor.get("../escape"), Err(Error::InvalidInput(_))));
+    assert!(matches!(supervisor.stop("../escape"), Err(Error::InvalidInput(_))));
+    assert!(matches!(supervisor.load_state("../escape"), Err(Error::InvalidInput(_))));
+}
+
+#[test]
+fn test_stop_cleans_up_after_a_supervisor_that_died() {
+    let dir = tempfile::tempdir().unwrap();
+    let supervisor = Supervisor::new(dir.path());
+    supervisor.prepare(&shell_spec("orphan", dir.path(), "sleep 30", RestartPolicy::Always)).unwrap();
+
+    // A supervisor that exits on SIGTERM without recording anything, and the process it left behind
+    let (supervisor_pid, supervisor_exited) = spawn_sleeper(Command::new("sleep").arg("30"));
+    let (pid, process_exited) = spawn_sleeper(Command::new("sleep").arg("30").process_group(0));
+    let mut state = ProcessState::new(supervisor_pid);
+    state.pid = Some(pid);
+    state.start_time = process_start_time(pid);
+    state.status = ProcessStatus::Running;
+    fs::write(dir.path().join("orphan").join("state.json"), serde_json::to_string(&state).unwrap()).unwrap();
+
+    let listed = supervisor.list().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].status(), ProcessStatus::Running);
+    assert_eq!(listed[0].pid(), Some(pid));
+
+    let stopped = supervisor.stop("orphan").unwrap();
+    assert_eq!(stopped.status(), ProcessStatus::Stopped);
+    assert_eq!(stopped.pid(), None);
+    assert!(supervisor_exited.recv_timeout(Duration::from_secs(5)).is_ok());
+    assert!(process_exited.recv_timeout(Duration::from_secs(5)).is_ok(), "the process survived");
+    assert_eq!(supervisor.get("orphan").unwrap(), stopped);
+}
+
+#[test]
+fn test_stop_leaves_processes_that_reuse_recorded_pids_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let supervisor = Supervisor::new(dir.path());
+    supervisor.prepare(&shell_spec("reused", dir.path(), "sleep 30", RestartPolicy::Always)).unwrap();
+
+    // Processes that got the recorded pids after the supervisor and its process exited
+    let (supervisor_pid, supervisor_exited) = spawn_sleeper(Command::new("sleep").arg("30"));
+    let (pid, process_exited) = spawn_sleeper(Command::new("sleep").arg("30").process_group(0));
+    let mut state = ProcessState::new(supervisor_pid);
+    state.supervisor_start_time = state.supervisor_start_time.map(|start| start - 1);
+    state.pid = Some(pid);
+    state.start_time = process_start_time(pid).map(|start| start - 1);
+    state.status = ProcessStatus::Running;
+    fs::write(dir.path().join("reused").join("state.json"), serde_json::to_string(&state).unwrap()).unwrap();
+    assert!(!state.is_active());
+    assert_eq!(supervisor.get("reused").unwrap().status(), ProcessStatus::Dead);
+
+    let stopped = supervisor.stop("reused").unwrap();
+    assert_eq!(stopped.status(), ProcessStatus::Stopped);
+    assert!(supervisor_exited.recv_timeout(Duration::from_millis(200)).is_err(), "the supervisor pid was signalled");
+    assert!(process_exited.recv_timeout(Duration::from_millis(200)).is_err(), "the process group was signalled");
+
+    unsafe {
+        libc::kill(supervisor_pid as libc::pid_t, libc::SIGKILL);
+        libc::kill(pid as libc::pid_t, libc::SIGKILL);
+    }
+    assert!(supervisor_exited.recv_timeout(Duration::from_secs(5)).is_ok());
+    assert!(process_exited.recv_timeout(Duration::from_secs(5)).is_ok());
+}
@@ -0,0 +1,102 @@
+use crate::modules::transaction::{failed_path, InstallTransaction};
+use std::fs;
+
+#[test]
+fn test_rollback_undoes_every_step() {
+    let dir = tempfile::tempdir().unwrap();
+    let subnets = dir.path().join("subnets");
+    let module_dir = subnets.join("synthia");
+    let venv_dir = dir.path().join(".synthia");
+    let env_file = dir.path().join(".env");
+    let new_file = dir.path().join("new.env");
+    fs::write(&env_file, "EXISTING=1\n").unwrap();
+
+    {
+        let mut transaction = InstallTransaction::begin("synthia", false);
+        let staging_dir = transaction.stage(&subnets).unwrap();
+        fs::write(staging_dir.join("setup.sh"), "echo hi").unwrap();
+        transaction.promote(&module_dir).unwrap();
+        assert!(module_dir.join("setup.sh").exists());
+        assert!(!staging_dir.exists());
+
+        transaction.track_venv(&venv_dir);
+        fs::create_dir_all(venv_dir.join("bin")).unwrap();
+        transaction.track_file(&env_file).unwrap();
+        fs::write(&env_file, "EXISTING=1\nSYNTHIA_API_PORT=8000\n").unwrap();
+        transaction.track_file(&new_file).unwrap();
+        fs::write(&new_file, "X=1\n").unwrap();
+        // Dropped without commit: the install failed.
+    }
+
+    assert!(!module_dir.exists());
+    assert!(!venv_dir.exists());
+    assert!(!new_file.exists());
+    assert_eq!(fs::read_to_string(&env_file).unwrap(), "EXISTING=1\n");
+    assert_eq!(fs::read_dir(&subnets).unwrap().count(), 0);
+}
+
+#[test]
+fn test_commit_keeps_changes_and_existing_venv_is_not_tracked() {
+    let dir = tempfile::tempdir().unwrap();
+    let modules = dir.path().join("modules");
+    let venv_dir = dir.path().join(".translation");
+    fs::create_dir_all(&venv_dir).unwrap();
+
+    let mut transaction = InstallTransaction::begin("translation", false);
+    transaction.stage(&modules).unwrap();
+    transaction.promote(&modules.join("translation")).unwrap();
+    transaction.commit();
+    assert!(modules.join("translation").is_dir());
+
+    let mut transaction = InstallTransaction::begin("translation", false);
+    transaction.track_venv(&venv_dir);
+    drop(transaction);
+    assert!(venv_dir.exists());
+}
+
+#[test]
+fn test_keep_failed_moves_the_tree_aside() {
+    let dir = tempfile::tempdir().unwrap();
+    let subnets = dir.path().join("subnets");
+    let module_dir = subnets.join("synthia");
+
+    let mut transaction = InstallTransaction::begin("synthia", true);
+    let staging_dir = transaction.stage(&subnets).unwrap();
+    fs::write(staging_dir.join("miner.py"), "").unwrap();
+    transaction.promote(&module_dir).unwrap();
+    drop(transaction);
+
+    assert!(!module_dir.exists());
+    assert!(failed_path(&module_dir).join("miner.py").exists());
+}
+
+#[test]
+fn test_replace_restores_the_previous_module_unless_committed() {
+    let dir = tempfile::tempdir().unwrap();
+    let modules = dir.path().join("modules");
+    let module_dir = modules.join("translation");
+    fs::create_dir_all(module_dir.join("data")).unwrap();
+    fs::write(module_dir.join("setup_translation.py"), "old").unwrap();
+    fs::write(module_dir.join("data").join("model.bin"), "weights").unwrap();
+
+    {
+        let mut transaction = InstallTransaction::begin("translation", false);
+        let staging_dir = transaction.stage_copy(&module_dir).unwrap();
+        assert_eq!(fs::read_to_string(staging_dir.join("data").join("model.bin")).unwrap(), "weights");
+        fs::write(staging_dir.join("setup_translation.py"), "new").unwrap();
+        transaction.replace(&module_dir).unwrap();
+        assert_eq!(fs::read_to_string(module_dir.join("setup_translation.py")).unwrap(), "new");
+        // Dropped without commit: the update failed.
+    }
+    assert_eq!(fs::read_to_string(module_dir.join("setup_translation.py")).unwrap(), "old");
+    assert_eq!(fs::read_dir(&modules).unwrap().count(), 1);
+
+    let mut transaction = InstallTransaction::begin("translation", false);
+    let staging_dir = transaction.stage_copy(&module_dir).unwrap();
+    fs::write(staging_dir.join("setup_translation.py"), "new").unwrap();
+    transaction.replace(&module_dir).unwrap();
+    transaction.commit();
+    assert_eq!(fs::read_to_string(module_dir.join("setup_translation.py")).unwrap(), "new");
+    assert!(module_dir.join("data").join("model.bin").exists());
+    assert_eq!(fs::read_dir(&modules).unwrap().count(), 1);
+}
@@ -0,0 +1,57 @@
+use crate::error::Error;
+use crate::modules::uninstall::UninstallPlan;
+use std::fs;
+
+#[test]
+fn test_uninstall_plan_finds_module_files() {
+    let root = tempfile::tempdir().unwrap();
+    fs::create_dir_all(root.path().join("subnets").join("synthia")).unwrap();
+    fs::create_dir_all(root.path().join(".synthia").join("bin")).unwrap();
+    fs::write(root.path().join(".env"), "SYNTHIA_API_PORT=8000\nOTHER=1\n").unwrap();
+
+    let plan = UninstallPlan::new(root.path(), "synthia", None).unwrap();
+    assert_eq!(plan.module_dir, Some(root.path().join("subnets").join("synthia")));
+    assert_eq!(plan.venv_dir, Some(root.path().join(".synthia")));
+    assert_eq!(plan.env_keys, vec!["SYNTHIA_API_PORT"]);
+
+    plan.execute().unwrap();
+    assert!(!root.path().join("subnets").join("synthia").exists());
+    assert!(!root.path().join(".synthia").exists());
+    assert_eq!(fs::read_to_string(root.path().join(".env")).unwrap(), "OTHER=1\n");
+}
+
+#[test]
+fn test_uninstall_plan_for_missing_module_is_empty() {
+    let root = tempfile::tempdir().unwrap();
+    let plan = UninstallPlan::new(root.path(), "translation", None).unwrap();
+    assert!(plan.is_empty());
+}
+
+#[test]
+fn test_uninstall_keeps_entries_of_modules_sharing_a_prefix() {
+    let root = tempfile::tempdir().unwrap();
+    fs::create_dir_all(root.path().join("modules").join("foo")).unwrap();
+    fs::create_dir_all(root.path().join("modules").join("foo_bar")).unwrap();
+    let env = "FOO_API_PORT=8000\nFOO_API_HOST=0.0.0.0\nFOO_BAR_API_PORT=8001\n# FOO_API_PORT=7000\n";
+    fs::write(root.path().join(".env"), env).unwrap();
+
+    let plan = UninstallPlan::new(root.path(), "foo", None).unwrap();
+    assert_eq!(plan.env_keys, vec!["FOO_API_PORT", "FOO_API_HOST"]);
+    plan.execute().unwrap();
+    assert!(root.path().join("modules").join("foo_bar").exists());
+    assert_eq!(
+        fs::read_to_string(root.path().join(".env")).unwrap(),
+        "FOO_BAR_API_PORT=8001\n# FOO_API_PORT=7000\n"
+    );
+}
+
+#[test]
+fn test_uninstall_rejects_names_outside_the_module_dirs() {
+    let root = tempfile::tempdir().unwrap();
+    fs::create_dir_all(root.path().join("modules")).unwrap();
+    for name in ["", ".", "..", "../escape", "a/b", "a\\b"] {
+        let plan = UninstallPlan::new(root.path(), name, None);
+        assert!(matches!(plan, Err(Error::InvalidInput(_))), "{:?} was accepted", name);
+    }
+    assert!(root.path().join("modules").exists());
+}
@@ -1,4 +1,4 @@
-use crate::utils::parse_url;
+use crate::utils::{parse_url, remove_env_entries, set_env_entries};
 
 #[test]
 fn test_parse_url_absolute() {
@@ -12,4 +12,34 @@ fn test_parse_url_relative() {
     let url = "relative/path";
     let parsed = parse_url(url).unwrap();
     assert_eq!(parsed.as_str(), "https://example.com/relative/path");
-}
\ No newline at end of file
+}
+#[test]
+fn test_remove_env_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let env_file = dir.path().join(".env");
+    std::fs::write(&env_file, "DATABASE_URL=postgres://localhost/db\nTRANSLATION_API_PORT=8090\n# TRANSLATION_API_HOST=old\nexport TRANSLATION_API_HOST=0.0.0.0\n").unwrap();
+
+    let keys = ["TRANSLATION_API_PORT".to_string(), "TRANSLATION_API_HOST".to_string()];
+    let removed = remove_env_entries(&env_file, &keys).unwrap();
+    assert_eq!(removed, vec!["TRANSLATION_API_PORT", "TRANSLATION_API_HOST"]);
+    assert_eq!(
+        std::fs::read_to_string(&env_file).unwrap(),
+        "DATABASE_URL=postgres://localhost/db\n# TRANSLATION_API_HOST=old\n"
+    );
+}
+
+#[test]
+fn test_set_env_entries_replaces_and_appends() {
+    let dir = tempfile::tempdir().unwrap();
+    let env_file = dir.path().join(".env");
+    std::fs::write(&env_file, "# comment\nTRANSLATION_API_PORT=8090\nOTHER=1\n").unwrap();
+
+    let entries = vec![
+        ("TRANSLATION_API_PORT".to_string(), "9000".to_string()),
+        ("TRANSLATION_API_HOST".to_string(), "0.0.0.0".to_string()),
+    ];
+    set_env_entries(&env_file, &entries).unwrap();
+
+    let content = std::fs::read_to_string(&env_file).unwrap();
+    assert_eq!(content, "# comment\nTRANSLATION_API_PORT=9000\nOTHER=1\nTRANSLATION_API_HOST=0.0.0.0\n");
+}
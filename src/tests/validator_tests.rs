@@ -1,43 +1,49 @@
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::answers::Answers;
+use crate::inference::python_executor::RunOptions;
 use crate::validator::Validator;
-use crate::cli::{Cli, Commands};
+
+/// Lays out an installed subnet with a validator script under a root directory.
+///
+/// The subnet's environment is the system interpreter, so nothing is created or downloaded.
+fn install_subnet(root: &Path, name: &str, script: &str) -> PathBuf {
+    let module_dir = root.join("subnets").join(name);
+    fs::create_dir_all(module_dir.join("neurons")).unwrap();
+    fs::write(module_dir.join("neurons").join("validator.py"), script).unwrap();
+    fs::write(module_dir.join("module.toml"), "environment = \"system\"\n").unwrap();
+    module_dir
+}
 
 #[test]
 fn test_validator_creation() {
-    let cli = Cli {
-        test: true,
-        command: Commands::Install { url: "https://github.com/agent-artificial/eden-subnet".to_string() },
-    };
-    let validator = Validator::new("eden-subnet").unwrap();
+    let root = tempfile::tempdir().unwrap();
+    install_subnet(root.path(), "eden-subnet", "");
+    let validator = Validator::new_in(root.path(), "eden-subnet", &Answers::default()).unwrap();
     assert_eq!(validator.subnet_name, "eden-subnet");
-    assert_eq!(validator.env_dir, PathBuf::from(".eden-subnet"));
-    assert_eq!(validator.module_dir, PathBuf::from("subnets").join("eden-subnet"));
+    assert_eq!(validator.env_dir, root.path().join(".eden-subnet"));
+    assert_eq!(validator.module_dir, root.path().join("subnets").join("eden-subnet"));
 }
 
 #[test]
 fn test_validator_find_script() {
-    let mut validator = Validator::new("eden-subnet").unwrap();
-    
-    // Create a mock validator script
-    std::fs::create_dir_all(&validator.module_dir).unwrap();
-    std::fs::File::create(validator.module_dir.join("neurons/validator.py")).unwrap();
-    
-    assert!(validator.find_validator_script().is_ok());
-    assert!(validator.validator_path.is_some());
+    let root = tempfile::tempdir().unwrap();
+    let module_dir = install_subnet(root.path(), "eden-subnet", "");
+    let mut validator = Validator::new_in(root.path(), "eden-subnet", &Answers::default()).unwrap();
+
+    validator.validator_path = None;
+    assert!(validator.find_validator_script(&Answers::default()).is_ok());
+    assert_eq!(validator.validator_path, Some(module_dir.join("neurons").join("validator.py")));
 }
 
 #[tokio::test]
 async fn test_validator_launch() {
-    let mut validator = Validator::new("eden-subnet").unwrap();
-    
-    // Create a mock validator script
-    std::fs::create_dir_all(&validator.module_dir).unwrap();
-    let script_content = r#"
-print("Validator launched successfully")
-"#;
-    std::fs::write(validator.module_dir.join("neurons/validator.py"), script_content).unwrap();
-    
-    validator.find_validator_script().unwrap();
-    let result = validator.launch(None);
-    assert!(result.is_ok());
-}
\ No newline at end of file
+    let root = tempfile::tempdir().unwrap();
+    install_subnet(root.path(), "eden-subnet", "print(\"Validator launched successfully\")\n");
+    let mut validator = Validator::new_in(root.path(), "eden-subnet", &Answers::default()).unwrap();
+
+    validator.identify_and_prepare_inference(&[]).unwrap();
+    let result = validator.launch(&[], &Answers::default(), &RunOptions::default()).await.unwrap();
+    assert_eq!(result.stdout, "Validator launched successfully\n");
+    assert!(root.path().join(".eden-subnet").join("bin").join("python3").exists());
+}
@@ -0,0 +1,89 @@
+use crate::error::Error;
+use crate::inference::inference_requests::InferenceRequest;
+use crate::inference::worker::{WorkerPool, WorkerPoolOptions};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+/// Lays out an inference module whose virtual environment points at the system Python.
+fn write_module(root: &Path, name: &str) {
+    let module_dir = root.join("modules").join(name);
+    fs::create_dir_all(&module_dir).unwrap();
+    fs::write(
+        module_dir.join(format!("{}.py", name)),
+        r#"
+import os
+
+loads = 0
+
+def load():
+    global loads
+    loads += 1
+
+def process(text="", crash=False):
+    print("printed output must not reach the protocol channel")
+    if crash:
+        os._exit(3)
+    if text == "fail":
+        raise RuntimeError("cannot process " + text)
+    return {"text": text.upper(), "pid": os.getpid(), "loads": loads}
+"#,
+    )
+    .unwrap();
+
+    let bin_dir = root.join(format!(".{}", name)).join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let python = which_python();
+    symlink(python, bin_dir.join("python3")).unwrap();
+}
+
+fn which_python() -> String {
+    let output = std::process::Command::new("sh").args(["-c", "command -v python3"]).output().unwrap();
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+#[test]
+fn test_worker_pool_serves_requests() {
+    let dir = tempfile::tempdir().unwrap();
+    write_module(dir.path(), "upper");
+    let options = WorkerPoolOptions { size: 1, max_requests: 0 };
+    let pool = WorkerPool::start_in(dir.path(), "upper", options).unwrap();
+
+    let request = InferenceRequest {
+        data: HashMap::from([("text".to_string(), json!("hi"))]),
+    };
+    let first = pool.process(&request).unwrap().data;
+    assert_eq!(first["text"], "HI");
+    assert_eq!(first["loads"], 1);
+    let second = pool.call(json!(["again"])).unwrap();
+    assert_eq!(second["pid"], first["pid"]);
+    assert_eq!(second["loads"], 1);
+
+    match pool.call(json!({"text": "fail"})) {
+        Err(Error::PythonFailed { message, .. }) => {
+            assert!(message.contains("RuntimeError: cannot process fail"), "{}", message)
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(pool.call(json!("ok")).unwrap()["pid"], first["pid"]);
+}
+
+#[test]
+fn test_worker_pool_restarts_and_recycles_workers() {
+    let dir = tempfile::tempdir().unwrap();
+    write_module(dir.path(), "restarting");
+    let options = WorkerPoolOptions { size: 1, max_requests: 2 };
+    let pool = WorkerPool::start_in(dir.path(), "restarting", options).unwrap();
+
+    let first = pool.call(json!({})).unwrap()["pid"].clone();
+    assert_eq!(pool.call(json!({})).unwrap()["pid"], first);
+    // The worker served max_requests and was replaced
+    let recycled = pool.call(json!({})).unwrap()["pid"].clone();
+    assert_ne!(recycled, first);
+
+    assert!(matches!(pool.call(json!({"crash": true})), Err(Error::PythonFailed { .. })));
+    let restarted = pool.call(json!({})).unwrap()["pid"].clone();
+    assert_ne!(restarted, recycled);
+}
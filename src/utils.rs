@@ -1,7 +1,13 @@
 //! Utility functions for the Module Validator application.
 
+use crate::error::{Error, Result};
+use crate::redact;
 use reqwest::Url;
-use std::error::Error;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
 
 /// Parse the URL and provide a base if the URL is relative.
 ///
@@ -12,7 +18,7 @@ use std::error::Error;
 /// # Returns
 ///
 /// A Result containing the parsed Url if successful, or an error if parsing fails.
-pub fn parse_url(input: impl AsRef<str>) -> Result<Url, Box<dyn Error>> {
+pub fn parse_url(input: impl AsRef<str>) -> Result<Url> {
     let input = input.as_ref();
     // If the input is a relative URL, provide a base
     if !input.starts_with("http://") && !input.starts_with("https://") {
@@ -21,4 +27,181 @@ pub fn parse_url(input: impl AsRef<str>) -> Result<Url, Box<dyn Error>> {
     } else {
         Ok(Url::parse(input)?)
     }
-}
\ No newline at end of file
+}
+
+/// Rejects names that are empty, hidden or contain anything but letters, digits, `-`, `_` and `.`,
+/// so that a module or process name joined into a path cannot leave its directory.
+///
+/// # Arguments
+///
+/// * `kind` - What the name is of, for the error message, e.g. `module`.
+/// * `name` - The name to check.
+///
+/// # Returns
+///
+/// A Result indicating success, or an InvalidInput error if the name is not allowed.
+pub fn check_name(kind: &str, name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        return Err(Error::InvalidInput(format!("Invalid {} name `{}`", kind, name)));
+    }
+    Ok(())
+}
+
+/// Returns the key of a `.env` line, or None for blank lines and comments.
+///
+/// # Arguments
+///
+/// * `line` - A line of a `.env` file.
+pub fn env_line_key(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line.starts_with('#') {
+        return None;
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    line.split_once('=').map(|(key, _)| key.trim())
+}
+
+/// Removes the entries with the given keys from a `.env` file.
+///
+/// # Arguments
+///
+/// * `env_file` - The path to the `.env` file.
+/// * `keys` - The keys of the entries to remove.
+///
+/// # Returns
+///
+/// A Result containing the keys that were removed, or an error if the file cannot be rewritten.
+pub fn remove_env_entries(env_file: &Path, keys: &[String]) -> io::Result<Vec<String>> {
+    let content = fs::read_to_string(env_file)?;
+    let mut removed = Vec::new();
+    let mut kept = String::new();
+    for line in content.lines() {
+        let key = env_line_key(line).filter(|key| keys.iter().any(|k| k == key));
+        if let Some(key) = key {
+            removed.push(key.to_string());
+        } else {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+    }
+    fs::write(env_file, kept)?;
+    Ok(removed)
+}
+
+/// Sets entries in a `.env` file, replacing existing entries with the same key and
+/// appending the others. The file is created if it does not exist.
+///
+/// # Arguments
+///
+/// * `env_file` - The path to the `.env` file.
+/// * `entries` - The keys and values to set.
+///
+/// # Returns
+///
+/// A Result indicating whether the file was written.
+pub fn set_env_entries(env_file: &Path, entries: &[(String, String)]) -> io::Result<()> {
+    let content = if env_file.exists() {
+        fs::read_to_string(env_file)?
+    } else {
+        String::new()
+    };
+    let mut remaining: Vec<&(String, String)> = entries.iter().collect();
+    let mut updated = String::new();
+    for line in content.lines() {
+        let key = env_line_key(line);
+        match remaining.iter().position(|(entry_key, _)| Some(entry_key.as_str()) == key) {
+            Some(index) => {
+                let (key, value) = remaining.remove(index);
+                updated.push_str(&format!("{}={}\n", key, value));
+            }
+            None => {
+                updated.push_str(line);
+                updated.push('\n');
+            }
+        }
+    }
+    for (key, value) in remaining {
+        updated.push_str(&format!("{}={}\n", key, value));
+    }
+    fs::write(env_file, updated)
+}
+
+/// Returns the lowercase hex SHA-256 digest of `data`.
+pub fn sha256_hex(data: impl AsRef<[u8]>) -> String {
+    Sha256::digest(data.as_ref())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Runs a command, echoing its stdout and stderr as they are produced.
+///
+/// # Arguments
+///
+/// * `command` - The program to run.
+/// * `args` - The arguments passed to the program.
+///
+/// # Returns
+///
+/// A Result indicating success, or a ProcessFailed error carrying the tail of stderr.
+pub fn run_command_with_output(command: &str, args: &[&str]) -> Result<()> {
+    run_with_output(Command::new(command).args(args))
+}
+
+/// Runs a prepared command, e.g. a sandboxed one, echoing its stdout and stderr as they are produced.
+///
+/// # Arguments
+///
+/// * `command` - The command to run; its stdout and stderr are replaced by pipes.
+///
+/// # Returns
+///
+/// A Result indicating success, or a ProcessFailed error carrying the tail of stderr.
+pub fn run_with_output(command: &mut Command) -> Result<()> {
+    let description = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stdout = child.stdout.take().expect("Failed to open stdout");
+    let stderr = child.stderr.take().expect("Failed to open stderr");
+
+    let stdout_thread = std::thread::spawn(move || {
+        BufReader::new(stdout)
+            .lines()
+            .map_while(std::result::Result::ok)
+            .for_each(|line| println!("{}", redact::text(&line)));
+    });
+
+    let stderr_thread = std::thread::spawn(move || {
+        let mut captured = String::new();
+        for line in BufReader::new(stderr).lines().map_while(std::result::Result::ok) {
+            eprintln!("{}", redact::text(&line));
+            captured.push_str(&line);
+            captured.push('\n');
+        }
+        captured
+    });
+
+    let status = child.wait()?;
+    let _ = stdout_thread.join();
+    let stderr_output = stderr_thread.join().unwrap_or_default();
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::process_failed(
+            description,
+            status,
+            &stderr_output,
+        ))
+    }
+}
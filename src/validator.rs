@@ -2,14 +2,20 @@
 //!
 //! This module provides functionality for validating and launching subnet modules.
 
-use std::error::Error;
+use crate::answers::Answers;
+use crate::error::{Error, Result};
 use std::fs;
-use std::path::PathBuf;
-use crate::inference::python_executor::PythonExecutor;
+use std::path::{Path, PathBuf};
+use crate::inference::process::ExecutionResult;
+use crate::inference::python_executor::{PythonExecutor, RunOptions};
+use crate::manifest::ModuleManifest;
+use crate::redact;
 
 /// Represents a validator for subnet modules.
 pub struct Validator {
     pub subnet_name: String,
+    /// The directory holding `subnets/` and the virtual environments; empty for the working directory.
+    pub root_dir: PathBuf,
     pub env_dir: PathBuf,
     pub module_dir: PathBuf,
     pub validator_path: Option<PathBuf>,
@@ -21,47 +27,89 @@ impl Validator {
     /// # Arguments
     ///
     /// * `subnet_name` - The name of the subnet to validate.
+    /// * `answers` - Values given ahead of time, used for the script path if it cannot be found.
     ///
     /// # Returns
     ///
     /// A Result containing the Validator if successful, or an error if creation fails.
-    pub fn new(subnet_name: &str) -> Result<Self, Box<dyn Error>> {
+    pub fn new(subnet_name: &str, answers: &Answers) -> Result<Self> {
+        Self::new_in(Path::new(""), subnet_name, answers)
+    }
+
+    /// Creates a new Validator instance for a subnet installed under the given root directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `root_dir` - The directory holding `subnets/` and the virtual environments.
+    /// * `subnet_name` - The name of the subnet to validate.
+    /// * `answers` - Values given ahead of time, used for the script path if it cannot be found.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the Validator if successful, or an error if creation fails.
+    pub fn new_in(root_dir: &Path, subnet_name: &str, answers: &Answers) -> Result<Self> {
         println!("Creating new validator for subnet: {}", subnet_name);
-        let env_dir = PathBuf::from(format!(".{}", subnet_name));
-        let module_dir = PathBuf::from("subnets").join(subnet_name);
+        let env_dir = root_dir.join(format!(".{}", subnet_name));
+        let module_dir = root_dir.join("subnets").join(subnet_name);
         
         let mut validator = Self {
             subnet_name: subnet_name.to_string(),
+            root_dir: root_dir.to_path_buf(),
             env_dir,
             module_dir,
             validator_path: None,
         };
 
         
-        validator.find_validator_script()?;
+        validator.find_validator_script(answers)?;
         Ok(validator)
     }
 
-    /// Prompts the user for the path to the validator script.
+    /// Asks for the path to the validator script, using the `VALIDATOR_PATH` answer when given.
+    ///
+    /// # Arguments
+    ///
+    /// * `answers` - Values given ahead of time with `--set` or `--answers`.
     ///
     /// # Returns
     ///
-    /// A Result containing the PathBuf of the validator script if successful, or an error if the operation
-    pub fn prompt_user_for_path(&self) -> Result<PathBuf, Box<dyn Error>> {
-        let mut validator_path = String::new();
-        println!("Enter the path to the validator script: ");
-        std::io::stdin().read_line(&mut validator_path).unwrap();
-        let validator_path = PathBuf::from(validator_path.trim());
-        Ok(validator_path)
+    /// A Result containing the PathBuf of the validator script if successful, or a MissingValues error when prompting is disabled.
+    pub fn prompt_user_for_path(&self, answers: &Answers) -> Result<PathBuf> {
+        match answers.ask("VALIDATOR_PATH", "Enter the path to the validator script", None)? {
+            Some(path) if !path.trim().is_empty() => Ok(PathBuf::from(path.trim())),
+            _ => Err(Error::MissingValues(vec!["VALIDATOR_PATH".to_string()])),
+        }
     }
 
     /// Finds the validator script in the module directory.
     ///
+    /// The `validator` entry point of the module manifest is preferred. Without one,
+    /// the module directory is searched for a file named `validator.py`, and as a last
+    /// resort the path is asked for.
+    ///
+    /// # Arguments
+    ///
+    /// * `answers` - Values given ahead of time with `--set` or `--answers`.
+    ///
     /// # Returns
     ///
     /// A Result indicating success or failure of finding the validator script.
-    pub fn find_validator_script(&mut self) -> Result<(), Box<dyn Error>> {
+    pub fn find_validator_script(&mut self, answers: &Answers) -> Result<()> {
         println!("Finding validator script in: {:?}", self.module_dir);
+        if let Some(manifest) = ModuleManifest::load(&self.module_dir)? {
+            if let Some(script_path) = manifest.entry_point(&self.module_dir, "validator") {
+                println!("Using validator entry point from the module manifest: {:?}", script_path);
+                if !script_path.is_file() {
+                    return Err(Error::ScriptNotFound {
+                        role: "validator".to_string(),
+                        path: script_path,
+                    });
+                }
+                self.validator_path = Some(script_path);
+                return Ok(());
+            }
+        }
+
         fn find_script(module_dir: &PathBuf) -> Option<PathBuf> {
             if let Ok(entries) = fs::read_dir(module_dir) {
                 println!("Entries: {:?}", entries);
@@ -84,7 +132,7 @@ impl Validator {
             self.validator_path = Some(script_path);
             Ok(())
         } else {
-            let somepath = self.prompt_user_for_path()?;
+            let somepath = self.prompt_user_for_path(answers)?;
             self.validator_path = Some(somepath);
             Ok(())
         }
@@ -99,14 +147,20 @@ impl Validator {
     /// # Returns
     ///
     /// A Result indicating success or failure of the preparation.
-    pub fn identify_and_prepare_inference(&mut self, _args: &String) -> Result<(), Box<dyn Error>> {
+    pub fn identify_and_prepare_inference(&mut self, _args: &[String]) -> Result<()> {
         println!("Preparing inference for subnet: {}", self.subnet_name);
         
-        let script_path = self.validator_path.as_ref().ok_or("Validator script not found")?;
+        let script_path = self.validator_path.as_ref().ok_or_else(|| self.script_not_found())?;
         println!("Original script path: {:?}", script_path);
 
         // Adjust the path to be relative to the subnet folder
-        let relative_script_path = script_path.strip_prefix(&self.module_dir)?;
+        let relative_script_path = script_path.strip_prefix(&self.module_dir).map_err(|_| {
+            Error::InvalidInput(format!(
+                "Validator script {} is not inside {}",
+                script_path.display(),
+                self.module_dir.display()
+            ))
+        })?;
         self.validator_path = Some(relative_script_path.to_path_buf());
         
         println!("Adjusted validator path: {:?}", self.validator_path);
@@ -117,36 +171,47 @@ impl Validator {
     ///
     /// # Arguments
     ///
-    /// * `args` - Optional arguments to pass to the validator.
+    /// * `args` - The arguments to pass to the validator, one element per argument.
+    /// * `answers` - Values given with `--set` or `--answers`, exported to the validator's environment.
+    /// * `options` - The timeout and cancel handle for the validator process.
     ///
     /// # Returns
     ///
-    /// A Result indicating success or failure of the validator launch.
-    pub fn launch(&self, args: Option<&String>) -> Result<(), Box<dyn Error>> {
+    /// A Result containing the ExecutionResult of the validator process, or an error if it could not be
+    /// started or did not exit successfully.
+    pub async fn launch(&self, args: &[String], answers: &Answers, options: &RunOptions) -> Result<ExecutionResult> {
         println!("Launching validator for subnet: {}", self.subnet_name);
-        let validator_path = self.validator_path.as_ref().ok_or("Validator path not set")?;
+        let validator_path = self.validator_path.as_ref().ok_or_else(|| self.script_not_found())?;
         
         println!("Validator path: {:?}", validator_path);
         
-        let executor = PythonExecutor::new(
+        let mut executor = PythonExecutor::new_in(
+            &self.root_dir,
             self.subnet_name.clone(),
             "subnet".to_string(),
             validator_path.to_str().unwrap().to_string(),
+            options.sandbox.as_ref(),
         )?;
+        for (key, value) in answers.values() {
+            executor.set_env(key, value);
+        }
 
         println!("Executing Python command...");
-        let output = match args {
-            Some(arg_str) => executor.run_command(arg_str.to_string())?,
-            None => executor.run_command(String::new())?,
-        };
-        println!("Raw output from Python execution: {:?}", output);
+        let result = executor.run_command_with(args, options).await?;
+        println!("{}", redact::text(&result.to_string()));
 
-        if output.trim().is_empty() {
+        if result.success() && result.stdout.trim().is_empty() {
             println!("Warning: The validator produced no output.");
-        } else {
-            println!("Validator output: {}", output);
         }
 
-        Ok(())
+        result.check()
+    }
+
+    /// Builds the error returned when the validator script cannot be located.
+    fn script_not_found(&self) -> Error {
+        Error::ScriptNotFound {
+            role: "validator".to_string(),
+            path: self.module_dir.clone(),
+        }
     }
 }